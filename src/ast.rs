@@ -1,15 +1,42 @@
-#[derive(Debug, PartialEq)]
+use std::collections::BTreeMap;
+
+// Implementation limit on how deeply blocks, loops and ifs nest. Passes over function bodies
+// recurse into nested instructions, so the parser and the loader reject deeper nesting before
+// it can exhaust the native stack.
+pub const MAX_NESTING_DEPTH: usize = 1000;
+
+#[derive(Debug, PartialEq, Default)]
 pub struct Module {
     pub types: Vec<Type>,
     pub funcs: Vec<Func>,
-//    pub tables: Vec<Table>,
-//    pub mems: Vec<Mem>,
-//    pub globals: Vec<Global>,
-//    pub elem: Vec<Elem>,
-//    pub data: Vec<Data>,
-//    pub start: Option<Start>,
-//    pub imports: Vec<Import>,
+    pub tables: Vec<Table>,
+    pub mems: Vec<Mem>,
+    pub globals: Vec<Global>,
+    pub elem: Vec<Elem>,
+    pub data: Vec<Data>,
+    // The number of data segments announced by the data count section of the binary format,
+    // which code referring to data segments requires
+    pub data_count: Option<u32>,
+    pub start: Option<Start>,
+    pub imports: Vec<Import>,
     pub exports: Vec<Export>,
+    pub customs: Vec<Custom>,
+}
+
+impl Module {
+    // Whether any function uses `memory.init` or `data.drop`.
+    pub fn uses_data_indices(&self) -> bool {
+        self.funcs.iter().any(|func| uses_data_indices(&func.body))
+    }
+}
+
+fn uses_data_indices(instrs: &[Instr]) -> bool {
+    instrs.iter().any(|instr| match instr {
+        Instr::MemoryInit(_) | Instr::DataDrop(_) => true,
+        Instr::Block(_, body) | Instr::Loop(_, body) => uses_data_indices(body),
+        Instr::If(_, then, otherwise) => uses_data_indices(then) || uses_data_indices(otherwise),
+        _ => false,
+    })
 }
 
 // ValueType ::= NumberType | VectorType | ReferenceType
@@ -47,20 +74,1080 @@ pub type ResultType = Vec<ValueType>;
 pub type FuncType = (ResultType, ResultType);
 pub type Type = FuncType;
 
+// Limits ::= {min u32, max u32?}
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+pub struct Limits {
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+// TableType ::= limits reftype
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+pub struct TableType {
+    pub limits: Limits,
+    pub elem_type: ReferenceType,
+}
+
+// MemType ::= limits
+// The limits are given in units of 64 KiB pages.
+pub type MemType = Limits;
+
+// GlobalType ::= mut valtype
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+pub struct GlobalType {
+    pub mutable: bool,
+    pub value_type: ValueType,
+}
+
+// BlockType ::= typeidx | valtype?
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(ValueType),
+    Type(usize),
+}
+
+// MemArg ::= {offset u32, align u32}
+// `align` is the exponent of the alignment, e.g. 2 for a 4 byte alignment.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Default)]
+pub struct MemArg {
+    pub offset: u32,
+    pub align: u32,
+}
+
+// Instr ::= ControlInstr | ReferenceInstr | ParametricInstr | VariableInstr | TableInstr
+//         | MemoryInstr | NumericInstr | VectorInstr
 #[derive(Debug, PartialEq, Clone, Eq)]
 pub enum Instr {
+    // Control instructions
+    Unreachable,
+    Nop,
+    Block(BlockType, Vec<Instr>),
+    Loop(BlockType, Vec<Instr>),
+    If(BlockType, Vec<Instr>, Vec<Instr>),
+    Br(usize),
+    BrIf(usize),
+    BrTable(Vec<usize>, usize),
+    Return,
+    Call(usize),
+    // tableidx, typeidx
+    CallIndirect(usize, usize),
+
+    // Reference instructions
+    RefNull(ReferenceType),
+    RefIsNull,
+    RefFunc(usize),
+
+    // Parametric instructions
+    Drop,
+    Select(Option<Vec<ValueType>>),
+
+    // Variable instructions
     LocalGet(usize),
+    LocalSet(usize),
+    LocalTee(usize),
+    GlobalGet(usize),
+    GlobalSet(usize),
+
+    // Table instructions
+    TableGet(usize),
+    TableSet(usize),
+    TableSize(usize),
+    TableGrow(usize),
+    TableFill(usize),
+    // dst tableidx, src tableidx
+    TableCopy(usize, usize),
+    // tableidx, elemidx
+    TableInit(usize, usize),
+    ElemDrop(usize),
+
+    // Memory instructions
+    I32Load(MemArg),
+    I64Load(MemArg),
+    F32Load(MemArg),
+    F64Load(MemArg),
+    I32Load8S(MemArg),
+    I32Load8U(MemArg),
+    I32Load16S(MemArg),
+    I32Load16U(MemArg),
+    I64Load8S(MemArg),
+    I64Load8U(MemArg),
+    I64Load16S(MemArg),
+    I64Load16U(MemArg),
+    I64Load32S(MemArg),
+    I64Load32U(MemArg),
+    I32Store(MemArg),
+    I64Store(MemArg),
+    F32Store(MemArg),
+    F64Store(MemArg),
+    I32Store8(MemArg),
+    I32Store16(MemArg),
+    I64Store8(MemArg),
+    I64Store16(MemArg),
+    I64Store32(MemArg),
+    MemorySize,
+    MemoryGrow,
+    MemoryFill,
+    MemoryCopy,
+    MemoryInit(usize),
+    DataDrop(usize),
+
+    // Numeric instructions
+    I32Const(i32),
+    I64Const(i64),
+    // Float constants are kept as bit patterns so that NaN payloads survive
+    F32Const(u32),
+    F64Const(u64),
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64LtU,
+    I64GtS,
+    I64GtU,
+    I64LeS,
+    I64LeU,
+    I64GeS,
+    I64GeU,
+    F32Eq,
+    F32Ne,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
     I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32DivU,
+    I32RemS,
+    I32RemU,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I32Rotl,
+    I32Rotr,
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemS,
+    I64RemU,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    I64ShrU,
+    I64Rotl,
+    I64Rotr,
+    F32Abs,
+    F32Neg,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F32Sqrt,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    F32Min,
+    F32Max,
+    F32Copysign,
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Sqrt,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    F64Max,
+    F64Copysign,
+    I32WrapI64,
+    I32TruncF32S,
+    I32TruncF32U,
+    I32TruncF64S,
+    I32TruncF64U,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    I64TruncF32S,
+    I64TruncF32U,
+    I64TruncF64S,
+    I64TruncF64U,
+    F32ConvertI32S,
+    F32ConvertI32U,
+    F32ConvertI64S,
+    F32ConvertI64U,
+    F32DemoteF64,
+    F64ConvertI32S,
+    F64ConvertI32U,
+    F64ConvertI64S,
+    F64ConvertI64U,
+    F64PromoteF32,
+    I32ReinterpretF32,
+    I64ReinterpretF64,
+    F32ReinterpretI32,
+    F64ReinterpretI64,
+    I32Extend8S,
+    I32Extend16S,
+    I64Extend8S,
+    I64Extend16S,
+    I64Extend32S,
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
+
+    // Vector instructions
+    V128Load(MemArg),
+    V128Load8x8S(MemArg),
+    V128Load8x8U(MemArg),
+    V128Load16x4S(MemArg),
+    V128Load16x4U(MemArg),
+    V128Load32x2S(MemArg),
+    V128Load32x2U(MemArg),
+    V128Load8Splat(MemArg),
+    V128Load16Splat(MemArg),
+    V128Load32Splat(MemArg),
+    V128Load64Splat(MemArg),
+    V128Store(MemArg),
+    V128Load32Zero(MemArg),
+    V128Load64Zero(MemArg),
+    V128Load8Lane(MemArg, u8),
+    V128Load16Lane(MemArg, u8),
+    V128Load32Lane(MemArg, u8),
+    V128Load64Lane(MemArg, u8),
+    V128Store8Lane(MemArg, u8),
+    V128Store16Lane(MemArg, u8),
+    V128Store32Lane(MemArg, u8),
+    V128Store64Lane(MemArg, u8),
+    V128Const(u128),
+    I8x16Shuffle([u8; 16]),
+    I8x16ExtractLaneS(u8),
+    I8x16ExtractLaneU(u8),
+    I8x16ReplaceLane(u8),
+    I16x8ExtractLaneS(u8),
+    I16x8ExtractLaneU(u8),
+    I16x8ReplaceLane(u8),
+    I32x4ExtractLane(u8),
+    I32x4ReplaceLane(u8),
+    I64x2ExtractLane(u8),
+    I64x2ReplaceLane(u8),
+    F32x4ExtractLane(u8),
+    F32x4ReplaceLane(u8),
+    F64x2ExtractLane(u8),
+    F64x2ReplaceLane(u8),
+    I8x16Swizzle,
+    I8x16Splat,
+    I16x8Splat,
+    I32x4Splat,
+    I64x2Splat,
+    F32x4Splat,
+    F64x2Splat,
+    I8x16Eq,
+    I8x16Ne,
+    I8x16LtS,
+    I8x16LtU,
+    I8x16GtS,
+    I8x16GtU,
+    I8x16LeS,
+    I8x16LeU,
+    I8x16GeS,
+    I8x16GeU,
+    I16x8Eq,
+    I16x8Ne,
+    I16x8LtS,
+    I16x8LtU,
+    I16x8GtS,
+    I16x8GtU,
+    I16x8LeS,
+    I16x8LeU,
+    I16x8GeS,
+    I16x8GeU,
+    I32x4Eq,
+    I32x4Ne,
+    I32x4LtS,
+    I32x4LtU,
+    I32x4GtS,
+    I32x4GtU,
+    I32x4LeS,
+    I32x4LeU,
+    I32x4GeS,
+    I32x4GeU,
+    F32x4Eq,
+    F32x4Ne,
+    F32x4Lt,
+    F32x4Gt,
+    F32x4Le,
+    F32x4Ge,
+    F64x2Eq,
+    F64x2Ne,
+    F64x2Lt,
+    F64x2Gt,
+    F64x2Le,
+    F64x2Ge,
+    V128Not,
+    V128And,
+    V128Andnot,
+    V128Or,
+    V128Xor,
+    V128Bitselect,
+    V128AnyTrue,
+    F32x4DemoteF64x2Zero,
+    F64x2PromoteLowF32x4,
+    I8x16Abs,
+    I8x16Neg,
+    I8x16Popcnt,
+    I8x16AllTrue,
+    I8x16Bitmask,
+    I8x16NarrowI16x8S,
+    I8x16NarrowI16x8U,
+    F32x4Ceil,
+    F32x4Floor,
+    F32x4Trunc,
+    F32x4Nearest,
+    I8x16Shl,
+    I8x16ShrS,
+    I8x16ShrU,
+    I8x16Add,
+    I8x16AddSatS,
+    I8x16AddSatU,
+    I8x16Sub,
+    I8x16SubSatS,
+    I8x16SubSatU,
+    F64x2Ceil,
+    F64x2Floor,
+    I8x16MinS,
+    I8x16MinU,
+    I8x16MaxS,
+    I8x16MaxU,
+    F64x2Trunc,
+    I8x16AvgrU,
+    I16x8ExtaddPairwiseI8x16S,
+    I16x8ExtaddPairwiseI8x16U,
+    I32x4ExtaddPairwiseI16x8S,
+    I32x4ExtaddPairwiseI16x8U,
+    I16x8Abs,
+    I16x8Neg,
+    I16x8Q15mulrSatS,
+    I16x8AllTrue,
+    I16x8Bitmask,
+    I16x8NarrowI32x4S,
+    I16x8NarrowI32x4U,
+    I16x8ExtendLowI8x16S,
+    I16x8ExtendHighI8x16S,
+    I16x8ExtendLowI8x16U,
+    I16x8ExtendHighI8x16U,
+    I16x8Shl,
+    I16x8ShrS,
+    I16x8ShrU,
+    I16x8Add,
+    I16x8AddSatS,
+    I16x8AddSatU,
+    I16x8Sub,
+    I16x8SubSatS,
+    I16x8SubSatU,
+    F64x2Nearest,
+    I16x8Mul,
+    I16x8MinS,
+    I16x8MinU,
+    I16x8MaxS,
+    I16x8MaxU,
+    I16x8AvgrU,
+    I16x8ExtmulLowI8x16S,
+    I16x8ExtmulHighI8x16S,
+    I16x8ExtmulLowI8x16U,
+    I16x8ExtmulHighI8x16U,
+    I32x4Abs,
+    I32x4Neg,
+    I32x4AllTrue,
+    I32x4Bitmask,
+    I32x4ExtendLowI16x8S,
+    I32x4ExtendHighI16x8S,
+    I32x4ExtendLowI16x8U,
+    I32x4ExtendHighI16x8U,
+    I32x4Shl,
+    I32x4ShrS,
+    I32x4ShrU,
+    I32x4Add,
+    I32x4Sub,
+    I32x4Mul,
+    I32x4MinS,
+    I32x4MinU,
+    I32x4MaxS,
+    I32x4MaxU,
+    I32x4DotI16x8S,
+    I32x4ExtmulLowI16x8S,
+    I32x4ExtmulHighI16x8S,
+    I32x4ExtmulLowI16x8U,
+    I32x4ExtmulHighI16x8U,
+    I64x2Abs,
+    I64x2Neg,
+    I64x2AllTrue,
+    I64x2Bitmask,
+    I64x2ExtendLowI32x4S,
+    I64x2ExtendHighI32x4S,
+    I64x2ExtendLowI32x4U,
+    I64x2ExtendHighI32x4U,
+    I64x2Shl,
+    I64x2ShrS,
+    I64x2ShrU,
+    I64x2Add,
+    I64x2Sub,
+    I64x2Mul,
+    I64x2Eq,
+    I64x2Ne,
+    I64x2LtS,
+    I64x2GtS,
+    I64x2LeS,
+    I64x2GeS,
+    I64x2ExtmulLowI32x4S,
+    I64x2ExtmulHighI32x4S,
+    I64x2ExtmulLowI32x4U,
+    I64x2ExtmulHighI32x4U,
+    F32x4Abs,
+    F32x4Neg,
+    F32x4Sqrt,
+    F32x4Add,
+    F32x4Sub,
+    F32x4Mul,
+    F32x4Div,
+    F32x4Min,
+    F32x4Max,
+    F32x4Pmin,
+    F32x4Pmax,
+    F64x2Abs,
+    F64x2Neg,
+    F64x2Sqrt,
+    F64x2Add,
+    F64x2Sub,
+    F64x2Mul,
+    F64x2Div,
+    F64x2Min,
+    F64x2Max,
+    F64x2Pmin,
+    F64x2Pmax,
+    I32x4TruncSatF32x4S,
+    I32x4TruncSatF32x4U,
+    F32x4ConvertI32x4S,
+    F32x4ConvertI32x4U,
+    I32x4TruncSatF64x2SZero,
+    I32x4TruncSatF64x2UZero,
+    F64x2ConvertLowI32x4S,
+    F64x2ConvertLowI32x4U,
+}
+
+// Generates the encoding and text name lookups of a family of instructions that share the
+// shape of their immediates. `$ctor` is the type of `Instr::Variant` for that shape and
+// `$extra` carries a per-instruction property, e.g. the natural alignment of loads.
+// Opcodes behind a prefix byte are written as `prefix << 24 | subopcode`.
+macro_rules! instr_table {
+    (@extra) => { () };
+    (@extra $extra:expr) => { $extra };
+    (
+        $info:ident, $by_opcode:ident, $by_name:ident, $ctor:ty, $extra:ty;
+        $($variant:ident = $opcode:literal, $name:literal $(, $e:expr)?;)*
+    ) => {
+        impl Instr {
+            pub fn $info(&self) -> Option<(u32, &'static str, $extra)> {
+                match self {
+                    $(Instr::$variant { .. } => Some(($opcode, $name, instr_table!(@extra $($e)?))),)*
+                    _ => None,
+                }
+            }
+
+            pub fn $by_opcode(opcode: u32) -> Option<($ctor, $extra)> {
+                match opcode {
+                    $($opcode => Some((Instr::$variant, instr_table!(@extra $($e)?))),)*
+                    _ => None,
+                }
+            }
+
+            pub fn $by_name(name: &str) -> Option<($ctor, $extra)> {
+                match name {
+                    $($name => Some((Instr::$variant, instr_table!(@extra $($e)?))),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// Instructions without immediates
+instr_table! {
+    plain_info, plain_by_opcode, plain_by_name, Instr, ();
+    Unreachable = 0x00, "unreachable";
+    Nop = 0x01, "nop";
+    Return = 0x0F, "return";
+    RefIsNull = 0xD1, "ref.is_null";
+    Drop = 0x1A, "drop";
+    I32Eqz = 0x45, "i32.eqz";
+    I32Eq = 0x46, "i32.eq";
+    I32Ne = 0x47, "i32.ne";
+    I32LtS = 0x48, "i32.lt_s";
+    I32LtU = 0x49, "i32.lt_u";
+    I32GtS = 0x4A, "i32.gt_s";
+    I32GtU = 0x4B, "i32.gt_u";
+    I32LeS = 0x4C, "i32.le_s";
+    I32LeU = 0x4D, "i32.le_u";
+    I32GeS = 0x4E, "i32.ge_s";
+    I32GeU = 0x4F, "i32.ge_u";
+    I64Eqz = 0x50, "i64.eqz";
+    I64Eq = 0x51, "i64.eq";
+    I64Ne = 0x52, "i64.ne";
+    I64LtS = 0x53, "i64.lt_s";
+    I64LtU = 0x54, "i64.lt_u";
+    I64GtS = 0x55, "i64.gt_s";
+    I64GtU = 0x56, "i64.gt_u";
+    I64LeS = 0x57, "i64.le_s";
+    I64LeU = 0x58, "i64.le_u";
+    I64GeS = 0x59, "i64.ge_s";
+    I64GeU = 0x5A, "i64.ge_u";
+    F32Eq = 0x5B, "f32.eq";
+    F32Ne = 0x5C, "f32.ne";
+    F32Lt = 0x5D, "f32.lt";
+    F32Gt = 0x5E, "f32.gt";
+    F32Le = 0x5F, "f32.le";
+    F32Ge = 0x60, "f32.ge";
+    F64Eq = 0x61, "f64.eq";
+    F64Ne = 0x62, "f64.ne";
+    F64Lt = 0x63, "f64.lt";
+    F64Gt = 0x64, "f64.gt";
+    F64Le = 0x65, "f64.le";
+    F64Ge = 0x66, "f64.ge";
+    I32Clz = 0x67, "i32.clz";
+    I32Ctz = 0x68, "i32.ctz";
+    I32Popcnt = 0x69, "i32.popcnt";
+    I32Add = 0x6A, "i32.add";
+    I32Sub = 0x6B, "i32.sub";
+    I32Mul = 0x6C, "i32.mul";
+    I32DivS = 0x6D, "i32.div_s";
+    I32DivU = 0x6E, "i32.div_u";
+    I32RemS = 0x6F, "i32.rem_s";
+    I32RemU = 0x70, "i32.rem_u";
+    I32And = 0x71, "i32.and";
+    I32Or = 0x72, "i32.or";
+    I32Xor = 0x73, "i32.xor";
+    I32Shl = 0x74, "i32.shl";
+    I32ShrS = 0x75, "i32.shr_s";
+    I32ShrU = 0x76, "i32.shr_u";
+    I32Rotl = 0x77, "i32.rotl";
+    I32Rotr = 0x78, "i32.rotr";
+    I64Clz = 0x79, "i64.clz";
+    I64Ctz = 0x7A, "i64.ctz";
+    I64Popcnt = 0x7B, "i64.popcnt";
+    I64Add = 0x7C, "i64.add";
+    I64Sub = 0x7D, "i64.sub";
+    I64Mul = 0x7E, "i64.mul";
+    I64DivS = 0x7F, "i64.div_s";
+    I64DivU = 0x80, "i64.div_u";
+    I64RemS = 0x81, "i64.rem_s";
+    I64RemU = 0x82, "i64.rem_u";
+    I64And = 0x83, "i64.and";
+    I64Or = 0x84, "i64.or";
+    I64Xor = 0x85, "i64.xor";
+    I64Shl = 0x86, "i64.shl";
+    I64ShrS = 0x87, "i64.shr_s";
+    I64ShrU = 0x88, "i64.shr_u";
+    I64Rotl = 0x89, "i64.rotl";
+    I64Rotr = 0x8A, "i64.rotr";
+    F32Abs = 0x8B, "f32.abs";
+    F32Neg = 0x8C, "f32.neg";
+    F32Ceil = 0x8D, "f32.ceil";
+    F32Floor = 0x8E, "f32.floor";
+    F32Trunc = 0x8F, "f32.trunc";
+    F32Nearest = 0x90, "f32.nearest";
+    F32Sqrt = 0x91, "f32.sqrt";
+    F32Add = 0x92, "f32.add";
+    F32Sub = 0x93, "f32.sub";
+    F32Mul = 0x94, "f32.mul";
+    F32Div = 0x95, "f32.div";
+    F32Min = 0x96, "f32.min";
+    F32Max = 0x97, "f32.max";
+    F32Copysign = 0x98, "f32.copysign";
+    F64Abs = 0x99, "f64.abs";
+    F64Neg = 0x9A, "f64.neg";
+    F64Ceil = 0x9B, "f64.ceil";
+    F64Floor = 0x9C, "f64.floor";
+    F64Trunc = 0x9D, "f64.trunc";
+    F64Nearest = 0x9E, "f64.nearest";
+    F64Sqrt = 0x9F, "f64.sqrt";
+    F64Add = 0xA0, "f64.add";
+    F64Sub = 0xA1, "f64.sub";
+    F64Mul = 0xA2, "f64.mul";
+    F64Div = 0xA3, "f64.div";
+    F64Min = 0xA4, "f64.min";
+    F64Max = 0xA5, "f64.max";
+    F64Copysign = 0xA6, "f64.copysign";
+    I32WrapI64 = 0xA7, "i32.wrap_i64";
+    I32TruncF32S = 0xA8, "i32.trunc_f32_s";
+    I32TruncF32U = 0xA9, "i32.trunc_f32_u";
+    I32TruncF64S = 0xAA, "i32.trunc_f64_s";
+    I32TruncF64U = 0xAB, "i32.trunc_f64_u";
+    I64ExtendI32S = 0xAC, "i64.extend_i32_s";
+    I64ExtendI32U = 0xAD, "i64.extend_i32_u";
+    I64TruncF32S = 0xAE, "i64.trunc_f32_s";
+    I64TruncF32U = 0xAF, "i64.trunc_f32_u";
+    I64TruncF64S = 0xB0, "i64.trunc_f64_s";
+    I64TruncF64U = 0xB1, "i64.trunc_f64_u";
+    F32ConvertI32S = 0xB2, "f32.convert_i32_s";
+    F32ConvertI32U = 0xB3, "f32.convert_i32_u";
+    F32ConvertI64S = 0xB4, "f32.convert_i64_s";
+    F32ConvertI64U = 0xB5, "f32.convert_i64_u";
+    F32DemoteF64 = 0xB6, "f32.demote_f64";
+    F64ConvertI32S = 0xB7, "f64.convert_i32_s";
+    F64ConvertI32U = 0xB8, "f64.convert_i32_u";
+    F64ConvertI64S = 0xB9, "f64.convert_i64_s";
+    F64ConvertI64U = 0xBA, "f64.convert_i64_u";
+    F64PromoteF32 = 0xBB, "f64.promote_f32";
+    I32ReinterpretF32 = 0xBC, "i32.reinterpret_f32";
+    I64ReinterpretF64 = 0xBD, "i64.reinterpret_f64";
+    F32ReinterpretI32 = 0xBE, "f32.reinterpret_i32";
+    F64ReinterpretI64 = 0xBF, "f64.reinterpret_i64";
+    I32Extend8S = 0xC0, "i32.extend8_s";
+    I32Extend16S = 0xC1, "i32.extend16_s";
+    I64Extend8S = 0xC2, "i64.extend8_s";
+    I64Extend16S = 0xC3, "i64.extend16_s";
+    I64Extend32S = 0xC4, "i64.extend32_s";
+    I32TruncSatF32S = 0xFC00_0000, "i32.trunc_sat_f32_s";
+    I32TruncSatF32U = 0xFC00_0001, "i32.trunc_sat_f32_u";
+    I32TruncSatF64S = 0xFC00_0002, "i32.trunc_sat_f64_s";
+    I32TruncSatF64U = 0xFC00_0003, "i32.trunc_sat_f64_u";
+    I64TruncSatF32S = 0xFC00_0004, "i64.trunc_sat_f32_s";
+    I64TruncSatF32U = 0xFC00_0005, "i64.trunc_sat_f32_u";
+    I64TruncSatF64S = 0xFC00_0006, "i64.trunc_sat_f64_s";
+    I64TruncSatF64U = 0xFC00_0007, "i64.trunc_sat_f64_u";
+    I8x16Swizzle = 0xFD00_000E, "i8x16.swizzle";
+    I8x16Splat = 0xFD00_000F, "i8x16.splat";
+    I16x8Splat = 0xFD00_0010, "i16x8.splat";
+    I32x4Splat = 0xFD00_0011, "i32x4.splat";
+    I64x2Splat = 0xFD00_0012, "i64x2.splat";
+    F32x4Splat = 0xFD00_0013, "f32x4.splat";
+    F64x2Splat = 0xFD00_0014, "f64x2.splat";
+    I8x16Eq = 0xFD00_0023, "i8x16.eq";
+    I8x16Ne = 0xFD00_0024, "i8x16.ne";
+    I8x16LtS = 0xFD00_0025, "i8x16.lt_s";
+    I8x16LtU = 0xFD00_0026, "i8x16.lt_u";
+    I8x16GtS = 0xFD00_0027, "i8x16.gt_s";
+    I8x16GtU = 0xFD00_0028, "i8x16.gt_u";
+    I8x16LeS = 0xFD00_0029, "i8x16.le_s";
+    I8x16LeU = 0xFD00_002A, "i8x16.le_u";
+    I8x16GeS = 0xFD00_002B, "i8x16.ge_s";
+    I8x16GeU = 0xFD00_002C, "i8x16.ge_u";
+    I16x8Eq = 0xFD00_002D, "i16x8.eq";
+    I16x8Ne = 0xFD00_002E, "i16x8.ne";
+    I16x8LtS = 0xFD00_002F, "i16x8.lt_s";
+    I16x8LtU = 0xFD00_0030, "i16x8.lt_u";
+    I16x8GtS = 0xFD00_0031, "i16x8.gt_s";
+    I16x8GtU = 0xFD00_0032, "i16x8.gt_u";
+    I16x8LeS = 0xFD00_0033, "i16x8.le_s";
+    I16x8LeU = 0xFD00_0034, "i16x8.le_u";
+    I16x8GeS = 0xFD00_0035, "i16x8.ge_s";
+    I16x8GeU = 0xFD00_0036, "i16x8.ge_u";
+    I32x4Eq = 0xFD00_0037, "i32x4.eq";
+    I32x4Ne = 0xFD00_0038, "i32x4.ne";
+    I32x4LtS = 0xFD00_0039, "i32x4.lt_s";
+    I32x4LtU = 0xFD00_003A, "i32x4.lt_u";
+    I32x4GtS = 0xFD00_003B, "i32x4.gt_s";
+    I32x4GtU = 0xFD00_003C, "i32x4.gt_u";
+    I32x4LeS = 0xFD00_003D, "i32x4.le_s";
+    I32x4LeU = 0xFD00_003E, "i32x4.le_u";
+    I32x4GeS = 0xFD00_003F, "i32x4.ge_s";
+    I32x4GeU = 0xFD00_0040, "i32x4.ge_u";
+    F32x4Eq = 0xFD00_0041, "f32x4.eq";
+    F32x4Ne = 0xFD00_0042, "f32x4.ne";
+    F32x4Lt = 0xFD00_0043, "f32x4.lt";
+    F32x4Gt = 0xFD00_0044, "f32x4.gt";
+    F32x4Le = 0xFD00_0045, "f32x4.le";
+    F32x4Ge = 0xFD00_0046, "f32x4.ge";
+    F64x2Eq = 0xFD00_0047, "f64x2.eq";
+    F64x2Ne = 0xFD00_0048, "f64x2.ne";
+    F64x2Lt = 0xFD00_0049, "f64x2.lt";
+    F64x2Gt = 0xFD00_004A, "f64x2.gt";
+    F64x2Le = 0xFD00_004B, "f64x2.le";
+    F64x2Ge = 0xFD00_004C, "f64x2.ge";
+    V128Not = 0xFD00_004D, "v128.not";
+    V128And = 0xFD00_004E, "v128.and";
+    V128Andnot = 0xFD00_004F, "v128.andnot";
+    V128Or = 0xFD00_0050, "v128.or";
+    V128Xor = 0xFD00_0051, "v128.xor";
+    V128Bitselect = 0xFD00_0052, "v128.bitselect";
+    V128AnyTrue = 0xFD00_0053, "v128.any_true";
+    F32x4DemoteF64x2Zero = 0xFD00_005E, "f32x4.demote_f64x2_zero";
+    F64x2PromoteLowF32x4 = 0xFD00_005F, "f64x2.promote_low_f32x4";
+    I8x16Abs = 0xFD00_0060, "i8x16.abs";
+    I8x16Neg = 0xFD00_0061, "i8x16.neg";
+    I8x16Popcnt = 0xFD00_0062, "i8x16.popcnt";
+    I8x16AllTrue = 0xFD00_0063, "i8x16.all_true";
+    I8x16Bitmask = 0xFD00_0064, "i8x16.bitmask";
+    I8x16NarrowI16x8S = 0xFD00_0065, "i8x16.narrow_i16x8_s";
+    I8x16NarrowI16x8U = 0xFD00_0066, "i8x16.narrow_i16x8_u";
+    F32x4Ceil = 0xFD00_0067, "f32x4.ceil";
+    F32x4Floor = 0xFD00_0068, "f32x4.floor";
+    F32x4Trunc = 0xFD00_0069, "f32x4.trunc";
+    F32x4Nearest = 0xFD00_006A, "f32x4.nearest";
+    I8x16Shl = 0xFD00_006B, "i8x16.shl";
+    I8x16ShrS = 0xFD00_006C, "i8x16.shr_s";
+    I8x16ShrU = 0xFD00_006D, "i8x16.shr_u";
+    I8x16Add = 0xFD00_006E, "i8x16.add";
+    I8x16AddSatS = 0xFD00_006F, "i8x16.add_sat_s";
+    I8x16AddSatU = 0xFD00_0070, "i8x16.add_sat_u";
+    I8x16Sub = 0xFD00_0071, "i8x16.sub";
+    I8x16SubSatS = 0xFD00_0072, "i8x16.sub_sat_s";
+    I8x16SubSatU = 0xFD00_0073, "i8x16.sub_sat_u";
+    F64x2Ceil = 0xFD00_0074, "f64x2.ceil";
+    F64x2Floor = 0xFD00_0075, "f64x2.floor";
+    I8x16MinS = 0xFD00_0076, "i8x16.min_s";
+    I8x16MinU = 0xFD00_0077, "i8x16.min_u";
+    I8x16MaxS = 0xFD00_0078, "i8x16.max_s";
+    I8x16MaxU = 0xFD00_0079, "i8x16.max_u";
+    F64x2Trunc = 0xFD00_007A, "f64x2.trunc";
+    I8x16AvgrU = 0xFD00_007B, "i8x16.avgr_u";
+    I16x8ExtaddPairwiseI8x16S = 0xFD00_007C, "i16x8.extadd_pairwise_i8x16_s";
+    I16x8ExtaddPairwiseI8x16U = 0xFD00_007D, "i16x8.extadd_pairwise_i8x16_u";
+    I32x4ExtaddPairwiseI16x8S = 0xFD00_007E, "i32x4.extadd_pairwise_i16x8_s";
+    I32x4ExtaddPairwiseI16x8U = 0xFD00_007F, "i32x4.extadd_pairwise_i16x8_u";
+    I16x8Abs = 0xFD00_0080, "i16x8.abs";
+    I16x8Neg = 0xFD00_0081, "i16x8.neg";
+    I16x8Q15mulrSatS = 0xFD00_0082, "i16x8.q15mulr_sat_s";
+    I16x8AllTrue = 0xFD00_0083, "i16x8.all_true";
+    I16x8Bitmask = 0xFD00_0084, "i16x8.bitmask";
+    I16x8NarrowI32x4S = 0xFD00_0085, "i16x8.narrow_i32x4_s";
+    I16x8NarrowI32x4U = 0xFD00_0086, "i16x8.narrow_i32x4_u";
+    I16x8ExtendLowI8x16S = 0xFD00_0087, "i16x8.extend_low_i8x16_s";
+    I16x8ExtendHighI8x16S = 0xFD00_0088, "i16x8.extend_high_i8x16_s";
+    I16x8ExtendLowI8x16U = 0xFD00_0089, "i16x8.extend_low_i8x16_u";
+    I16x8ExtendHighI8x16U = 0xFD00_008A, "i16x8.extend_high_i8x16_u";
+    I16x8Shl = 0xFD00_008B, "i16x8.shl";
+    I16x8ShrS = 0xFD00_008C, "i16x8.shr_s";
+    I16x8ShrU = 0xFD00_008D, "i16x8.shr_u";
+    I16x8Add = 0xFD00_008E, "i16x8.add";
+    I16x8AddSatS = 0xFD00_008F, "i16x8.add_sat_s";
+    I16x8AddSatU = 0xFD00_0090, "i16x8.add_sat_u";
+    I16x8Sub = 0xFD00_0091, "i16x8.sub";
+    I16x8SubSatS = 0xFD00_0092, "i16x8.sub_sat_s";
+    I16x8SubSatU = 0xFD00_0093, "i16x8.sub_sat_u";
+    F64x2Nearest = 0xFD00_0094, "f64x2.nearest";
+    I16x8Mul = 0xFD00_0095, "i16x8.mul";
+    I16x8MinS = 0xFD00_0096, "i16x8.min_s";
+    I16x8MinU = 0xFD00_0097, "i16x8.min_u";
+    I16x8MaxS = 0xFD00_0098, "i16x8.max_s";
+    I16x8MaxU = 0xFD00_0099, "i16x8.max_u";
+    I16x8AvgrU = 0xFD00_009B, "i16x8.avgr_u";
+    I16x8ExtmulLowI8x16S = 0xFD00_009C, "i16x8.extmul_low_i8x16_s";
+    I16x8ExtmulHighI8x16S = 0xFD00_009D, "i16x8.extmul_high_i8x16_s";
+    I16x8ExtmulLowI8x16U = 0xFD00_009E, "i16x8.extmul_low_i8x16_u";
+    I16x8ExtmulHighI8x16U = 0xFD00_009F, "i16x8.extmul_high_i8x16_u";
+    I32x4Abs = 0xFD00_00A0, "i32x4.abs";
+    I32x4Neg = 0xFD00_00A1, "i32x4.neg";
+    I32x4AllTrue = 0xFD00_00A3, "i32x4.all_true";
+    I32x4Bitmask = 0xFD00_00A4, "i32x4.bitmask";
+    I32x4ExtendLowI16x8S = 0xFD00_00A7, "i32x4.extend_low_i16x8_s";
+    I32x4ExtendHighI16x8S = 0xFD00_00A8, "i32x4.extend_high_i16x8_s";
+    I32x4ExtendLowI16x8U = 0xFD00_00A9, "i32x4.extend_low_i16x8_u";
+    I32x4ExtendHighI16x8U = 0xFD00_00AA, "i32x4.extend_high_i16x8_u";
+    I32x4Shl = 0xFD00_00AB, "i32x4.shl";
+    I32x4ShrS = 0xFD00_00AC, "i32x4.shr_s";
+    I32x4ShrU = 0xFD00_00AD, "i32x4.shr_u";
+    I32x4Add = 0xFD00_00AE, "i32x4.add";
+    I32x4Sub = 0xFD00_00B1, "i32x4.sub";
+    I32x4Mul = 0xFD00_00B5, "i32x4.mul";
+    I32x4MinS = 0xFD00_00B6, "i32x4.min_s";
+    I32x4MinU = 0xFD00_00B7, "i32x4.min_u";
+    I32x4MaxS = 0xFD00_00B8, "i32x4.max_s";
+    I32x4MaxU = 0xFD00_00B9, "i32x4.max_u";
+    I32x4DotI16x8S = 0xFD00_00BA, "i32x4.dot_i16x8_s";
+    I32x4ExtmulLowI16x8S = 0xFD00_00BC, "i32x4.extmul_low_i16x8_s";
+    I32x4ExtmulHighI16x8S = 0xFD00_00BD, "i32x4.extmul_high_i16x8_s";
+    I32x4ExtmulLowI16x8U = 0xFD00_00BE, "i32x4.extmul_low_i16x8_u";
+    I32x4ExtmulHighI16x8U = 0xFD00_00BF, "i32x4.extmul_high_i16x8_u";
+    I64x2Abs = 0xFD00_00C0, "i64x2.abs";
+    I64x2Neg = 0xFD00_00C1, "i64x2.neg";
+    I64x2AllTrue = 0xFD00_00C3, "i64x2.all_true";
+    I64x2Bitmask = 0xFD00_00C4, "i64x2.bitmask";
+    I64x2ExtendLowI32x4S = 0xFD00_00C7, "i64x2.extend_low_i32x4_s";
+    I64x2ExtendHighI32x4S = 0xFD00_00C8, "i64x2.extend_high_i32x4_s";
+    I64x2ExtendLowI32x4U = 0xFD00_00C9, "i64x2.extend_low_i32x4_u";
+    I64x2ExtendHighI32x4U = 0xFD00_00CA, "i64x2.extend_high_i32x4_u";
+    I64x2Shl = 0xFD00_00CB, "i64x2.shl";
+    I64x2ShrS = 0xFD00_00CC, "i64x2.shr_s";
+    I64x2ShrU = 0xFD00_00CD, "i64x2.shr_u";
+    I64x2Add = 0xFD00_00CE, "i64x2.add";
+    I64x2Sub = 0xFD00_00D1, "i64x2.sub";
+    I64x2Mul = 0xFD00_00D5, "i64x2.mul";
+    I64x2Eq = 0xFD00_00D6, "i64x2.eq";
+    I64x2Ne = 0xFD00_00D7, "i64x2.ne";
+    I64x2LtS = 0xFD00_00D8, "i64x2.lt_s";
+    I64x2GtS = 0xFD00_00D9, "i64x2.gt_s";
+    I64x2LeS = 0xFD00_00DA, "i64x2.le_s";
+    I64x2GeS = 0xFD00_00DB, "i64x2.ge_s";
+    I64x2ExtmulLowI32x4S = 0xFD00_00DC, "i64x2.extmul_low_i32x4_s";
+    I64x2ExtmulHighI32x4S = 0xFD00_00DD, "i64x2.extmul_high_i32x4_s";
+    I64x2ExtmulLowI32x4U = 0xFD00_00DE, "i64x2.extmul_low_i32x4_u";
+    I64x2ExtmulHighI32x4U = 0xFD00_00DF, "i64x2.extmul_high_i32x4_u";
+    F32x4Abs = 0xFD00_00E0, "f32x4.abs";
+    F32x4Neg = 0xFD00_00E1, "f32x4.neg";
+    F32x4Sqrt = 0xFD00_00E3, "f32x4.sqrt";
+    F32x4Add = 0xFD00_00E4, "f32x4.add";
+    F32x4Sub = 0xFD00_00E5, "f32x4.sub";
+    F32x4Mul = 0xFD00_00E6, "f32x4.mul";
+    F32x4Div = 0xFD00_00E7, "f32x4.div";
+    F32x4Min = 0xFD00_00E8, "f32x4.min";
+    F32x4Max = 0xFD00_00E9, "f32x4.max";
+    F32x4Pmin = 0xFD00_00EA, "f32x4.pmin";
+    F32x4Pmax = 0xFD00_00EB, "f32x4.pmax";
+    F64x2Abs = 0xFD00_00EC, "f64x2.abs";
+    F64x2Neg = 0xFD00_00ED, "f64x2.neg";
+    F64x2Sqrt = 0xFD00_00EF, "f64x2.sqrt";
+    F64x2Add = 0xFD00_00F0, "f64x2.add";
+    F64x2Sub = 0xFD00_00F1, "f64x2.sub";
+    F64x2Mul = 0xFD00_00F2, "f64x2.mul";
+    F64x2Div = 0xFD00_00F3, "f64x2.div";
+    F64x2Min = 0xFD00_00F4, "f64x2.min";
+    F64x2Max = 0xFD00_00F5, "f64x2.max";
+    F64x2Pmin = 0xFD00_00F6, "f64x2.pmin";
+    F64x2Pmax = 0xFD00_00F7, "f64x2.pmax";
+    I32x4TruncSatF32x4S = 0xFD00_00F8, "i32x4.trunc_sat_f32x4_s";
+    I32x4TruncSatF32x4U = 0xFD00_00F9, "i32x4.trunc_sat_f32x4_u";
+    F32x4ConvertI32x4S = 0xFD00_00FA, "f32x4.convert_i32x4_s";
+    F32x4ConvertI32x4U = 0xFD00_00FB, "f32x4.convert_i32x4_u";
+    I32x4TruncSatF64x2SZero = 0xFD00_00FC, "i32x4.trunc_sat_f64x2_s_zero";
+    I32x4TruncSatF64x2UZero = 0xFD00_00FD, "i32x4.trunc_sat_f64x2_u_zero";
+    F64x2ConvertLowI32x4S = 0xFD00_00FE, "f64x2.convert_low_i32x4_s";
+    F64x2ConvertLowI32x4U = 0xFD00_00FF, "f64x2.convert_low_i32x4_u";
+}
+
+// Loads and stores, with their natural alignment
+instr_table! {
+    memory_info, memory_by_opcode, memory_by_name, fn(MemArg) -> Instr, u32;
+    I32Load = 0x28, "i32.load", 2;
+    I64Load = 0x29, "i64.load", 3;
+    F32Load = 0x2A, "f32.load", 2;
+    F64Load = 0x2B, "f64.load", 3;
+    I32Load8S = 0x2C, "i32.load8_s", 0;
+    I32Load8U = 0x2D, "i32.load8_u", 0;
+    I32Load16S = 0x2E, "i32.load16_s", 1;
+    I32Load16U = 0x2F, "i32.load16_u", 1;
+    I64Load8S = 0x30, "i64.load8_s", 0;
+    I64Load8U = 0x31, "i64.load8_u", 0;
+    I64Load16S = 0x32, "i64.load16_s", 1;
+    I64Load16U = 0x33, "i64.load16_u", 1;
+    I64Load32S = 0x34, "i64.load32_s", 2;
+    I64Load32U = 0x35, "i64.load32_u", 2;
+    I32Store = 0x36, "i32.store", 2;
+    I64Store = 0x37, "i64.store", 3;
+    F32Store = 0x38, "f32.store", 2;
+    F64Store = 0x39, "f64.store", 3;
+    I32Store8 = 0x3A, "i32.store8", 0;
+    I32Store16 = 0x3B, "i32.store16", 1;
+    I64Store8 = 0x3C, "i64.store8", 0;
+    I64Store16 = 0x3D, "i64.store16", 1;
+    I64Store32 = 0x3E, "i64.store32", 2;
+    V128Load = 0xFD00_0000, "v128.load", 4;
+    V128Load8x8S = 0xFD00_0001, "v128.load8x8_s", 3;
+    V128Load8x8U = 0xFD00_0002, "v128.load8x8_u", 3;
+    V128Load16x4S = 0xFD00_0003, "v128.load16x4_s", 3;
+    V128Load16x4U = 0xFD00_0004, "v128.load16x4_u", 3;
+    V128Load32x2S = 0xFD00_0005, "v128.load32x2_s", 3;
+    V128Load32x2U = 0xFD00_0006, "v128.load32x2_u", 3;
+    V128Load8Splat = 0xFD00_0007, "v128.load8_splat", 0;
+    V128Load16Splat = 0xFD00_0008, "v128.load16_splat", 1;
+    V128Load32Splat = 0xFD00_0009, "v128.load32_splat", 2;
+    V128Load64Splat = 0xFD00_000A, "v128.load64_splat", 3;
+    V128Store = 0xFD00_000B, "v128.store", 4;
+    V128Load32Zero = 0xFD00_005C, "v128.load32_zero", 2;
+    V128Load64Zero = 0xFD00_005D, "v128.load64_zero", 3;
+}
+
+// Vector loads and stores of a single lane, with their natural alignment and lane count
+instr_table! {
+    memory_lane_info, memory_lane_by_opcode, memory_lane_by_name, fn(MemArg, u8) -> Instr, (u32, u8);
+    V128Load8Lane = 0xFD00_0054, "v128.load8_lane", (0, 16);
+    V128Load16Lane = 0xFD00_0055, "v128.load16_lane", (1, 8);
+    V128Load32Lane = 0xFD00_0056, "v128.load32_lane", (2, 4);
+    V128Load64Lane = 0xFD00_0057, "v128.load64_lane", (3, 2);
+    V128Store8Lane = 0xFD00_0058, "v128.store8_lane", (0, 16);
+    V128Store16Lane = 0xFD00_0059, "v128.store16_lane", (1, 8);
+    V128Store32Lane = 0xFD00_005A, "v128.store32_lane", (2, 4);
+    V128Store64Lane = 0xFD00_005B, "v128.store64_lane", (3, 2);
+}
+
+// Vector lane accesses, with their lane count
+instr_table! {
+    lane_info, lane_by_opcode, lane_by_name, fn(u8) -> Instr, u8;
+    I8x16ExtractLaneS = 0xFD00_0015, "i8x16.extract_lane_s", 16;
+    I8x16ExtractLaneU = 0xFD00_0016, "i8x16.extract_lane_u", 16;
+    I8x16ReplaceLane = 0xFD00_0017, "i8x16.replace_lane", 16;
+    I16x8ExtractLaneS = 0xFD00_0018, "i16x8.extract_lane_s", 8;
+    I16x8ExtractLaneU = 0xFD00_0019, "i16x8.extract_lane_u", 8;
+    I16x8ReplaceLane = 0xFD00_001A, "i16x8.replace_lane", 8;
+    I32x4ExtractLane = 0xFD00_001B, "i32x4.extract_lane", 4;
+    I32x4ReplaceLane = 0xFD00_001C, "i32x4.replace_lane", 4;
+    I64x2ExtractLane = 0xFD00_001D, "i64x2.extract_lane", 2;
+    I64x2ReplaceLane = 0xFD00_001E, "i64x2.replace_lane", 2;
+    F32x4ExtractLane = 0xFD00_001F, "f32x4.extract_lane", 4;
+    F32x4ReplaceLane = 0xFD00_0020, "f32x4.replace_lane", 4;
+    F64x2ExtractLane = 0xFD00_0021, "f64x2.extract_lane", 2;
+    F64x2ReplaceLane = 0xFD00_0022, "f64x2.replace_lane", 2;
+}
+
+impl Instr {
+    // Returns the memory argument of a load or store.
+    pub fn memarg(&self) -> Option<&MemArg> {
+        match self {
+            Instr::I32Load(memarg) | Instr::I64Load(memarg) | Instr::F32Load(memarg) | Instr::F64Load(memarg)
+            | Instr::I32Load8S(memarg) | Instr::I32Load8U(memarg) | Instr::I32Load16S(memarg)
+            | Instr::I32Load16U(memarg) | Instr::I64Load8S(memarg) | Instr::I64Load8U(memarg)
+            | Instr::I64Load16S(memarg) | Instr::I64Load16U(memarg) | Instr::I64Load32S(memarg)
+            | Instr::I64Load32U(memarg) | Instr::I32Store(memarg) | Instr::I64Store(memarg)
+            | Instr::F32Store(memarg) | Instr::F64Store(memarg) | Instr::I32Store8(memarg)
+            | Instr::I32Store16(memarg) | Instr::I64Store8(memarg) | Instr::I64Store16(memarg)
+            | Instr::I64Store32(memarg) | Instr::V128Load(memarg) | Instr::V128Load8x8S(memarg)
+            | Instr::V128Load8x8U(memarg) | Instr::V128Load16x4S(memarg) | Instr::V128Load16x4U(memarg)
+            | Instr::V128Load32x2S(memarg) | Instr::V128Load32x2U(memarg) | Instr::V128Load8Splat(memarg)
+            | Instr::V128Load16Splat(memarg) | Instr::V128Load32Splat(memarg)
+            | Instr::V128Load64Splat(memarg) | Instr::V128Store(memarg) | Instr::V128Load32Zero(memarg)
+            | Instr::V128Load64Zero(memarg) | Instr::V128Load8Lane(memarg, _) | Instr::V128Load16Lane(memarg, _)
+            | Instr::V128Load32Lane(memarg, _) | Instr::V128Load64Lane(memarg, _)
+            | Instr::V128Store8Lane(memarg, _) | Instr::V128Store16Lane(memarg, _)
+            | Instr::V128Store32Lane(memarg, _) | Instr::V128Store64Lane(memarg, _) => Some(memarg),
+            _ => None,
+        }
+    }
+
+    // Returns the lane index of a lane access, including the single lane loads and stores.
+    pub fn lane(&self) -> Option<u8> {
+        match self {
+            Instr::V128Load8Lane(_, lane) | Instr::V128Load16Lane(_, lane) | Instr::V128Load32Lane(_, lane)
+            | Instr::V128Load64Lane(_, lane) | Instr::V128Store8Lane(_, lane) | Instr::V128Store16Lane(_, lane)
+            | Instr::V128Store32Lane(_, lane) | Instr::V128Store64Lane(_, lane)
+            | Instr::I8x16ExtractLaneS(lane) | Instr::I8x16ExtractLaneU(lane) | Instr::I8x16ReplaceLane(lane)
+            | Instr::I16x8ExtractLaneS(lane) | Instr::I16x8ExtractLaneU(lane) | Instr::I16x8ReplaceLane(lane)
+            | Instr::I32x4ExtractLane(lane) | Instr::I32x4ReplaceLane(lane) | Instr::I64x2ExtractLane(lane)
+            | Instr::I64x2ReplaceLane(lane) | Instr::F32x4ExtractLane(lane) | Instr::F32x4ReplaceLane(lane)
+            | Instr::F64x2ExtractLane(lane) | Instr::F64x2ReplaceLane(lane) => Some(*lane),
+            _ => None,
+        }
+    }
 }
 
 // Func ::= {type typeidx, locals vec(ValType), body Expr}
 #[derive(Debug, PartialEq, Clone, Eq)]
 pub struct Func {
-    pub f_type: i32,
+    pub f_type: usize,
     pub locals: Vec<ValueType>,
     pub body: Vec<Instr>,
 }
 
+// Table ::= {type tabletype}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Table {
+    pub table_type: TableType,
+}
+
+// Mem ::= {type memtype}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Mem {
+    pub mem_type: MemType,
+}
+
+// Global ::= {type globaltype, init Expr}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Global {
+    pub global_type: GlobalType,
+    pub init: Vec<Instr>,
+}
+
+// ElemMode ::= Passive | Active {table tableidx, offset Expr} | Declarative
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub enum ElemMode {
+    Passive,
+    Active { table: usize, offset: Vec<Instr> },
+    Declarative,
+}
+
+// Elem ::= {type reftype, init vec(Expr), mode ElemMode}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Elem {
+    pub elem_type: ReferenceType,
+    pub init: Vec<Vec<Instr>>,
+    pub mode: ElemMode,
+}
+
+// DataMode ::= Passive | Active {memory memidx, offset Expr}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub enum DataMode {
+    Passive,
+    Active { memory: usize, offset: Vec<Instr> },
+}
+
+// Data ::= {init vec(byte), mode DataMode}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Data {
+    pub init: Vec<u8>,
+    pub mode: DataMode,
+}
+
+// Start ::= {func funcidx}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Start {
+    pub func: usize,
+}
+
+// ImportDesc ::= Func(typeidx) | Table(TableType) | Mem(MemType) | Global(GlobalType)
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub enum ImportDesc {
+    Func(usize),
+    Table(TableType),
+    Mem(MemType),
+    Global(GlobalType),
+}
+
+// Import ::= {module name, name name, desc ImportDesc}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub desc: ImportDesc,
+}
+
 // ExportDesc ::= Func(funcidx) | Table(tableidx) | Mem(memidx) | Global(globalidx)
 #[derive(Debug, PartialEq, Clone, Eq)]
 pub enum ExportDesc {
@@ -75,4 +1162,30 @@ pub enum ExportDesc {
 pub struct Export {
     pub name: String,
     pub desc: ExportDesc,
-}
\ No newline at end of file
+}
+
+// NameMap ::= vec(idx name)
+pub type NameMap = BTreeMap<usize, String>;
+
+// Names ::= {module name?, funcs namemap, locals indirectnamemap, types namemap, ...}
+// The contents of the `name` custom section, including the subsections of the extended
+// name section proposal for the other index spaces.
+#[derive(Debug, PartialEq, Clone, Eq, Default)]
+pub struct Names {
+    pub module: Option<String>,
+    pub funcs: NameMap,
+    pub locals: BTreeMap<usize, NameMap>,
+    pub types: NameMap,
+    pub tables: NameMap,
+    pub mems: NameMap,
+    pub globals: NameMap,
+    pub elems: NameMap,
+    pub datas: NameMap,
+}
+
+// Custom ::= {name name, data vec(byte)}
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Custom {
+    pub name: String,
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,768 @@
+use crate::ast::{
+    BlockType, DataMode, Elem, ElemMode, ExportDesc, FuncType, GlobalType, ImportDesc, Instr, Limits, MemArg, Module,
+    NameMap, Names, NumberType, ReferenceType, TableType, ValueType, VectorType,
+};
+use crate::runtime::loader::section;
+
+// Output buffer mirroring `loader::Reader`.
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Self { data: vec![] }
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.data.push(byte);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn dword(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn qword(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.unsigned(value as u64);
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.signed(value as i64);
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.signed(value);
+    }
+
+    // Unsigned LEB128 in the fewest bytes possible
+    fn unsigned(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.byte(byte);
+                return;
+            }
+            self.byte(byte | 0x80);
+        }
+    }
+
+    // Signed LEB128 in the fewest bytes possible
+    fn signed(&mut self, mut value: i64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+            if done {
+                self.byte(byte);
+                return;
+            }
+            self.byte(byte | 0x80);
+        }
+    }
+
+    // name ::= b*:vec(byte)
+    fn name(&mut self, name: &str) {
+        self.usize(name.len());
+        self.bytes(name.as_bytes());
+    }
+}
+
+// Serializes `module` to the binary format. Sections are written in their canonical order
+// and omitted when empty, custom sections go last.
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut wasm = Writer::new();
+    wasm.bytes(b"\0asm");
+    wasm.dword(1);
+
+    if !module.types.is_empty() {
+        write_section(&mut wasm, section::TYPE, |w| write_type_section(w, module));
+    }
+    if !module.imports.is_empty() {
+        write_section(&mut wasm, section::IMPORT, |w| write_import_section(w, module));
+    }
+    if !module.funcs.is_empty() {
+        write_section(&mut wasm, section::FUNCTION, |w| write_function_section(w, module));
+    }
+    if !module.tables.is_empty() {
+        write_section(&mut wasm, section::TABLE, |w| write_table_section(w, module));
+    }
+    if !module.mems.is_empty() {
+        write_section(&mut wasm, section::MEMORY, |w| write_memory_section(w, module));
+    }
+    if !module.globals.is_empty() {
+        write_section(&mut wasm, section::GLOBAL, |w| write_global_section(w, module));
+    }
+    if !module.exports.is_empty() {
+        write_section(&mut wasm, section::EXPORT, |w| write_export_section(w, module));
+    }
+    if let Some(start) = &module.start {
+        write_section(&mut wasm, section::START, |w| w.usize(start.func));
+    }
+    if !module.elem.is_empty() {
+        write_section(&mut wasm, section::ELEMENT, |w| write_element_section(w, module));
+    }
+    if let Some(count) = module.data_count {
+        write_section(&mut wasm, section::DATA_COUNT, |w| w.u32(count));
+    }
+    if !module.funcs.is_empty() {
+        write_section(&mut wasm, section::CODE, |w| write_code_section(w, module));
+    }
+    if !module.data.is_empty() {
+        write_section(&mut wasm, section::DATA, |w| write_data_section(w, module));
+    }
+    for custom in &module.customs {
+        write_section(&mut wasm, section::CUSTOM, |w| {
+            w.name(&custom.name);
+            w.bytes(&custom.data);
+        });
+    }
+    wasm.data
+}
+
+// Serializes the contents of a `name` custom section, the inverse of `loader::decode_names`.
+// Subsections without any names are omitted.
+pub fn encode_names(names: &Names) -> Vec<u8> {
+    let mut wasm = Writer::new();
+    if let Some(module) = &names.module {
+        write_section(&mut wasm, 0, |w| w.name(module));
+    }
+    write_namemap_section(&mut wasm, 1, &names.funcs);
+    if !names.locals.is_empty() {
+        write_section(&mut wasm, 2, |w| {
+            w.usize(names.locals.len());
+            for (idx, locals) in &names.locals {
+                w.usize(*idx);
+                write_namemap(w, locals);
+            }
+        });
+    }
+    write_namemap_section(&mut wasm, 4, &names.types);
+    write_namemap_section(&mut wasm, 5, &names.tables);
+    write_namemap_section(&mut wasm, 6, &names.mems);
+    write_namemap_section(&mut wasm, 7, &names.globals);
+    write_namemap_section(&mut wasm, 8, &names.elems);
+    write_namemap_section(&mut wasm, 9, &names.datas);
+    wasm.data
+}
+
+fn write_namemap_section(wasm: &mut Writer, id: u8, names: &NameMap) {
+    if !names.is_empty() {
+        write_section(wasm, id, |w| write_namemap(w, names));
+    }
+}
+
+// namemap ::= vec(nameassoc)
+// nameassoc ::= idx name
+fn write_namemap(wasm: &mut Writer, names: &NameMap) {
+    wasm.usize(names.len());
+    for (idx, name) in names {
+        wasm.usize(*idx);
+        wasm.name(name);
+    }
+}
+
+// section_N(B) ::= N:byte size:u32 cont:B
+fn write_section(wasm: &mut Writer, id: u8, contents: impl FnOnce(&mut Writer)) {
+    let mut section = Writer::new();
+    contents(&mut section);
+    wasm.byte(id);
+    wasm.usize(section.data.len());
+    wasm.bytes(&section.data);
+}
+
+// typesec ::= section_1(vec(functype))
+fn write_type_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.types.len());
+    for func_type in &module.types {
+        write_functype(wasm, func_type);
+    }
+}
+
+// importsec ::= section_2(vec(import))
+fn write_import_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.imports.len());
+    for import in &module.imports {
+        wasm.name(&import.module);
+        wasm.name(&import.name);
+        match &import.desc {
+            ImportDesc::Func(type_idx) => {
+                wasm.byte(0x00);
+                wasm.usize(*type_idx);
+            },
+            ImportDesc::Table(table_type) => {
+                wasm.byte(0x01);
+                write_tabletype(wasm, table_type);
+            },
+            ImportDesc::Mem(mem_type) => {
+                wasm.byte(0x02);
+                write_limits(wasm, mem_type);
+            },
+            ImportDesc::Global(global_type) => {
+                wasm.byte(0x03);
+                write_globaltype(wasm, global_type);
+            },
+        }
+    }
+}
+
+// funcsec ::= section_3(vec(typeidx))
+fn write_function_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.funcs.len());
+    for func in &module.funcs {
+        wasm.usize(func.f_type);
+    }
+}
+
+// tablesec ::= section_4(vec(table))
+fn write_table_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.tables.len());
+    for table in &module.tables {
+        write_tabletype(wasm, &table.table_type);
+    }
+}
+
+// memsec ::= section_5(vec(mem))
+fn write_memory_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.mems.len());
+    for mem in &module.mems {
+        write_limits(wasm, &mem.mem_type);
+    }
+}
+
+// globalsec ::= section_6(vec(global))
+fn write_global_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.globals.len());
+    for global in &module.globals {
+        write_globaltype(wasm, &global.global_type);
+        write_expr(wasm, &global.init);
+    }
+}
+
+// exportsec ::= section_7(vec(export))
+fn write_export_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.exports.len());
+    for export in &module.exports {
+        wasm.name(&export.name);
+        let (kind, idx) = match export.desc {
+            ExportDesc::Func(idx) => (0x00, idx),
+            ExportDesc::Table(idx) => (0x01, idx),
+            ExportDesc::Mem(idx) => (0x02, idx),
+            ExportDesc::Global(idx) => (0x03, idx),
+        };
+        wasm.byte(kind);
+        wasm.usize(idx);
+    }
+}
+
+// elemsec ::= section_9(vec(elem))
+// The most compact of the eight segment kinds is chosen: function indices rather than
+// expressions if every item is a `ref.func`, and no table index or type for table 0.
+fn write_element_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.elem.len());
+    for elem in &module.elem {
+        let func_indices = func_indices(elem);
+        let uses_exprs = func_indices.is_none();
+        let is_funcref = elem.elem_type == ReferenceType::FuncRef;
+
+        let mut kind = match &elem.mode {
+            ElemMode::Active { table: 0, .. } if is_funcref => 0b000,
+            ElemMode::Active { .. } => 0b010,
+            ElemMode::Passive => 0b001,
+            ElemMode::Declarative => 0b011,
+        };
+        if uses_exprs {
+            kind |= 0b100;
+        }
+        wasm.u32(kind);
+
+        if let ElemMode::Active { table, offset } = &elem.mode {
+            if kind & 0b010 != 0 {
+                wasm.usize(*table);
+            }
+            write_expr(wasm, offset);
+        }
+        if kind & 0b011 != 0 {
+            if uses_exprs {
+                write_reftype(wasm, elem.elem_type);
+            } else {
+                // elemkind ::= 0x00
+                wasm.byte(0x00);
+            }
+        }
+
+        match func_indices {
+            Some(indices) => {
+                wasm.usize(indices.len());
+                for idx in indices {
+                    wasm.usize(idx);
+                }
+            },
+            None => {
+                wasm.usize(elem.init.len());
+                for init in &elem.init {
+                    write_expr(wasm, init);
+                }
+            },
+        }
+    }
+}
+
+// Returns the function indices of a funcref segment whose items are all `ref.func`.
+fn func_indices(elem: &Elem) -> Option<Vec<usize>> {
+    if elem.elem_type != ReferenceType::FuncRef {
+        return None;
+    }
+    elem.init.iter()
+        .map(|init| match init.as_slice() {
+            [Instr::RefFunc(idx)] => Some(*idx),
+            _ => None,
+        })
+        .collect()
+}
+
+// codesec ::= section_10(vec(code))
+// code ::= size:u32 code:func
+// Runs of locals with the same type are grouped into a single entry.
+fn write_code_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.funcs.len());
+    for func in &module.funcs {
+        let mut code = Writer::new();
+        let mut groups: Vec<(u32, ValueType)> = vec![];
+        for local in &func.locals {
+            match groups.last_mut() {
+                Some((count, value_type)) if value_type == local => *count += 1,
+                _ => groups.push((1, *local)),
+            }
+        }
+        code.usize(groups.len());
+        for (count, value_type) in groups {
+            code.u32(count);
+            write_valuetype(&mut code, value_type);
+        }
+        write_expr(&mut code, &func.body);
+
+        wasm.usize(code.data.len());
+        wasm.bytes(&code.data);
+    }
+}
+
+// datasec ::= section_11(vec(data))
+fn write_data_section(wasm: &mut Writer, module: &Module) {
+    wasm.usize(module.data.len());
+    for data in &module.data {
+        match &data.mode {
+            DataMode::Active { memory: 0, offset } => {
+                wasm.u32(0);
+                write_expr(wasm, offset);
+            },
+            DataMode::Passive => wasm.u32(1),
+            DataMode::Active { memory, offset } => {
+                wasm.u32(2);
+                wasm.usize(*memory);
+                write_expr(wasm, offset);
+            },
+        }
+        wasm.usize(data.init.len());
+        wasm.bytes(&data.init);
+    }
+}
+
+// functype ::= 0x60 rt1:resulttype rt2:resulttype
+fn write_functype(wasm: &mut Writer, (params, results): &FuncType) {
+    wasm.byte(0x60);
+    write_resulttype(wasm, params);
+    write_resulttype(wasm, results);
+}
+
+// resulttype ::= t*:vec(valtype)
+fn write_resulttype(wasm: &mut Writer, types: &[ValueType]) {
+    wasm.usize(types.len());
+    for value_type in types {
+        write_valuetype(wasm, *value_type);
+    }
+}
+
+// tabletype ::= et:reftype lim:limits
+fn write_tabletype(wasm: &mut Writer, table_type: &TableType) {
+    write_reftype(wasm, table_type.elem_type);
+    write_limits(wasm, &table_type.limits);
+}
+
+// limits ::= 0x00 n:u32 | 0x01 n:u32 m:u32
+fn write_limits(wasm: &mut Writer, limits: &Limits) {
+    match limits.max {
+        None => {
+            wasm.byte(0x00);
+            wasm.u32(limits.min);
+        },
+        Some(max) => {
+            wasm.byte(0x01);
+            wasm.u32(limits.min);
+            wasm.u32(max);
+        },
+    }
+}
+
+// globaltype ::= t:valtype m:mut
+fn write_globaltype(wasm: &mut Writer, global_type: &GlobalType) {
+    write_valuetype(wasm, global_type.value_type);
+    wasm.byte(global_type.mutable as u8);
+}
+
+// expr ::= (in:instr)* 0x0B
+fn write_expr(wasm: &mut Writer, instrs: &[Instr]) {
+    write_instrs(wasm, instrs);
+    wasm.byte(0x0B);
+}
+
+fn write_instrs(wasm: &mut Writer, instrs: &[Instr]) {
+    for instr in instrs {
+        write_instr(wasm, instr);
+    }
+}
+
+fn write_instr(wasm: &mut Writer, instr: &Instr) {
+    match instr {
+        // Control instructions
+        Instr::Block(block_type, body) => {
+            wasm.byte(0x02);
+            write_blocktype(wasm, block_type);
+            write_expr(wasm, body);
+        },
+        Instr::Loop(block_type, body) => {
+            wasm.byte(0x03);
+            write_blocktype(wasm, block_type);
+            write_expr(wasm, body);
+        },
+        Instr::If(block_type, then, otherwise) => {
+            wasm.byte(0x04);
+            write_blocktype(wasm, block_type);
+            write_instrs(wasm, then);
+            if !otherwise.is_empty() {
+                wasm.byte(0x05);
+                write_instrs(wasm, otherwise);
+            }
+            wasm.byte(0x0B);
+        },
+        Instr::Br(label) => {
+            wasm.byte(0x0C);
+            wasm.usize(*label);
+        },
+        Instr::BrIf(label) => {
+            wasm.byte(0x0D);
+            wasm.usize(*label);
+        },
+        Instr::BrTable(labels, default) => {
+            wasm.byte(0x0E);
+            wasm.usize(labels.len());
+            for label in labels {
+                wasm.usize(*label);
+            }
+            wasm.usize(*default);
+        },
+        Instr::Call(idx) => {
+            wasm.byte(0x10);
+            wasm.usize(*idx);
+        },
+        Instr::CallIndirect(table, type_idx) => {
+            wasm.byte(0x11);
+            wasm.usize(*type_idx);
+            wasm.usize(*table);
+        },
+
+        // Reference instructions
+        Instr::RefNull(ref_type) => {
+            wasm.byte(0xD0);
+            write_reftype(wasm, *ref_type);
+        },
+        Instr::RefFunc(idx) => {
+            wasm.byte(0xD2);
+            wasm.usize(*idx);
+        },
+
+        // Parametric instructions
+        Instr::Select(None) => wasm.byte(0x1B),
+        Instr::Select(Some(types)) => {
+            wasm.byte(0x1C);
+            write_resulttype(wasm, types);
+        },
+
+        // Variable instructions
+        Instr::LocalGet(idx) => write_index_instr(wasm, 0x20, *idx),
+        Instr::LocalSet(idx) => write_index_instr(wasm, 0x21, *idx),
+        Instr::LocalTee(idx) => write_index_instr(wasm, 0x22, *idx),
+        Instr::GlobalGet(idx) => write_index_instr(wasm, 0x23, *idx),
+        Instr::GlobalSet(idx) => write_index_instr(wasm, 0x24, *idx),
+
+        // Table instructions
+        Instr::TableGet(idx) => write_index_instr(wasm, 0x25, *idx),
+        Instr::TableSet(idx) => write_index_instr(wasm, 0x26, *idx),
+        Instr::TableInit(table, elem) => {
+            write_prefixed(wasm, 0xFC, 12);
+            wasm.usize(*elem);
+            wasm.usize(*table);
+        },
+        Instr::ElemDrop(idx) => {
+            write_prefixed(wasm, 0xFC, 13);
+            wasm.usize(*idx);
+        },
+        Instr::TableCopy(dst, src) => {
+            write_prefixed(wasm, 0xFC, 14);
+            wasm.usize(*dst);
+            wasm.usize(*src);
+        },
+        Instr::TableGrow(idx) => {
+            write_prefixed(wasm, 0xFC, 15);
+            wasm.usize(*idx);
+        },
+        Instr::TableSize(idx) => {
+            write_prefixed(wasm, 0xFC, 16);
+            wasm.usize(*idx);
+        },
+        Instr::TableFill(idx) => {
+            write_prefixed(wasm, 0xFC, 17);
+            wasm.usize(*idx);
+        },
+
+        // Memory instructions, the trailing zero bytes are the reserved memory indices
+        Instr::MemorySize => wasm.bytes(&[0x3F, 0x00]),
+        Instr::MemoryGrow => wasm.bytes(&[0x40, 0x00]),
+        Instr::MemoryInit(idx) => {
+            write_prefixed(wasm, 0xFC, 8);
+            wasm.usize(*idx);
+            wasm.byte(0x00);
+        },
+        Instr::DataDrop(idx) => {
+            write_prefixed(wasm, 0xFC, 9);
+            wasm.usize(*idx);
+        },
+        Instr::MemoryCopy => {
+            write_prefixed(wasm, 0xFC, 10);
+            wasm.bytes(&[0x00, 0x00]);
+        },
+        Instr::MemoryFill => {
+            write_prefixed(wasm, 0xFC, 11);
+            wasm.byte(0x00);
+        },
+
+        // Numeric instructions
+        Instr::I32Const(value) => {
+            wasm.byte(0x41);
+            wasm.i32(*value);
+        },
+        Instr::I64Const(value) => {
+            wasm.byte(0x42);
+            wasm.i64(*value);
+        },
+        Instr::F32Const(bits) => {
+            wasm.byte(0x43);
+            wasm.dword(*bits);
+        },
+        Instr::F64Const(bits) => {
+            wasm.byte(0x44);
+            wasm.qword(*bits);
+        },
+
+        // Vector instructions
+        Instr::V128Const(value) => {
+            write_prefixed(wasm, 0xFD, 12);
+            wasm.bytes(&value.to_le_bytes());
+        },
+        Instr::I8x16Shuffle(lanes) => {
+            write_prefixed(wasm, 0xFD, 13);
+            wasm.bytes(lanes);
+        },
+
+        _ => write_table_instr(wasm, instr),
+    }
+}
+
+fn write_index_instr(wasm: &mut Writer, opcode: u8, idx: usize) {
+    wasm.byte(opcode);
+    wasm.usize(idx);
+}
+
+fn write_prefixed(wasm: &mut Writer, prefix: u8, opcode: u32) {
+    wasm.byte(prefix);
+    wasm.u32(opcode);
+}
+
+// Writes an opcode of the instruction tables of `ast::Instr`, where prefixed opcodes keep
+// the prefix in their top byte.
+fn write_opcode(wasm: &mut Writer, opcode: u32) {
+    if opcode > 0xFF {
+        write_prefixed(wasm, (opcode >> 24) as u8, opcode & 0xFF_FFFF);
+    } else {
+        wasm.byte(opcode as u8);
+    }
+}
+
+// Writes an instruction described by the tables of `ast::Instr` with its immediates.
+fn write_table_instr(wasm: &mut Writer, instr: &Instr) {
+    if let Some((opcode, _, _)) = instr.plain_info() {
+        write_opcode(wasm, opcode);
+    } else if let Some((opcode, _, _)) = instr.memory_info() {
+        write_opcode(wasm, opcode);
+        write_memarg(wasm, instr.memarg().unwrap());
+    } else if let Some((opcode, _, _)) = instr.memory_lane_info() {
+        write_opcode(wasm, opcode);
+        write_memarg(wasm, instr.memarg().unwrap());
+        wasm.byte(instr.lane().unwrap());
+    } else if let Some((opcode, _, _)) = instr.lane_info() {
+        write_opcode(wasm, opcode);
+        wasm.byte(instr.lane().unwrap());
+    } else {
+        unreachable!("{:?} is not in an instruction table", instr);
+    }
+}
+
+// memarg ::= a:u32 o:u32
+fn write_memarg(wasm: &mut Writer, memarg: &MemArg) {
+    wasm.u32(memarg.align);
+    wasm.u32(memarg.offset);
+}
+
+// blocktype ::= 0x40 | t:valtype | x:s33
+fn write_blocktype(wasm: &mut Writer, block_type: &BlockType) {
+    match block_type {
+        BlockType::Empty => wasm.byte(0x40),
+        BlockType::Value(value_type) => write_valuetype(wasm, *value_type),
+        BlockType::Type(idx) => wasm.signed(*idx as i64),
+    }
+}
+
+// reftype ::= 0x70 | 0x6F
+fn write_reftype(wasm: &mut Writer, ref_type: ReferenceType) {
+    match ref_type {
+        ReferenceType::FuncRef => wasm.byte(0x70),
+        ReferenceType::ExternRef => wasm.byte(0x6F),
+    }
+}
+
+fn write_valuetype(wasm: &mut Writer, value_type: ValueType) {
+    match value_type {
+        ValueType::NumberType(NumberType::I32) => wasm.byte(0x7F),
+        ValueType::NumberType(NumberType::I64) => wasm.byte(0x7E),
+        ValueType::NumberType(NumberType::F32) => wasm.byte(0x7D),
+        ValueType::NumberType(NumberType::F64) => wasm.byte(0x7C),
+        ValueType::VectorType(VectorType::V128) => wasm.byte(0x7B),
+        ValueType::ReferenceType(ref_type) => write_reftype(wasm, ref_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::runtime::loader;
+    use crate::validator;
+
+    const MODULE: &str = r#"(module
+        (type $pair (func (param i32 i32) (result i32)))
+        (import "env" "print" (func $print (param i32)))
+        (import "env" "memory" (memory 1 2))
+        (import "env" "base" (global $base i32))
+        (table $funcs 2 funcref)
+        (table $externs 1 10 externref)
+        (global $counter (mut i64) i64.const -624485)
+        (global f64 f64.const 0.5)
+        (elem (table $funcs) (offset i32.const 0) funcref (item ref.func $add) (item ref.func $loop))
+        (elem (table $funcs) (offset i32.const 1) funcref (item ref.null func))
+        (elem (table $externs) (offset i32.const 0) externref (item ref.null extern))
+        (elem $passive funcref (item ref.func $add))
+        (elem declare funcref (item ref.func $loop))
+        (data (offset global.get $base) "hello")
+        (data $bytes "ab")
+        (func $add (type $pair) local.get 0 local.get 1 i32.add)
+        (func $loop (param $n i32) (result i32) (local i64 i64 f32)
+            block $done (result i32)
+                local.get $n
+                loop $next (param i32) (result i32)
+                    local.get $n
+                    i32.eqz
+                    br_if $done
+                    i32.const 1
+                    i32.const 2
+                    i32.const 0
+                    call_indirect $funcs (type $pair)
+                    br_table $next $done 0
+                end
+            end)
+        (func (param i32) (result i64)
+            local.get 0
+            if (result i64)
+                i64.const 9223372036854775807
+            else
+                i32.const 0 i64.load offset=65536 align=4
+                i32.const 0 i32.load8_u
+                i64.extend_i32_u
+                i64.const 1 i32.const 0 select (result i64)
+                i64.add
+            end
+            i32.const 0 i32.const 1 i32.const 2 memory.init $bytes
+            data.drop $bytes
+            i32.const 0 i32.const 0 i32.const 1 table.init $funcs $passive
+            elem.drop $passive
+            i32.const 0 i32.const 0 i32.const 1 table.copy $funcs $funcs
+            i32.const 0 table.get $externs drop
+            memory.size memory.grow drop
+            i32.const 0 v128.load
+            v128.const i16x8 -1 2 -3 4 -5 6 -7 8
+            i8x16.shuffle 0 17 2 19 4 21 6 23 8 25 10 27 12 29 14 31
+            i32x4.extract_lane 3
+            i32.const 0 v128.const f32x4 1.5 -1.5 0 1 v128.load32_lane align=4 2
+            i32x4.trunc_sat_f32x4_s
+            v128.any_true i32.add
+            call $print)
+        (start $print_zero)
+        (func $print_zero i32.const 0 call $print)
+        (export "add" (func $add))
+        (export "memory" (memory 0))
+        (export "counter" (global $counter))
+        (export "funcs" (table $funcs)))"#;
+
+    #[test]
+    fn leb128_is_minimal() {
+        let cases: &[(i64, &[u8])] = &[
+            (0, &[0x00]),
+            (63, &[0x3F]),
+            (64, &[0xC0, 0x00]),
+            (-1, &[0x7F]),
+            (-64, &[0x40]),
+            (-65, &[0xBF, 0x7F]),
+            (-624485, &[0x9B, 0xF1, 0x59]),
+        ];
+        for (value, expected) in cases {
+            let mut wasm = Writer::new();
+            wasm.signed(*value);
+            assert_eq!(wasm.data, *expected, "signed {}", value);
+        }
+
+        let mut wasm = Writer::new();
+        wasm.u32(624485);
+        assert_eq!(wasm.data, [0xE5, 0x8E, 0x26]);
+    }
+
+    #[test]
+    fn round_trips_through_decoder() {
+        let module = parser::parse(MODULE).unwrap();
+        validator::validate(&module).unwrap();
+        let bytes = encode(&module);
+        let decoded = loader::decode(&bytes).unwrap();
+        assert_eq!(decoded, module);
+        assert_eq!(encode(&decoded), bytes);
+    }
+}
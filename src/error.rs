@@ -1,14 +1,49 @@
-use core::fmt;
+use std::fmt::{self, Debug, Display, Formatter};
 
-#[derive(Debug)]
-pub struct Error {
+use crate::token::Span;
 
+// Errors of `lexer::Lexer`, with the span of the offending source text.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Error {
+    UnterminatedString { span: Span },
+    InvalidEscape { span: Span },
+    MalformedNumber { span: Span },
+    UnexpectedCharacter { span: Span },
+    UnterminatedComment { span: Span },
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error")
+impl Error {
+    fn message(&self) -> &str {
+        match self {
+            Self::UnterminatedString { .. } => "unterminated string",
+            Self::InvalidEscape { .. } => "invalid escape",
+            Self::MalformedNumber { .. } => "malformed number",
+            Self::UnexpectedCharacter { .. } => "unexpected character",
+            Self::UnterminatedComment { .. } => "unterminated block comment",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match *self {
+            Self::UnterminatedString { span }
+            | Self::InvalidEscape { span }
+            | Self::MalformedNumber { span }
+            | Self::UnexpectedCharacter { span }
+            | Self::UnterminatedComment { span } => span,
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
     }
 }
 
-impl std::error::Error for Error { }
\ No newline at end of file
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message(), self.span().start, self.span().end)
+    }
+}
@@ -2,15 +2,17 @@ use crate::error::Error;
 
 use std::{
     iter,
-    str::{self, CharIndices},
+    str,
 };
 
 use crate::token::{
+    decode_string,
     Token,
     TokenKind,
     Span,
     FloatKind,
     IntegerKind,
+    StringLiteral,
 };
 
 #[derive(Debug)]
@@ -28,22 +30,75 @@ impl<'a> Lexer<'a> {
     }
 
     fn token(&mut self) -> Result<Option<Token<'a>>, Error> {
-        match self.iter.peek() {
+        match self.iter.peek().cloned() {
             Some((_, c)) => {
-                let token = match c {
-                    '(' => self.left_paren(),
-                    ')' => self.right_paren(),
-                    '"' => self.string_literal(),
-                    ' ' | '\t' | '\n' | '\r' => self.whitespace(),
+                let token = match (c, self.peek_second()) {
+                    ('(', Some(';')) => self.block_comment()?,
+                    (';', Some(';')) => self.line_comment(),
+                    ('(', _) => self.left_paren(),
+                    (')', _) => self.right_paren(),
+                    ('"', _) => self.string_literal()?,
+                    (' ' | '\t' | '\n' | '\r', _) => self.whitespace(),
                     /*_ => Some(Token::new(TokenKind::Eof, Span { start: *pos as u32, end: (*pos + 1) as u32 })),*/
-                    _ => self.reserved(),
+                    _ => Some(self.reserved()?),
                 };
-                return Ok(token);
+                Ok(token)
             },
-            None => return Ok(None),
+            None => Ok(None),
         }
     }
-    
+
+    fn peek_second(&self) -> Option<char> {
+        let mut iter = self.iter.clone();
+        iter.next();
+        iter.next().map(|(_, c)| c)
+    }
+
+    // linecomment ::= ';;' linechar* ('\n' | eof)
+    // The newline is not part of the comment.
+    fn line_comment(&mut self) -> Option<Token<'a>> {
+        let (start, _) = self.iter.peek().cloned().unwrap();
+        let mut end = self.source.len();
+        while let Some((pos, c)) = self.iter.peek().cloned() {
+            if c == '\n' {
+                end = pos;
+                break;
+            }
+            self.iter.next();
+        }
+        Some(Token::new(TokenKind::LineComment(&self.source[start..end]),
+            Span { start: start as u32, end: end as u32 }))
+    }
+
+    // blockcomment ::= '(;' blockchar* ';)'
+    // Block comments nest, so every `(;` inside needs a matching `;)`.
+    fn block_comment(&mut self) -> Result<Option<Token<'a>>, Error> {
+        let (start, _) = self.iter.next().unwrap();
+        self.iter.next();
+        let mut depth = 1;
+        loop {
+            let (pos, c) = self.iter.next().ok_or(Error::UnterminatedComment {
+                span: Span { start: start as u32, end: start as u32 + 2 },
+            })?;
+            match (c, self.iter.peek().map(|(_, c)| *c)) {
+                ('(', Some(';')) => {
+                    self.iter.next();
+                    depth += 1;
+                },
+                (';', Some(')')) => {
+                    self.iter.next();
+                    depth -= 1;
+                    if depth == 0 {
+                        let end = pos + 2;
+                        return Ok(Some(Token::new(TokenKind::BlockComment(&self.source[start..end]),
+                            Span { start: start as u32, end: end as u32 })));
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
     fn left_paren(&mut self) -> Option<Token<'a>> {
         if let Some((pos, _)) = self.iter.next() {
             return Some(Token::new(TokenKind::LeftParen,
@@ -60,30 +115,33 @@ impl<'a> Lexer<'a> {
         None
     }
 
-    fn string_literal(&mut self) -> Option<Token<'a>> {
-        let (start, _) = self.iter.peek().cloned().unwrap();
-        self.iter.next();
-        let mut end = start;
-        // search for the closing quote
-        while let Some((_, c)) = self.iter.peek().cloned() {
-            if c != '"' {
-                self.iter.next();
-            } else {
-                break;
+    // The closing quote is found by skipping over escaped characters, the escapes themselves
+    // are checked once the whole literal is known.
+    fn string_literal(&mut self) -> Result<Option<Token<'a>>, Error> {
+        let (start, _) = self.iter.next().unwrap();
+        loop {
+            let unterminated = Error::UnterminatedString {
+                span: Span { start: start as u32, end: self.source.len() as u32 },
+            };
+            match self.iter.next().ok_or(unterminated)? {
+                (_, '\\') => {
+                    self.iter.next();
+                },
+                (pos, '"') => {
+                    let end = pos + 1;
+                    let src = &self.source[start..end];
+                    decode_string(&src[1..src.len() - 1], start + 1)?;
+                    return Ok(Some(Token::new(TokenKind::String(StringLiteral { src }),
+                        Span { start: start as u32, end: end as u32 })));
+                },
+                _ => {},
             }
         }
-        // consume the closing quote
-        self.iter.next();
-        if let Some((pos, _)) = self.iter.peek().cloned() {
-            end = pos;
-        }
-        Some(Token::new(TokenKind::String(&self.source[start..end]), 
-            Span { start: start as u32, end: end as u32 }))
     }
 
     fn whitespace(&mut self) -> Option<Token<'a>> {
         let (start, _) = self.iter.peek().cloned().unwrap();
-        let mut end = start;
+        let mut end = self.source.len();
         while let Some((pos, c)) = self.iter.peek().cloned() {
             if Self::is_whitespace(c) {
                 self.iter.next();
@@ -96,10 +154,13 @@ impl<'a> Lexer<'a> {
             Span { start: start as u32, end: end as u32 }))
     }
 
-    fn reserved(&mut self) -> Option<Token<'a>> {
-        let (start, _) = self.iter.peek().cloned().unwrap();
-        self.iter.next();
-        let mut end = start;
+    fn reserved(&mut self) -> Result<Token<'a>, Error> {
+        let (start, first) = self.iter.next().unwrap();
+        if !Self::is_legal_char(first) {
+            let span = Span { start: start as u32, end: (start + first.len_utf8()) as u32 };
+            return Err(Error::UnexpectedCharacter { span });
+        }
+        let mut end = self.source.len();
         while let Some((pos, c)) = self.iter.peek().cloned() {
             if Self::is_legal_char(c) {
                 self.iter.next();
@@ -110,24 +171,35 @@ impl<'a> Lexer<'a> {
         }
 
         let reserved = &self.source[start..end];
-
-        if let Some(number) = self.number(reserved) {
-            Some(Token::new(number, Span { start: start as u32, end: end as u32 }))
-        } else if let Some(keyword) = self.keyword(reserved) {
-            Some(keyword)
-        } else if let Some(identifier) = self.identifier(reserved) {
-            Some(identifier)
+        let span = Span { start: start as u32, end: end as u32 };
+
+        let kind = if let Some(number) = self.number(reserved) {
+            number
+        } else if reserved.trim_start_matches(['+', '-']).starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(Error::MalformedNumber { span });
+        } else if Self::is_keyword(reserved) {
+            TokenKind::Keyword(reserved)
+        } else if Self::is_identifier(reserved) {
+            TokenKind::Identifier(reserved)
         } else {
-            Some(Token::new(TokenKind::Reserved(&self.source[start..end]),
-                Span { start: start as u32, end: end as u32 }))
-        }
+            TokenKind::Reserved(reserved)
+        };
+        Ok(Token::new(kind, span))
     }
 
+    // num ::= digit ('_'? digit)*
+    // hexnum ::= hexdigit ('_'? hexdigit)*
+    // float ::= num '.'? | num '.' frac | num '.'? ('E' | 'e') sign? num | num '.' frac ('E' | 'e') sign? num
+    // hexfloat ::= '0x' hexnum '.'? | '0x' hexnum '.' hexfrac | '0x' hexnum '.'? ('P' | 'p') sign? num
+    //            | '0x' hexnum '.' hexfrac ('P' | 'p') sign? num
+    //
+    // Literals without a fraction or exponent are integers, which the parser also accepts
+    // where a float is expected.
     fn number(&mut self, src: &'a str) -> Option<TokenKind<'a>> {
-        let (negative, num) = if src.starts_with('-') {
-            (true, &src[1..])
-        } else if src.starts_with('+') {
-            (false, &src[1..])
+        let (negative, num) = if let Some(num) = src.strip_prefix('-') {
+            (true, num)
+        } else if let Some(num) = src.strip_prefix('+') {
+            (false, num)
         } else {
             (false, src)
         };
@@ -136,144 +208,93 @@ impl<'a> Lexer<'a> {
             return Some(TokenKind::Float(FloatKind::Inf { src, negative }));
         } else if num == "nan" {
             return Some(TokenKind::Float(FloatKind::Nan { src, negative, value: None }));
-        } else if num.starts_with("nan:0x") {
-            let value = u64::from_str_radix(&num[6..], 16).ok();
-            return Some(TokenKind::Float(FloatKind::Nan { src, negative, value }));
+        } else if let Some(payload) = num.strip_prefix("nan:0x") {
+            // Payloads too large for any float saturate and are rejected by the parser
+            let (digits, "") = Self::digits(payload, char::is_ascii_hexdigit)? else {
+                return None;
+            };
+            let value = u64::from_str_radix(&digits.replace('_', ""), 16).unwrap_or(u64::MAX);
+            return Some(TokenKind::Float(FloatKind::Nan { src, negative, value: Some(value) }));
         }
 
-        // Are we dealing with a hex or decimal number?
-        let(mut iterator, is_hex, test_valid) = if num.starts_with("0x") {
-            (num[2..].char_indices(), true, char::is_ascii_hexdigit as fn(&char) -> bool)
-        } else {
-            (num.char_indices(), false, char::is_ascii_digit as fn(&char) -> bool)
+        let (hex, num, is_digit) = match num.strip_prefix("0x") {
+            Some(hex) => (true, hex, char::is_ascii_hexdigit as fn(&char) -> bool),
+            None => (false, num, char::is_ascii_digit as fn(&char) -> bool),
         };
 
-        // Parse the integral part of the number
-        let integral = Self::consume_digits(&mut iterator, test_valid).unwrap();
-
-        let it = iterator.clone().next();
-        if it.is_none() { // If there are no more characters, we have a valid integer
-            if is_hex {
-                return Some(TokenKind::Integer(IntegerKind::Hex { src: integral, negative }));
-            } else {
-                return Some(TokenKind::Integer(IntegerKind::Decimal { src: integral, negative }));
-            }
-        } else if let Some((_,'.')) = it { // If there is a decimal point, we have a float
-            iterator.next();
-        } else { // Otherwise, we have an invalid number
+        let (integral, rest) = Self::digits(num, is_digit)?;
+        let (dot, fractional, rest) = match rest.strip_prefix('.') {
+            Some(rest) => match Self::digits(rest, is_digit) {
+                Some((fractional, rest)) => (true, fractional, rest),
+                None => (true, "", rest),
+            },
+            None => (false, "", rest),
+        };
+        let exponent_marks: &[char] = if hex { &['p', 'P'] } else { &['e', 'E'] };
+        let (exponent, rest) = match rest.strip_prefix(exponent_marks) {
+            Some(rest) => {
+                let digits = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+                let (magnitude, after) = Self::digits(digits, char::is_ascii_digit)?;
+                let sign = rest.len() - digits.len();
+                (&rest[..sign + magnitude.len()], after)
+            },
+            None => ("", rest),
+        };
+        if !rest.is_empty() {
             return None;
         }
 
-        // Parse the fractional part of the number
-        let fractional= Self::consume_digits(&mut iterator, test_valid).unwrap();
-
-        // If there is an exponent, we have a float
-
-
-//        if iterator.clone().next() == Some('e') || iterator.clone().next() == Some('E')
-//        || iterator.clone().next() == Some('p') || iterator.clone().next() == Some('P') {
-//            let mut exponent: String = String::new();
-//            if iterator.clone().next() == Some('-') || iterator.clone().next() == Some('+') {
-//                exponent.push(iterator.next().unwrap());
-//            }
-//            while let Some(c) = iterator.next() {
-//                if test_valid(&c) {
-//                    exponent.push(c);
-//                } else {
-//                    break;
-//                }
-//            }
-//        }
-
-        if iterator.clone().next().is_none() {
-            return Some(TokenKind::Float(FloatKind::Val { src: num, negative, integral, fractional, exponent: "" }));
-        } else {
-            //None
-
-            return Some(TokenKind::Float(FloatKind::Val { src: num, negative, integral, fractional, exponent: "" }));
-        }
-    }
-
-    fn keyword(&mut self, src: &'a str) -> Option<Token<'a>> {
-        let (start, _) = self.iter.peek().cloned().unwrap();
-        let mut end = start;
-        while let Some((pos, c)) = self.iter.peek().cloned() {
-            if Self::is_identifier(c) {
-                self.iter.next();
-            } else {
-                end = pos;
-                break;
-            }
-        }
-        Some(Token::new(TokenKind::Keyword(&self.source[start..end]), 
-            Span { start: start as u32, end: end as u32 }))
-    }
-
-    fn identifier(&mut self, src: &'a str) -> Option<Token<'a>> {
-        let (start, _) = self.iter.peek().cloned().unwrap();
-        self.iter.next();
-        let mut end = start;
-        while let Some((pos, c)) = self.iter.peek().cloned() {
-            if Self::is_identifier(c) {
-                self.iter.next();
-            } else {
-                end = pos;
-                break;
-            }
-        }
-        Some(Token::new(TokenKind::Identifier(&self.source[start..end]),
-            Span { start: start as u32, end: end as u32 }))
+        Some(match (dot || !exponent.is_empty(), hex) {
+            (false, false) => TokenKind::Integer(IntegerKind::Decimal { src: integral, negative }),
+            (false, true) => TokenKind::Integer(IntegerKind::Hex { src: integral, negative }),
+            (true, _) => TokenKind::Float(FloatKind::Val { src, negative, hex, integral, fractional, exponent }),
+        })
     }
 
-    fn consume_digits(it: &mut CharIndices<'a>, good: fn(&char) -> bool) -> Option<&'a str> {
-        let src = it.as_str();
-        let (start, _) = it.clone().next()?;
-        let mut end = start;
-        while let Some((pos, c)) = it.clone().next() {
-            if good(&c) {
+    // Splits `src` after its leading digits, which may be separated by single underscores.
+    // Returns `None` if `src` does not start with a digit.
+    fn digits(src: &'a str, is_digit: fn(&char) -> bool) -> Option<(&'a str, &'a str)> {
+        let mut end = 0;
+        let mut after_digit = false;
+        for (pos, c) in src.char_indices() {
+            if is_digit(&c) {
+                after_digit = true;
                 end = pos + 1;
-                it.next();
+            } else if c == '_' && after_digit {
+                after_digit = false;
             } else {
                 break;
             }
         }
-        Some(&src[0..end-start])
-    }
-
-    fn is_alpha(c: char) -> bool {
-        match c {
-            'a'..='z' | 'A'..='Z' => true,
-            _ => false,
-        }
-    }
-
-    fn is_numeric(c: char) -> bool {
-        match c {
-            '0'..='9' => true,
-            _ => false,
+        if end == 0 {
+            return None;
         }
+        Some((&src[..end], &src[end..]))
     }
 
-    fn is_alphanumeric(c: char) -> bool {
-        Self::is_alpha(c) || Self::is_numeric(c)
+    // keyword ::= ('a' ... 'z') idchar*
+    fn is_keyword(src: &str) -> bool {
+        let mut chars = src.chars();
+        matches!(chars.next(), Some('a'..='z')) && chars.all(Self::is_legal_char)
     }
 
-    fn is_identifier(c: char) -> bool {
-        Self::is_alphanumeric(c) || c == '_'
+    // id ::= '$' idchar+
+    pub(crate) fn is_identifier(src: &str) -> bool {
+        match src.strip_prefix('$') {
+            Some(name) => !name.is_empty() && name.chars().all(Self::is_legal_char),
+            None => false,
+        }
     }
 
     fn is_whitespace(c: char) -> bool {
-        match c {
-            ' ' | '\t' | '\n' | '\r' => true,
-            _ => false,
-        }
+        matches!(c, ' ' | '\t' | '\n' | '\r')
     }
 
+    // idchar ::= '0' ... '9' | 'A' ... 'Z' | 'a' ... 'z' | '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+'
+    //          | '-' | '.' | '/' | ':' | '<' | '=' | '>' | '?' | '@' | '\\' | '^' | '_' | '`' | '|' | '~'
     fn is_legal_char(c: char) -> bool {
-        match c {
-            'a'..='z' | 'A'..='Z' | '0'..='9' | '!' | '@' | '#' | '$' | '%' | '^' | '&' | '*' | '-' | '+' | '=' | '<' | '>' | '?' | '/' | '\\' | '|' | ':' | ';' | ',' | '.' | '[' | ']' | '{' | '}' | '_' | '"' => true,
-            _ => false,
-        }
+        matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+' | '-' | '.' | '/'
+            | ':' | '<' | '=' | '>' | '?' | '@' | '\\' | '^' | '_' | '`' | '|' | '~')
     }
 }
 
@@ -282,8 +303,124 @@ impl<'a> Iterator for Lexer<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         // transpose() Switches Option<Result<T, E>> to Result<Option<T>, E>
-        let token = self.token().transpose();
-        //self.iter.next();
-        token
+        self.token().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind<'_>> {
+        Lexer::new(source).map(|token| token.unwrap().kind).collect()
+    }
+
+    #[test]
+    fn lexes_comments_as_trivia() {
+        assert_eq!(kinds("nop;; a (; b\n)"), [
+            TokenKind::Keyword("nop"),
+            TokenKind::LineComment(";; a (; b"),
+            TokenKind::Whitespace,
+            TokenKind::RightParen,
+        ]);
+        assert_eq!(kinds("((; a (; b ;) ;; c ;)nop"), [
+            TokenKind::LeftParen,
+            TokenKind::BlockComment("(; a (; b ;) ;; c ;)"),
+            TokenKind::Keyword("nop"),
+        ]);
+        assert!(Lexer::new("(; a (; b ;)").any(|token| token.is_err()));
+    }
+
+    #[test]
+    fn decodes_string_escapes() {
+        let string = |source| match Lexer::new(source).next() {
+            Some(Ok(Token { kind: TokenKind::String(string), .. })) => Some(string.bytes()),
+            _ => None,
+        };
+        assert_eq!(string(r#""a\"b\\\t\n\r\'""#).unwrap(), b"a\"b\\\t\n\r'");
+        assert_eq!(string(r#""\00\ff\u{41}\u{1_F600}""#).unwrap(), b"\x00\xffA\xf0\x9f\x98\x80");
+        assert_eq!(string(r#""\q""#), None);
+        assert_eq!(string(r#""\u{D800}""#), None);
+        assert_eq!(string("\"a\u{1}\""), None);
+        assert_eq!(string(r#""abc"#), None);
+    }
+
+    #[test]
+    fn lexes_numbers() {
+        assert_eq!(kinds("1_000 0xFF_ff"), [
+            TokenKind::Integer(IntegerKind::Decimal { src: "1_000", negative: false }),
+            TokenKind::Whitespace,
+            TokenKind::Integer(IntegerKind::Hex { src: "FF_ff", negative: false }),
+        ]);
+        assert_eq!(kinds("-0x1.8p-3"), [TokenKind::Float(FloatKind::Val {
+            src: "-0x1.8p-3", negative: true, hex: true, integral: "1", fractional: "8", exponent: "-3",
+        })]);
+        assert_eq!(kinds("1.e+5"), [TokenKind::Float(FloatKind::Val {
+            src: "1.e+5", negative: false, hex: false, integral: "1", fractional: "", exponent: "+5",
+        })]);
+        assert_eq!(kinds("nan:0x1_0"), [TokenKind::Float(FloatKind::Nan { src: "nan:0x1_0", negative: false, value: Some(16) })]);
+    }
+
+    #[test]
+    fn converts_numbers() {
+        let f32 = |source| match kinds(source)[..] {
+            [TokenKind::Float(float)] => float.to_f32_bits(),
+            [TokenKind::Integer(integer)] => integer.to_f32_bits(),
+            _ => panic!("{} is not a number", source),
+        };
+        let f64 = |source| match kinds(source)[..] {
+            [TokenKind::Float(float)] => float.to_f64_bits(),
+            [TokenKind::Integer(integer)] => integer.to_f64_bits(),
+            _ => panic!("{} is not a number", source),
+        };
+        let i32 = |source| match kinds(source)[..] {
+            [TokenKind::Integer(integer)] => integer.to_i32(),
+            _ => panic!("{} is not an integer", source),
+        };
+
+        assert_eq!(i32("0xFFFF_FFFF"), Some(-1));
+        assert_eq!(i32("-0x8000_0000"), Some(i32::MIN));
+        assert_eq!(i32("-2147483649"), None);
+        assert_eq!(i32("4294967296"), None);
+
+        assert_eq!(f32("0x1p-149"), Some(1));
+        assert_eq!(f32("0x1p-150"), Some(0));
+        assert_eq!(f32("0x1.000001p-150"), Some(1));
+        assert_eq!(f32("0x1.fffffep127"), Some(0x7F7F_FFFF));
+        assert_eq!(f32("0x1.fffffefffffffp127"), Some(0x7F7F_FFFF));
+        assert_eq!(f32("0x1.ffffffp127"), None);
+        assert_eq!(f32("0x1.00000100000000000000000001p0"), Some(0x3F80_0001));
+        assert_eq!(f32("-0x0.0p0"), Some(0x8000_0000));
+        assert_eq!(f32("16777217"), Some(16777216f32.to_bits()));
+        assert_eq!(f32("1e39"), None);
+        assert_eq!(f32("nan:0x80_0000"), None);
+        assert_eq!(f32("-nan:0x1"), Some(0xFF80_0001));
+
+        assert_eq!(f64("0x1p-1074"), Some(1));
+        assert_eq!(f64("0x1.8p1"), Some(3f64.to_bits()));
+        assert_eq!(f64("0x1_0000_0000_0000_0000_0"), Some(2f64.powi(68).to_bits()));
+        assert_eq!(f64("0x1p-99999999999999999999"), Some(0));
+        assert_eq!(f64("0x1p99999999999999999999"), None);
+        assert_eq!(f64("1_000.5e-3"), Some(1.0005f64.to_bits()));
+        assert_eq!(f64("1e400"), None);
+        assert_eq!(f64("inf"), Some(f64::INFINITY.to_bits()));
+    }
+
+    #[test]
+    fn reports_errors_with_spans() {
+        fn error(source: &str) -> (Error, &str) {
+            let error = Lexer::new(source).find_map(Result::err).unwrap();
+            (error, &source[error.span().start as usize..error.span().end as usize])
+        }
+
+        assert!(matches!(error(r#"(data "abc"#), (Error::UnterminatedString { .. }, "\"abc")));
+        assert!(matches!(error(r#""a\qb""#), (Error::InvalidEscape { .. }, r"\q")));
+        assert!(matches!(error(r#""\u{d800}""#), (Error::InvalidEscape { .. }, r"\u{d800}")));
+        assert!(matches!(error("\"a b\x7F\""), (Error::UnexpectedCharacter { .. }, "\x7F")));
+        assert!(matches!(error("i32.const 12x"), (Error::MalformedNumber { .. }, "12x")));
+        assert!(matches!(error("1__0"), (Error::MalformedNumber { .. }, "1__0")));
+        assert!(matches!(error("-0x"), (Error::MalformedNumber { .. }, "-0x")));
+        assert!(matches!(error("(func [)"), (Error::UnexpectedCharacter { .. }, "[")));
+        assert!(matches!(error("(; (; ;)"), (Error::UnterminatedComment { .. }, "(;")));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,12 @@
+pub mod ast;
+pub mod encoder;
+pub mod error;
+pub mod lexer;
+pub mod parser;
+pub mod parsing;
+pub mod printer;
+pub mod runtime;
+pub mod source_map;
+pub mod token;
+pub mod validator;
+pub mod wast;
@@ -2,60 +2,230 @@ use std::{
     error::Error, fs, io::{stdout, Write}, str
 };
 
-mod lexer;
-mod token;
-mod error;
+use mag::ast::{Limits, Module, NumberType, ValueType};
+use mag::encoder;
+use mag::parser;
+use mag::parsing::{Grammars, Lexer};
+use mag::printer;
+use mag::validator;
+use mag::wast;
+use mag::runtime::{loader, Linker, Store, Trap, Value};
+use mag::source_map::SourceMap;
 
 fn main() {
     let args: Vec<_> = std::env::args().collect();
-    if args.len() > 2 {
-        println!("Usage: {} [script]", args[0]);
+    let result = if args.len() > 2 && args[1] == "wat2wasm" {
+        wat2wasm(&args[2..])
+    } else if args.len() > 2 && args[1] == "wasm2wat" {
+        wasm2wat(&args[2..])
+    } else if args.len() == 3 && args[1] == "lex" {
+        lex(&args[2])
+    } else if args.len() == 3 && args[1] == "wast" {
+        wast(&args[2])
     } else if args.len() == 2 {
-        run_file(args[1].as_str());
+        run_file(args[1].as_str())
+    } else if args.len() > 2 {
+        invoke_file(args[1].as_str(), args[2].as_str(), &args[3..])
     } else {
-        run_prompt();
+        run_prompt()
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
 }
 
 fn run_file(path: &str) -> Result<(), Box<dyn Error>> {
     let byte_content = fs::read(path)?;
+    if path.ends_with(".wasm") {
+        println!("{:#?}", loader::decode(&byte_content)?);
+        return Ok(());
+    }
     let content = str::from_utf8(&byte_content)?;
-    run(content)?;
+    run(path, content)?;
+    Ok(())
+}
+
+// `mag wat2wasm in.wat [-o out.wasm]` validates a text module and writes its binary encoding,
+// by default next to the input.
+fn wat2wasm(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (input, output) = match args {
+        [input] => (input.as_str(), None),
+        [input, flag, output] if flag == "-o" => (input.as_str(), Some(output.clone())),
+        _ => return Err("usage: mag wat2wasm <in.wat> [-o <out.wasm>]".into()),
+    };
+    let output = output.unwrap_or_else(|| {
+        let stem = input.strip_suffix(".wat").unwrap_or(input);
+        format!("{}.wasm", stem)
+    });
+
+    let content = fs::read_to_string(input)?;
+    let module = load_text(input, &content)?;
+    fs::write(output, encoder::encode(&module))?;
     Ok(())
 }
 
+// `mag wasm2wat in.wasm [-o out.wat] [--fold]` prints a binary module in the text format, to
+// stdout unless an output file is given.
+fn wasm2wat(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let folded = args.iter().any(|arg| arg == "--fold");
+    let args: Vec<_> = args.iter().filter(|arg| *arg != "--fold").collect();
+    let (input, output) = match args.as_slice() {
+        [input] => (input.as_str(), None),
+        [input, flag, output] if *flag == "-o" => (input.as_str(), Some(output.as_str())),
+        _ => return Err("usage: mag wasm2wat <in.wasm> [-o <out.wat>] [--fold]".into()),
+    };
+
+    let module = loader::decode(&fs::read(input)?)?;
+    let text = printer::print(&module, folded);
+    match output {
+        Some(output) => fs::write(output, text)?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+// `mag lex file` prints the tokens of a file, lexed with the grammar for its extension.
+fn lex(path: &str) -> Result<(), Box<dyn Error>> {
+    let grammars = Grammars::new();
+    let grammar = grammars.for_path(path).ok_or_else(|| format!("no grammar for `{}`", path))?;
+    let mut map = SourceMap::new();
+    let file = map.add(path, fs::read_to_string(path)?);
+    let source = map.source(file);
+
+    for token in Lexer::with_grammar(source, grammar.clone()) {
+        let token = token.map_err(|e| map.render(file, e.span(), &e.to_string()))?;
+        if token.kind.is_trivia() {
+            continue;
+        }
+        let location = map.location(file, token.span.start);
+        println!("{}:{}\t{}\t{}", location.line, location.column, token.kind, token.text(source));
+    }
+    Ok(())
+}
+
+// `mag wast file.wast` runs a spec test script and reports the outcome of every command.
+fn wast(path: &str) -> Result<(), Box<dyn Error>> {
+    let mut map = SourceMap::new();
+    let file = map.add(path, fs::read_to_string(path)?);
+    let outcomes = wast::run(map.source(file)).map_err(|e| map.render(file, e.span, &e.message))?;
+
+    let mut failed = 0;
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(()) => {
+                let location = map.location(file, outcome.span.start);
+                println!("{}:{}:{}: {} passed", path, location.line, location.column, outcome.directive);
+            },
+            Err(message) => {
+                failed += 1;
+                let message = format!("{} failed: {}", outcome.directive, message);
+                println!("{}", map.render(file, outcome.span, &message));
+            },
+        }
+    }
+    println!("{} passed, {} failed", outcomes.len() - failed, failed);
+    if failed > 0 {
+        return Err(format!("{} of {} commands failed", failed, outcomes.len()).into());
+    }
+    Ok(())
+}
+
+// Instantiates the module in `path` and calls its export `name` with `args`, e.g.
+// `mag test2.wat add 2 3`.
+fn invoke_file(path: &str, name: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let byte_content = fs::read(path)?;
+    let module = if path.ends_with(".wasm") {
+        let module = loader::decode(&byte_content)?;
+        validator::validate(&module)?;
+        module
+    } else {
+        load_text(path, str::from_utf8(&byte_content)?)?
+    };
+
+    let store = Store::new();
+    let instance = env(&store).instantiate(&module)?;
+    let (params, _) = instance.func_type(name)?;
+    if params.len() != args.len() {
+        return Err(format!("`{}` expects {} arguments, got {}", name, params.len(), args.len()).into());
+    }
+    let args = params.iter().zip(args)
+        .map(|(param, arg)| parse_arg(*param, arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let results = instance.invoke(name, &args)?;
+    if !results.is_empty() {
+        let results: Vec<_> = results.iter().map(Value::to_string).collect();
+        println!("{}", results.join(" "));
+    }
+    Ok(())
+}
+
+// The host environment modules run by the CLI import from:
+// - `env.buffer`, a memory of one page,
+// - `env.start_string`, an immutable i32 global holding 0, where strings start in memory,
+// - `env.print_string(len)`, which prints the first `len` bytes of the memory of the caller.
+fn env(store: &Store) -> Linker {
+    let mut linker = Linker::new(store);
+    linker.memory("env", "buffer", &Limits { min: 1, max: None });
+    linker.global("env", "start_string", false, Value::I32(0));
+    let i32 = ValueType::NumberType(NumberType::I32);
+    linker.func("env", "print_string", (vec![i32], vec![]), |caller, args| {
+        let len = match args {
+            [Value::I32(len)] => *len as u32 as usize,
+            _ => unreachable!("arguments match the parameters"),
+        };
+        let memory = caller.memory().ok_or(Trap::OutOfBoundsMemoryAccess)?;
+        println!("{}", memory.read_string(0, len)?);
+        Ok(vec![])
+    });
+    linker
+}
+
+fn parse_arg(param: ValueType, arg: &str) -> Result<Value, Box<dyn Error>> {
+    Ok(match param {
+        ValueType::NumberType(NumberType::I32) => Value::I32(arg.parse()?),
+        ValueType::NumberType(NumberType::I64) => Value::I64(arg.parse()?),
+        ValueType::NumberType(NumberType::F32) => Value::F32(arg.parse()?),
+        ValueType::NumberType(NumberType::F64) => Value::F64(arg.parse()?),
+        _ => return Err(format!("cannot pass `{}` as {:?}", arg, param).into()),
+    })
+}
+
 fn run_prompt() -> Result<(), Box<dyn Error>> {
     loop {
         print!("> ");
         stdout().flush()?;
         let mut input = String::new();
         std::io::stdin().read_line(&mut input)?;
-        if(input.is_empty()) {
+        if input.is_empty() {
             return Ok(()); // EOF
         }
 
-        match run(input.as_str()) {
+        match run("<stdin>", input.as_str()) {
             Ok(_) => {},
             Err(e) => eprintln!("{}", e),
         }
     }
 }
 
-fn run(source: &str) -> Result<(), Box<dyn Error>> {
-    let lexer = lexer::Lexer::new(source);
-    for token in lexer {
-        match token {
-            Ok(token) => println!("{:?}", token),
-            Err(e) => eprintln!("{}", e),
-        }
-    }
+fn run(path: &str, source: &str) -> Result<(), Box<dyn Error>> {
+    println!("{:#?}", load_text(path, source)?);
     Ok(())
 }
 
-fn error(line: usize, message: &str) {
-    report(line, "", message);
+// Parses and validates a text module, rendering errors against its source.
+fn load_text(path: &str, source: &str) -> Result<Module, Box<dyn Error>> {
+    let mut map = SourceMap::new();
+    let file = map.add(path, source);
+    let (module, spans) = parser::parse_with_spans(source)
+        .map_err(|e| map.render(file, e.span, &e.message))?;
+    if let Err(e) = validator::validate(&module) {
+        let span = e.func.and_then(|func| spans.func(func, e.offset));
+        return Err(match span {
+            Some(span) => map.render(file, span, &e.message),
+            None => e.to_string(),
+        }.into());
+    }
+    Ok(module)
 }
-
-fn report(line: usize, location: &str, message: &str) {
-    eprintln!("[line {}] Error {}: {}", line, location, message);
-}
\ No newline at end of file
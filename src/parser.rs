@@ -0,0 +1,1618 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::{
+    BlockType,
+    Custom,
+    Data,
+    DataMode,
+    Elem,
+    ElemMode,
+    Export,
+    ExportDesc,
+    Func,
+    FuncType,
+    Global,
+    GlobalType,
+    Import,
+    ImportDesc,
+    Instr,
+    Limits,
+    Mem,
+    MemArg,
+    Module,
+    NameMap,
+    Names,
+    NumberType,
+    ReferenceType,
+    Start,
+    Table,
+    TableType,
+    ValueType,
+    VectorType,
+    MAX_NESTING_DEPTH,
+};
+use crate::encoder;
+use crate::lexer::Lexer;
+use crate::runtime::memory::PAGE_SIZE;
+use crate::token::{IntegerKind, Span, Token, TokenKind};
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+pub fn parse(source: &str) -> Result<Module, ParseError> {
+    Parser::new(source)?.module()
+}
+
+// Like `parse`, but also returns where the functions of the module are in the source.
+pub fn parse_with_spans(source: &str) -> Result<(Module, Spans), ParseError> {
+    let mut parser = Parser::new(source)?;
+    let module = parser.module()?;
+    Ok((module, parser.spans))
+}
+
+fn num_imported_funcs(module: &Module) -> usize {
+    num_imports(module, |desc| matches!(desc, ImportDesc::Func(_)))
+}
+
+fn num_imports(module: &Module, is_kind: fn(&ImportDesc) -> bool) -> usize {
+    module.imports.iter().filter(|import| is_kind(&import.desc)).count()
+}
+
+// Imports share the index spaces of the definitions, which number the imports first.
+fn add_import(module: &mut Module, import: Import, span: Span) -> Result<(), ParseError> {
+    if !module.funcs.is_empty() || !module.tables.is_empty() || !module.mems.is_empty() || !module.globals.is_empty() {
+        return Err(ParseError::new("import after definition", span));
+    }
+    module.imports.push(import);
+    Ok(())
+}
+
+// Source positions of the functions of a parsed module, for diagnostics of later passes.
+#[derive(Debug, Default)]
+pub struct Spans {
+    num_imported_funcs: usize,
+    // The `func` keyword of every defined function and the instructions of its body, in the
+    // order in which `validator::ValidationError::offset` counts them
+    funcs: Vec<(Span, Vec<Span>)>,
+}
+
+impl Spans {
+    // Returns the span of the function `func` of the function index space, or of its
+    // instruction at the 1-based `offset`.
+    pub fn func(&self, func: usize, offset: Option<usize>) -> Option<Span> {
+        let (span, instrs) = self.funcs.get(func.checked_sub(self.num_imported_funcs)?)?;
+        match offset {
+            Some(offset) => instrs.get(offset.checked_sub(1)?).copied(),
+            None => Some(*span),
+        }
+    }
+}
+
+// The identifiers of one index space, or of the locals or labels in scope. An identifier may
+// be defined only once per namespace, and resolves to the index it was defined for.
+struct Namespace<'a> {
+    kind: &'static str,
+    ids: HashMap<&'a str, usize>,
+}
+
+impl<'a> Namespace<'a> {
+    fn new(kind: &'static str) -> Self {
+        Self { kind, ids: HashMap::new() }
+    }
+
+    fn define(&mut self, id: Option<(&'a str, Span)>, idx: usize) -> Result<(), ParseError> {
+        match id {
+            Some((id, span)) if self.ids.insert(id, idx).is_some() => {
+                Err(ParseError::new(format!("duplicate {} `{}`", self.kind, id), span))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve(&self, id: &str, span: Span) -> Result<usize, ParseError> {
+        self.ids.get(id).copied()
+            .ok_or_else(|| ParseError::new(format!("unknown {} `{}`", self.kind, id), span))
+    }
+
+    fn clear(&mut self) {
+        self.ids.clear();
+    }
+
+    // The identifiers without their `$`, as they are stored in the `name` section.
+    fn names(&self) -> NameMap {
+        self.ids.iter().map(|(id, idx)| (*idx, id[1..].to_string())).collect()
+    }
+}
+
+// A block or folded instruction whose contents are being parsed. Those that start a new
+// instruction sequence keep the enclosing one in `outer`.
+enum Open<'a> {
+    // 'block' | 'loop' | 'if' ... 'end', `then` holds the then branch of an if once its
+    // `else` is seen
+    Block { keyword: &'a str, label: Option<&'a str>, block_type: BlockType, outer: Vec<Instr>, then: Option<Vec<Instr>> },
+    // '(' ('block' | 'loop') ... ')'
+    FoldedBlock { keyword: &'a str, block_type: BlockType, outer: Vec<Instr> },
+    // '(' plaininstr foldedinstr* ')', the instruction follows its operands
+    Folded { instr: Instr, span: Span },
+    // The conditions of '(' 'if' ... ')' up to '(' 'then'
+    Condition { label: Option<&'a str>, block_type: BlockType, span: Span },
+    // '(' 'then' instr* ')' and '(' 'else' instr* ')'
+    Branch { block_type: BlockType, outer: Vec<Instr>, then: Option<Vec<Instr>> },
+}
+
+// Recursive descent parser over the token stream produced by `lexer::Lexer`.
+// Whitespace and comments are dropped up front so every rule can look ahead by index.
+pub struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    eof: Span,
+    // Identifiers of the module level index spaces, collected before the fields are parsed
+    // so that fields can refer to definitions that come later in the module.
+    type_ids: Namespace<'a>,
+    func_ids: Namespace<'a>,
+    table_ids: Namespace<'a>,
+    mem_ids: Namespace<'a>,
+    global_ids: Namespace<'a>,
+    elem_ids: Namespace<'a>,
+    data_ids: Namespace<'a>,
+    // Explicitly defined types, also collected up front, followed by the types implied by
+    // type uses without a `(type x)`. The latter are appended to the module in order.
+    types: Vec<FuncType>,
+    implicit_types: Vec<FuncType>,
+    // Identifiers of the function currently being parsed
+    local_ids: Namespace<'a>,
+    labels: Vec<Option<&'a str>>,
+    // How deeply the instruction being parsed nests in blocks and folded instructions,
+    // which `enter` keeps within `MAX_NESTING_DEPTH`
+    depth: usize,
+    // Identifiers of the locals of every function parsed so far, by function index
+    local_names: BTreeMap<usize, NameMap>,
+    instr_spans: Vec<Span>,
+    spans: Spans,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Result<Self, ParseError> {
+        let eof = Span { start: source.len() as u32, end: source.len() as u32 };
+        let mut tokens = vec![];
+        for token in Lexer::new(source) {
+            let token = token.map_err(|e| ParseError::new(e.to_string(), e.span()))?;
+            if !token.kind.is_trivia() {
+                tokens.push(token);
+            }
+        }
+        Ok(Self {
+            tokens,
+            pos: 0,
+            eof,
+            type_ids: Namespace::new("type"),
+            func_ids: Namespace::new("function"),
+            table_ids: Namespace::new("table"),
+            mem_ids: Namespace::new("memory"),
+            global_ids: Namespace::new("global"),
+            elem_ids: Namespace::new("elem"),
+            data_ids: Namespace::new("data"),
+            types: vec![],
+            implicit_types: vec![],
+            local_ids: Namespace::new("local"),
+            labels: vec![],
+            depth: 0,
+            local_names: BTreeMap::new(),
+            instr_spans: vec![],
+            spans: Spans::default(),
+        })
+    }
+
+    // module ::= '(' 'module' id? field* ')'
+    pub fn module(&mut self) -> Result<Module, ParseError> {
+        let mut module = Module::default();
+
+        self.expect_left_paren()?;
+        self.expect_keyword("module")?;
+        let id = self.id();
+        self.collect_fields()?;
+
+        while !self.peek_right_paren() {
+            let (keyword, span) = self.peek_field()?;
+            match keyword {
+                "type" => {
+                    // Already parsed by `collect_fields`
+                    self.pos += 2;
+                    self.skip_to_right_paren()?;
+                },
+                "import" => {
+                    let import = self.import_field()?;
+                    add_import(&mut module, import, span)?;
+                },
+                "func" => self.func_field(&mut module)?,
+                "table" => self.table_field(&mut module)?,
+                "memory" => self.memory_field(&mut module)?,
+                "global" => self.global_field(&mut module)?,
+                "export" => module.exports.push(self.export_field()?),
+                "start" => {
+                    if module.start.is_some() {
+                        return Err(ParseError::new("multiple start functions", span));
+                    }
+                    module.start = Some(self.start_field()?);
+                },
+                "elem" => module.elem.push(self.elem_field()?),
+                "data" => module.data.push(self.data_field()?),
+                _ => return Err(ParseError::new(format!("unexpected module field `{}`", keyword), span)),
+            }
+        }
+        self.expect_right_paren()?;
+
+        if let Some(token) = self.tokens.get(self.pos) {
+            return Err(ParseError::new("unexpected token after module", token.span));
+        }
+
+        module.types = self.types.drain(..).chain(self.implicit_types.drain(..)).collect();
+        module.data_count = module.uses_data_indices().then_some(module.data.len() as u32);
+        self.spans.num_imported_funcs = num_imported_funcs(&module);
+
+        let names = Names {
+            module: id.map(|id| id[1..].to_string()),
+            funcs: self.func_ids.names(),
+            locals: std::mem::take(&mut self.local_names),
+            types: self.type_ids.names(),
+            tables: self.table_ids.names(),
+            mems: self.mem_ids.names(),
+            globals: self.global_ids.names(),
+            elems: self.elem_ids.names(),
+            datas: self.data_ids.names(),
+        };
+        if names != Names::default() {
+            module.customs.push(Custom { name: "name".to_string(), data: encoder::encode_names(&names) });
+        }
+        Ok(module)
+    }
+
+    // Walks the module fields once to assign indices to `$id`s and to parse the type
+    // definitions, which type uses anywhere in the module may refer to.
+    fn collect_fields(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        let (mut num_funcs, mut num_tables, mut num_mems) = (0, 0, 0);
+        let (mut num_globals, mut num_elems, mut num_datas) = (0, 0, 0);
+        while !self.peek_right_paren() {
+            let (keyword, _) = self.peek_field()?;
+            if keyword == "type" {
+                let field = self.pos;
+                self.pos += 2;
+                let id = self.id_with_span();
+                self.type_ids.define(id, self.types.len())?;
+                self.pos = field;
+                let func_type = self.type_field()?;
+                self.types.push(func_type);
+                continue;
+            }
+
+            self.pos += 2;
+            // The kind and id of an import are given by its description
+            let (kind, id, depth) = if keyword == "import" {
+                self.name()?;
+                self.name()?;
+                let (kind, _) = self.peek_field()?;
+                self.pos += 2;
+                (kind, self.id_with_span(), 2)
+            } else {
+                (keyword, self.id_with_span(), 1)
+            };
+
+            let (ids, count) = match kind {
+                "func" => (&mut self.func_ids, &mut num_funcs),
+                "table" => (&mut self.table_ids, &mut num_tables),
+                "memory" => (&mut self.mem_ids, &mut num_mems),
+                "global" => (&mut self.global_ids, &mut num_globals),
+                "elem" => (&mut self.elem_ids, &mut num_elems),
+                "data" => (&mut self.data_ids, &mut num_datas),
+                _ => (&mut Namespace::new(""), &mut 0),
+            };
+            ids.define(id, *count)?;
+            *count += 1;
+
+            // The inline data of a memory and the inline elements of a table are segments
+            // of their own
+            if depth == 1 && kind == "memory" && self.peek_child_field("data") {
+                num_datas += 1;
+            }
+            if depth == 1 && kind == "table" && self.peek_child_field("elem") {
+                num_elems += 1;
+            }
+
+            for _ in 0..depth {
+                self.skip_to_right_paren()?;
+            }
+        }
+        self.pos = start;
+        Ok(())
+    }
+
+    // type ::= '(' 'type' id? '(' 'func' param* result* ')' ')'
+    fn type_field(&mut self) -> Result<FuncType, ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("type")?;
+        self.id();
+        self.expect_left_paren()?;
+        self.expect_keyword("func")?;
+        let (params, _) = self.params()?;
+        let results = self.results()?;
+        self.expect_right_paren()?;
+        self.expect_right_paren()?;
+        Ok((params, results))
+    }
+
+    // func ::= '(' 'func' id? typeuse local* instr* ')'
+    //        | '(' 'func' id? inlineexport* inlineimport typeuse ')'
+    //        | '(' 'func' id? inlineexport* typeuse local* instr* ')'
+    fn func_field(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("func")?;
+        let span = self.tokens[self.pos - 1].span;
+        self.id();
+        let idx = num_imported_funcs(module) + module.funcs.len();
+        for name in self.inline_exports()? {
+            module.exports.push(Export { name, desc: ExportDesc::Func(idx) });
+        }
+        if let Some((import_module, name)) = self.inline_import()? {
+            let (f_type, _, _) = self.type_use()?;
+            self.expect_right_paren()?;
+            return add_import(module, Import { module: import_module, name, desc: ImportDesc::Func(f_type) }, span);
+        }
+        self.instr_spans.clear();
+
+        let (f_type, (params, _), param_ids) = self.type_use()?;
+        self.local_ids = param_ids;
+        self.labels.clear();
+
+        // local ::= '(' 'local' id valtype ')' | '(' 'local' valtype* ')'
+        let mut locals = vec![];
+        while self.peek_keyword_field("local") {
+            self.expect_left_paren()?;
+            self.expect_keyword("local")?;
+            if let Some(id) = self.id_with_span() {
+                self.local_ids.define(Some(id), params.len() + locals.len())?;
+                locals.push(self.value_type()?);
+            } else {
+                while !self.peek_right_paren() {
+                    locals.push(self.value_type()?);
+                }
+            }
+            self.expect_right_paren()?;
+        }
+
+        let body = self.instrs()?;
+        self.expect_right_paren()?;
+        self.spans.funcs.push((span, std::mem::take(&mut self.instr_spans)));
+        module.funcs.push(Func { f_type, locals, body });
+
+        let local_names = self.local_ids.names();
+        if !local_names.is_empty() {
+            self.local_names.insert(idx, local_names);
+        }
+        Ok(())
+    }
+
+    // import ::= '(' 'import' name name importdesc ')'
+    // importdesc ::= '(' 'func' id? typeuse ')' | '(' 'table' id? tabletype ')'
+    //              | '(' 'memory' id? memtype ')' | '(' 'global' id? globaltype ')'
+    fn import_field(&mut self) -> Result<Import, ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("import")?;
+        let module = self.name()?;
+        let name = self.name()?;
+        self.expect_left_paren()?;
+        let (keyword, span) = self.keyword()?;
+        self.id();
+        let desc = match keyword {
+            "func" => ImportDesc::Func(self.type_use()?.0),
+            "table" => ImportDesc::Table(self.table_type()?),
+            "memory" => ImportDesc::Mem(self.limits()?),
+            "global" => ImportDesc::Global(self.global_type()?),
+            _ => return Err(ParseError::new(format!("unexpected import kind `{}`", keyword), span)),
+        };
+        self.expect_right_paren()?;
+        self.expect_right_paren()?;
+        Ok(Import { module, name, desc })
+    }
+
+    // table ::= '(' 'table' id? tabletype ')'
+    //         | '(' 'table' id? inlineexport* inlineimport tabletype ')'
+    //         | '(' 'table' id? inlineexport* reftype '(' 'elem' (elemexpr* | funcidx*) ')' ')'
+    //
+    // Inline elements make the table exactly as large as the segment placed at its start.
+    fn table_field(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("table")?;
+        let span = self.tokens[self.pos - 1].span;
+        self.id();
+        let idx = num_imports(module, |desc| matches!(desc, ImportDesc::Table(_))) + module.tables.len();
+        for name in self.inline_exports()? {
+            module.exports.push(Export { name, desc: ExportDesc::Table(idx) });
+        }
+        if let Some((import_module, name)) = self.inline_import()? {
+            let table_type = self.table_type()?;
+            self.expect_right_paren()?;
+            return add_import(module, Import { module: import_module, name, desc: ImportDesc::Table(table_type) }, span);
+        }
+
+        let table_type = if self.peek_index() {
+            self.table_type()?
+        } else {
+            let elem_type = self.ref_type()?;
+            self.expect_left_paren()?;
+            self.expect_keyword("elem")?;
+            let init = if self.peek_index() { self.func_refs()? } else { self.elem_exprs()? };
+            self.expect_right_paren()?;
+            let size = init.len() as u32;
+            let mode = ElemMode::Active { table: idx, offset: vec![Instr::I32Const(0)] };
+            module.elem.push(Elem { elem_type, init, mode });
+            TableType { limits: Limits { min: size, max: Some(size) }, elem_type }
+        };
+        self.expect_right_paren()?;
+        module.tables.push(Table { table_type });
+        Ok(())
+    }
+
+    // mem ::= '(' 'memory' id? memtype ')'
+    //       | '(' 'memory' id? inlineexport* inlineimport memtype ')'
+    //       | '(' 'memory' id? inlineexport* '(' 'data' datastring ')' ')'
+    //
+    // Inline data makes the memory exactly as large as needed to hold it at address 0.
+    fn memory_field(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("memory")?;
+        let span = self.tokens[self.pos - 1].span;
+        self.id();
+        let idx = num_imports(module, |desc| matches!(desc, ImportDesc::Mem(_))) + module.mems.len();
+        for name in self.inline_exports()? {
+            module.exports.push(Export { name, desc: ExportDesc::Mem(idx) });
+        }
+        if let Some((import_module, name)) = self.inline_import()? {
+            let limits = self.limits()?;
+            self.expect_right_paren()?;
+            return add_import(module, Import { module: import_module, name, desc: ImportDesc::Mem(limits) }, span);
+        }
+
+        let mem_type = if self.peek_keyword_field("data") {
+            self.expect_left_paren()?;
+            self.expect_keyword("data")?;
+            let init = self.data_string()?;
+            self.expect_right_paren()?;
+            let pages = init.len().div_ceil(PAGE_SIZE) as u32;
+            module.data.push(Data { init, mode: DataMode::Active { memory: idx, offset: vec![Instr::I32Const(0)] } });
+            Limits { min: pages, max: Some(pages) }
+        } else {
+            self.limits()?
+        };
+        self.expect_right_paren()?;
+        module.mems.push(Mem { mem_type });
+        Ok(())
+    }
+
+    // global ::= '(' 'global' id? globaltype expr ')'
+    //          | '(' 'global' id? inlineexport* inlineimport globaltype ')'
+    //          | '(' 'global' id? inlineexport* globaltype expr ')'
+    fn global_field(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("global")?;
+        let span = self.tokens[self.pos - 1].span;
+        self.id();
+        let idx = num_imports(module, |desc| matches!(desc, ImportDesc::Global(_))) + module.globals.len();
+        for name in self.inline_exports()? {
+            module.exports.push(Export { name, desc: ExportDesc::Global(idx) });
+        }
+        if let Some((import_module, name)) = self.inline_import()? {
+            let global_type = self.global_type()?;
+            self.expect_right_paren()?;
+            return add_import(module, Import { module: import_module, name, desc: ImportDesc::Global(global_type) }, span);
+        }
+
+        let global_type = self.global_type()?;
+        let init = self.const_expr()?;
+        self.expect_right_paren()?;
+        module.globals.push(Global { global_type, init });
+        Ok(())
+    }
+
+    // inlineexport ::= '(' 'export' name ')'
+    fn inline_exports(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut names = vec![];
+        while self.peek_keyword_field("export") {
+            self.expect_left_paren()?;
+            self.expect_keyword("export")?;
+            names.push(self.name()?);
+            self.expect_right_paren()?;
+        }
+        Ok(names)
+    }
+
+    // inlineimport ::= '(' 'import' name name ')'
+    fn inline_import(&mut self) -> Result<Option<(String, String)>, ParseError> {
+        if !self.peek_keyword_field("import") {
+            return Ok(None);
+        }
+        self.expect_left_paren()?;
+        self.expect_keyword("import")?;
+        let module = self.name()?;
+        let name = self.name()?;
+        self.expect_right_paren()?;
+        Ok(Some((module, name)))
+    }
+
+    // start ::= '(' 'start' funcidx ')'
+    fn start_field(&mut self) -> Result<Start, ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("start")?;
+        let func = self.func_index()?;
+        self.expect_right_paren()?;
+        Ok(Start { func })
+    }
+
+    // elem ::= '(' 'elem' id? elemlist ')'
+    //        | '(' 'elem' id? ('(' 'table' tableidx ')')? offset elemlist ')'
+    //        | '(' 'elem' id? 'declare' elemlist ')'
+    //        | '(' 'elem' id? offset funcidx* ')'
+    // elemlist ::= reftype elemexpr* | 'func' funcidx*
+    fn elem_field(&mut self) -> Result<Elem, ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("elem")?;
+        self.id();
+
+        let mut table_omitted = false;
+        let mode = if matches!(self.tokens.get(self.pos).map(|t| t.kind), Some(TokenKind::Keyword("declare"))) {
+            self.pos += 1;
+            ElemMode::Declarative
+        } else if self.peek_field().is_ok() {
+            // Element lists never start with a paren, so this is the table or the offset
+            let table = if self.peek_keyword_field("table") {
+                self.expect_left_paren()?;
+                self.expect_keyword("table")?;
+                let table = self.index(|p, id, span| p.table_ids.resolve(id, span))?;
+                self.expect_right_paren()?;
+                table
+            } else {
+                table_omitted = true;
+                0
+            };
+            ElemMode::Active { table, offset: self.offset()? }
+        } else {
+            ElemMode::Passive
+        };
+
+        let (elem_type, init) = if table_omitted && (self.peek_index() || self.peek_right_paren()) {
+            (ReferenceType::FuncRef, self.func_refs()?)
+        } else if matches!(self.tokens.get(self.pos).map(|t| t.kind), Some(TokenKind::Keyword("func"))) {
+            self.pos += 1;
+            (ReferenceType::FuncRef, self.func_refs()?)
+        } else {
+            (self.ref_type()?, self.elem_exprs()?)
+        };
+        self.expect_right_paren()?;
+        Ok(Elem { elem_type, init, mode })
+    }
+
+    // funcidx*, each of which stands for the element expression `ref.func funcidx`
+    fn func_refs(&mut self) -> Result<Vec<Vec<Instr>>, ParseError> {
+        let mut init = vec![];
+        while self.peek_index() {
+            init.push(vec![Instr::RefFunc(self.func_index()?)]);
+        }
+        Ok(init)
+    }
+
+    // elemexpr ::= '(' 'item' expr ')' | '(' instr ')'
+    fn elem_exprs(&mut self) -> Result<Vec<Vec<Instr>>, ParseError> {
+        let mut init = vec![];
+        while !self.peek_right_paren() {
+            if self.peek_keyword_field("item") {
+                self.expect_left_paren()?;
+                self.expect_keyword("item")?;
+                init.push(self.const_expr()?);
+                self.expect_right_paren()?;
+            } else {
+                init.push(self.folded_const_expr()?);
+            }
+        }
+        Ok(init)
+    }
+
+    // data ::= '(' 'data' id? datastring ')'
+    //        | '(' 'data' id? ('(' 'memory' memidx ')')? '(' 'offset' expr ')' datastring ')'
+    fn data_field(&mut self) -> Result<Data, ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("data")?;
+        self.id();
+
+        // Data strings never start with a paren, so this is the memory or the offset
+        let mode = if self.peek_field().is_ok() {
+            let memory = if self.peek_keyword_field("memory") {
+                self.expect_left_paren()?;
+                self.expect_keyword("memory")?;
+                let memory = self.index(|p, id, span| p.mem_ids.resolve(id, span))?;
+                self.expect_right_paren()?;
+                memory
+            } else {
+                0
+            };
+            DataMode::Active { memory, offset: self.offset()? }
+        } else {
+            DataMode::Passive
+        };
+
+        let init = self.data_string()?;
+        self.expect_right_paren()?;
+        Ok(Data { init, mode })
+    }
+
+    // datastring ::= string*
+    fn data_string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let mut init = vec![];
+        while !self.peek_right_paren() {
+            init.extend(self.string()?);
+        }
+        Ok(init)
+    }
+
+    // offset ::= '(' 'offset' expr ')' | '(' instr ')'
+    fn offset(&mut self) -> Result<Vec<Instr>, ParseError> {
+        if !self.peek_keyword_field("offset") {
+            return self.folded_const_expr();
+        }
+        self.expect_left_paren()?;
+        self.expect_keyword("offset")?;
+        let offset = self.const_expr()?;
+        self.expect_right_paren()?;
+        Ok(offset)
+    }
+
+    // Constant expressions live outside of any function, so no locals or labels are in scope.
+    fn const_expr(&mut self) -> Result<Vec<Instr>, ParseError> {
+        self.local_ids.clear();
+        self.labels.clear();
+        self.instrs()
+    }
+
+    // A constant expression written as a single folded instruction.
+    fn folded_const_expr(&mut self) -> Result<Vec<Instr>, ParseError> {
+        self.local_ids.clear();
+        self.labels.clear();
+        self.folded_instr()
+    }
+
+    // export ::= '(' 'export' name exportdesc ')'
+    // exportdesc ::= '(' ('func' | 'table' | 'memory' | 'global') idx ')'
+    fn export_field(&mut self) -> Result<Export, ParseError> {
+        self.expect_left_paren()?;
+        self.expect_keyword("export")?;
+        let name = self.name()?;
+        self.expect_left_paren()?;
+        let (keyword, span) = self.keyword()?;
+        let desc = match keyword {
+            "func" => ExportDesc::Func(self.func_index()?),
+            "table" => ExportDesc::Table(self.index(|p, id, span| p.table_ids.resolve(id, span))?),
+            "memory" => ExportDesc::Mem(self.index(|p, id, span| p.mem_ids.resolve(id, span))?),
+            "global" => ExportDesc::Global(self.global_index()?),
+            _ => return Err(ParseError::new(format!("unexpected export kind `{}`", keyword), span)),
+        };
+        self.expect_right_paren()?;
+        self.expect_right_paren()?;
+        Ok(Export { name, desc })
+    }
+
+    // typeuse ::= '(' 'type' typeidx ')' param* result* | param* result*
+    //
+    // Returns the type index, the function type and the parameter identifiers. Without a
+    // `(type x)` the index of the first type matching the parameters and results is used.
+    fn type_use(&mut self) -> Result<(usize, FuncType, Namespace<'a>), ParseError> {
+        let explicit = if self.peek_keyword_field("type") {
+            self.expect_left_paren()?;
+            self.expect_keyword("type")?;
+            let span = self.tokens.get(self.pos).map_or(self.eof, |t| t.span);
+            let idx = self.index(|p, id, span| p.type_ids.resolve(id, span))?;
+            self.expect_right_paren()?;
+            Some((idx, span))
+        } else {
+            None
+        };
+
+        let (params, ids) = self.params()?;
+        let results = self.results()?;
+
+        match explicit {
+            // A numeric index past the defined types is well-formed, the validator reports it
+            Some((idx, span)) => match self.types.iter().chain(&self.implicit_types).nth(idx).cloned() {
+                Some(func_type) => {
+                    if (!params.is_empty() || !results.is_empty()) && (&params, &results) != (&func_type.0, &func_type.1) {
+                        return Err(ParseError::new("inline function type does not match type definition", span));
+                    }
+                    Ok((idx, func_type, ids))
+                },
+                None => Ok((idx, (params, results), ids)),
+            },
+            None => {
+                let func_type = (params, results);
+                Ok((self.implicit_type(&func_type), func_type, ids))
+            },
+        }
+    }
+
+    // Returns the index of the first type equal to `func_type`, adding it if there is none.
+    fn implicit_type(&mut self, func_type: &FuncType) -> usize {
+        match self.types.iter().chain(&self.implicit_types).position(|t| t == func_type) {
+            Some(idx) => idx,
+            None => {
+                self.implicit_types.push(func_type.clone());
+                self.types.len() + self.implicit_types.len() - 1
+            },
+        }
+    }
+
+    // param ::= '(' 'param' id valtype ')' | '(' 'param' valtype* ')'
+    fn params(&mut self) -> Result<(Vec<ValueType>, Namespace<'a>), ParseError> {
+        let mut params = vec![];
+        let mut ids = Namespace::new("local");
+        while self.peek_keyword_field("param") {
+            self.expect_left_paren()?;
+            self.expect_keyword("param")?;
+            if let Some(id) = self.id_with_span() {
+                ids.define(Some(id), params.len())?;
+                params.push(self.value_type()?);
+            } else {
+                while !self.peek_right_paren() {
+                    params.push(self.value_type()?);
+                }
+            }
+            self.expect_right_paren()?;
+        }
+        Ok((params, ids))
+    }
+
+    // result ::= '(' 'result' valtype* ')'
+    fn results(&mut self) -> Result<Vec<ValueType>, ParseError> {
+        let mut results = vec![];
+        while self.peek_keyword_field("result") {
+            self.expect_left_paren()?;
+            self.expect_keyword("result")?;
+            while !self.peek_right_paren() {
+                results.push(self.value_type()?);
+            }
+            self.expect_right_paren()?;
+        }
+        Ok(results)
+    }
+
+    // instr*, terminated by `end`, `else` or a closing paren
+    fn instrs(&mut self) -> Result<Vec<Instr>, ParseError> {
+        self.instr_seq(false)
+    }
+
+    // foldedinstr ::= '(' plaininstr foldedinstr* ')'
+    //               | '(' 'block' label blocktype instr* ')'
+    //               | '(' 'loop' label blocktype instr* ')'
+    //               | '(' 'if' label blocktype foldedinstr* '(' 'then' instr* ')' ('(' 'else' instr* ')')? ')'
+    //
+    // Unfolds the instruction, the operands come before the instruction that consumes them.
+    // Their spans are recorded in the same order.
+    fn folded_instr(&mut self) -> Result<Vec<Instr>, ParseError> {
+        self.instr_seq(true)
+    }
+
+    // Parses an instruction sequence, or a single folded instruction if `folded`. Blocks and
+    // folded instructions are kept on the `open` stack while their contents are parsed, so
+    // nesting does not recurse.
+    fn instr_seq(&mut self, folded: bool) -> Result<Vec<Instr>, ParseError> {
+        let mut instrs = vec![];
+        let mut open = vec![];
+        if folded {
+            self.open_folded(&mut open, &mut instrs)?;
+        }
+        loop {
+            let kind = self.tokens.get(self.pos).map(|t| t.kind);
+            match (open.last_mut(), kind) {
+                (None, _) if folded => return Ok(instrs),
+                (None, Some(TokenKind::Keyword("end" | "else") | TokenKind::RightParen) | None) => return Ok(instrs),
+
+                // The operands of a folded instruction are folded instructions
+                (Some(Open::Folded { instr, span }), Some(TokenKind::RightParen)) => {
+                    let (instr, span) = (std::mem::replace(instr, Instr::Nop), *span);
+                    self.pos += 1;
+                    self.depth -= 1;
+                    open.pop();
+                    self.instr_spans.push(span);
+                    instrs.push(instr);
+                },
+                (Some(Open::Folded { .. }), _) => self.open_folded(&mut open, &mut instrs)?,
+                // The condition is evaluated outside of the block
+                (Some(Open::Condition { label, block_type, span }), _) => {
+                    if !self.peek_keyword_field("then") && !self.peek_right_paren() {
+                        self.open_folded(&mut open, &mut instrs)?;
+                        continue;
+                    }
+                    let (label, block_type) = (*label, *block_type);
+                    self.instr_spans.push(*span);
+                    self.labels.push(label);
+                    self.expect_left_paren()?;
+                    self.expect_keyword("then")?;
+                    open.pop();
+                    open.push(Open::Branch { block_type, outer: std::mem::take(&mut instrs), then: None });
+                },
+
+                (_, Some(TokenKind::LeftParen)) => self.open_folded(&mut open, &mut instrs)?,
+                (_, Some(TokenKind::Keyword("block" | "loop" | "if"))) => {
+                    let (keyword, span) = self.keyword()?;
+                    self.enter(span)?;
+                    self.instr_spans.push(span);
+                    let label = self.id();
+                    let block_type = self.block_type()?;
+                    self.labels.push(label);
+                    open.push(Open::Block { keyword, label, block_type, outer: std::mem::take(&mut instrs), then: None });
+                },
+
+                (Some(Open::Block { keyword: "if", label, then: then @ None, .. }), Some(TokenKind::Keyword("else"))) => {
+                    self.pos += 1;
+                    self.end_label(*label)?;
+                    *then = Some(std::mem::take(&mut instrs));
+                },
+                (Some(Open::Block { label, .. }), Some(TokenKind::Keyword("end"))) => {
+                    self.pos += 1;
+                    self.end_label(*label)?;
+                    let Some(Open::Block { keyword, block_type, outer, then, .. }) = open.pop() else { unreachable!() };
+                    let body = std::mem::replace(&mut instrs, outer);
+                    instrs.push(match (keyword, then) {
+                        ("block", _) => Instr::Block(block_type, body),
+                        ("loop", _) => Instr::Loop(block_type, body),
+                        (_, Some(then)) => Instr::If(block_type, then, body),
+                        (_, None) => Instr::If(block_type, body, vec![]),
+                    });
+                    self.labels.pop();
+                    self.depth -= 1;
+                },
+                (Some(Open::Block { .. }), Some(TokenKind::Keyword("else") | TokenKind::RightParen) | None) => {
+                    self.expect_keyword("end")?;
+                },
+
+                (Some(Open::FoldedBlock { .. }), Some(TokenKind::RightParen)) => {
+                    self.pos += 1;
+                    let Some(Open::FoldedBlock { keyword, block_type, outer }) = open.pop() else { unreachable!() };
+                    let body = std::mem::replace(&mut instrs, outer);
+                    if keyword == "block" {
+                        instrs.push(Instr::Block(block_type, body));
+                    } else {
+                        instrs.push(Instr::Loop(block_type, body));
+                    }
+                    self.labels.pop();
+                    self.depth -= 1;
+                },
+                (Some(Open::Branch { then, .. }), Some(TokenKind::RightParen)) => {
+                    self.pos += 1;
+                    if then.is_none() {
+                        *then = Some(std::mem::take(&mut instrs));
+                        if self.peek_keyword_field("else") {
+                            self.expect_left_paren()?;
+                            self.expect_keyword("else")?;
+                            continue;
+                        }
+                    }
+                    let Some(Open::Branch { block_type, outer, then: Some(then) }) = open.pop() else { unreachable!() };
+                    let otherwise = std::mem::replace(&mut instrs, outer);
+                    instrs.push(Instr::If(block_type, then, otherwise));
+                    self.labels.pop();
+                    self.depth -= 1;
+                    self.expect_right_paren()?;
+                },
+                (Some(Open::FoldedBlock { .. } | Open::Branch { .. }), Some(TokenKind::Keyword("end" | "else")) | None) => {
+                    self.expect_right_paren()?;
+                },
+
+                _ => instrs.push(self.instr()?),
+            }
+        }
+    }
+
+    // Enters the folded instruction at the current position. Block instructions start a new
+    // sequence, the operands of the others and the condition of an if go to `instrs`.
+    fn open_folded(&mut self, open: &mut Vec<Open<'a>>, instrs: &mut Vec<Instr>) -> Result<(), ParseError> {
+        self.enter(self.tokens.get(self.pos).map_or(self.eof, |t| t.span))?;
+        self.expect_left_paren()?;
+        match self.tokens.get(self.pos).map(|t| t.kind) {
+            Some(TokenKind::Keyword(keyword @ ("block" | "loop"))) => {
+                let span = self.next()?.span;
+                self.instr_spans.push(span);
+                let label = self.id();
+                let block_type = self.block_type()?;
+                self.labels.push(label);
+                open.push(Open::FoldedBlock { keyword, block_type, outer: std::mem::take(instrs) });
+            },
+            Some(TokenKind::Keyword("if")) => {
+                let span = self.next()?.span;
+                let label = self.id();
+                let block_type = self.block_type()?;
+                open.push(Open::Condition { label, block_type, span });
+            },
+            _ => {
+                let instr = self.instr()?;
+                let span = self.instr_spans.pop().expect("plain instructions record their span");
+                open.push(Open::Folded { instr, span });
+            },
+        }
+        Ok(())
+    }
+
+    // Enters a block or folded instruction at `span`, which may not nest deeper than the limit.
+    fn enter(&mut self, span: Span) -> Result<(), ParseError> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(ParseError::new("instructions nested too deeply", span));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn instr(&mut self) -> Result<Instr, ParseError> {
+        let (keyword, span) = self.keyword()?;
+        self.instr_spans.push(span);
+        let instr = match keyword {
+            // Control instructions
+            "br" => Instr::Br(self.label()?),
+            "br_if" => Instr::BrIf(self.label()?),
+            "br_table" => {
+                let mut labels = vec![self.label()?];
+                while self.peek_index() {
+                    labels.push(self.label()?);
+                }
+                let default = labels.pop().unwrap();
+                Instr::BrTable(labels, default)
+            },
+            "call" => Instr::Call(self.func_index()?),
+            // call_indirect ::= 'call_indirect' tableidx? typeuse
+            "call_indirect" => {
+                let table = self.table_index()?;
+                let (type_idx, _, _) = self.type_use()?;
+                Instr::CallIndirect(table, type_idx)
+            },
+
+            // Reference instructions
+            "ref.null" => {
+                let (heap_type, span) = self.keyword()?;
+                match heap_type {
+                    "func" => Instr::RefNull(ReferenceType::FuncRef),
+                    "extern" => Instr::RefNull(ReferenceType::ExternRef),
+                    _ => return Err(ParseError::new(format!("unknown heap type `{}`", heap_type), span)),
+                }
+            },
+            "ref.func" => Instr::RefFunc(self.func_index()?),
+
+            // Parametric instructions
+            "select" => {
+                if self.peek_keyword_field("result") {
+                    Instr::Select(Some(self.results()?))
+                } else {
+                    Instr::Select(None)
+                }
+            },
+
+            // Variable instructions
+            "local.get" => Instr::LocalGet(self.local_index()?),
+            "local.set" => Instr::LocalSet(self.local_index()?),
+            "local.tee" => Instr::LocalTee(self.local_index()?),
+            "global.get" => Instr::GlobalGet(self.global_index()?),
+            "global.set" => Instr::GlobalSet(self.global_index()?),
+
+            // Table instructions, the table index defaults to 0
+            "table.get" => Instr::TableGet(self.table_index()?),
+            "table.set" => Instr::TableSet(self.table_index()?),
+            "table.size" => Instr::TableSize(self.table_index()?),
+            "table.grow" => Instr::TableGrow(self.table_index()?),
+            "table.fill" => Instr::TableFill(self.table_index()?),
+            "table.copy" => {
+                let dst = self.table_index()?;
+                Instr::TableCopy(dst, self.table_index()?)
+            },
+            // table.init ::= 'table.init' tableidx elemidx | 'table.init' elemidx
+            "table.init" => {
+                if self.peek_index() && self.peek_index_at(self.pos + 1) {
+                    let table = self.table_index()?;
+                    Instr::TableInit(table, self.elem_index()?)
+                } else {
+                    Instr::TableInit(0, self.elem_index()?)
+                }
+            },
+            "elem.drop" => Instr::ElemDrop(self.elem_index()?),
+
+            // Memory instructions
+            "memory.size" => Instr::MemorySize,
+            "memory.grow" => Instr::MemoryGrow,
+            "memory.fill" => Instr::MemoryFill,
+            "memory.copy" => Instr::MemoryCopy,
+            "memory.init" => Instr::MemoryInit(self.data_index()?),
+            "data.drop" => Instr::DataDrop(self.data_index()?),
+
+            // Numeric instructions
+            "i32.const" => Instr::I32Const(self.i32()?),
+            "i64.const" => Instr::I64Const(self.i64()?),
+            "f32.const" => Instr::F32Const(self.f32()?),
+            "f64.const" => Instr::F64Const(self.f64()?),
+
+            // Vector instructions
+            "v128.const" => Instr::V128Const(self.v128()?),
+            "i8x16.shuffle" => {
+                let mut lanes = [0; 16];
+                for lane in lanes.iter_mut() {
+                    *lane = self.lane()?;
+                }
+                Instr::I8x16Shuffle(lanes)
+            },
+
+            _ => self.table_instr(keyword, span)?,
+        };
+        Ok(instr)
+    }
+
+    // Looks up `keyword` in the instruction tables of `ast::Instr` and parses the immediates
+    // shared by that family.
+    fn table_instr(&mut self, keyword: &str, span: Span) -> Result<Instr, ParseError> {
+        if let Some((instr, _)) = Instr::plain_by_name(keyword) {
+            Ok(instr)
+        } else if let Some((instr, natural_align)) = Instr::memory_by_name(keyword) {
+            Ok(instr(self.memarg(natural_align)?))
+        } else if let Some((instr, (natural_align, _))) = Instr::memory_lane_by_name(keyword) {
+            let memarg = self.memarg(natural_align)?;
+            Ok(instr(memarg, self.lane()?))
+        } else if let Some((instr, _)) = Instr::lane_by_name(keyword) {
+            Ok(instr(self.lane()?))
+        } else {
+            Err(ParseError::new(format!("unknown instruction `{}`", keyword), span))
+        }
+    }
+
+    // blocktype ::= (result valtype)? | typeuse
+    fn block_type(&mut self) -> Result<BlockType, ParseError> {
+        if self.peek_keyword_field("type") {
+            let (idx, _, _) = self.type_use()?;
+            return Ok(BlockType::Type(idx));
+        }
+        let (params, _) = self.params()?;
+        let results = self.results()?;
+        match (params.is_empty(), results.as_slice()) {
+            (true, []) => Ok(BlockType::Empty),
+            (true, [value_type]) => Ok(BlockType::Value(*value_type)),
+            _ => Ok(BlockType::Type(self.implicit_type(&(params, results)))),
+        }
+    }
+
+    // The identifier after `end` or `else` must repeat the label of the block, if any.
+    fn end_label(&mut self, label: Option<&'a str>) -> Result<(), ParseError> {
+        let span = self.tokens.get(self.pos).map_or(self.eof, |t| t.span);
+        match self.id() {
+            Some(id) if Some(id) != label => Err(ParseError::new(format!("mismatching label `{}`", id), span)),
+            _ => Ok(()),
+        }
+    }
+
+    // memarg ::= ('offset=' u32)? ('align=' u32)?
+    fn memarg(&mut self, natural_align: u32) -> Result<MemArg, ParseError> {
+        let mut memarg = MemArg { offset: 0, align: natural_align };
+        if let Some((value, span)) = self.memarg_field("offset=")? {
+            memarg.offset = u32::try_from(value).map_err(|_| ParseError::new("offset out of range", span))?;
+        }
+        if let Some((value, span)) = self.memarg_field("align=")? {
+            if !value.is_power_of_two() {
+                return Err(ParseError::new("alignment must be a power of two", span));
+            }
+            memarg.align = value.trailing_zeros();
+        }
+        Ok(memarg)
+    }
+
+    fn memarg_field(&mut self, prefix: &str) -> Result<Option<(u64, Span)>, ParseError> {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => *token,
+            None => return Ok(None),
+        };
+        let value = match token.kind {
+            TokenKind::Keyword(keyword) => match keyword.strip_prefix(prefix) {
+                Some(value) => value,
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        // The value after the `=` is an unsigned integer literal of its own
+        let tokens: Vec<_> = Lexer::new(value).collect();
+        let value = match tokens.as_slice() {
+            [Ok(Token { kind: TokenKind::Integer(integer), .. })] if !value.starts_with(['+', '-']) => {
+                integer.magnitude().map(|(_, value)| value)
+            },
+            _ => None,
+        };
+        let value = value.ok_or_else(|| ParseError::new(format!("malformed `{}`", prefix), token.span))?;
+        Ok(Some((value, token.span)))
+    }
+
+    // v128.const takes a shape followed by one literal per lane, stored little endian.
+    fn v128(&mut self) -> Result<u128, ParseError> {
+        let (shape, span) = self.keyword()?;
+        let (lanes, bits) = match shape {
+            "i8x16" => (16, 8),
+            "i16x8" => (8, 16),
+            "i32x4" | "f32x4" => (4, 32),
+            "i64x2" | "f64x2" => (2, 64),
+            _ => return Err(ParseError::new(format!("unknown vector shape `{}`", shape), span)),
+        };
+        let mut value = 0u128;
+        for lane in 0..lanes {
+            let bits_of_lane = match shape {
+                "f32x4" => self.f32()? as u64,
+                "f64x2" => self.f64()?,
+                _ => self.int_bits(bits)?,
+            };
+            value |= (bits_of_lane as u128) << (lane * bits);
+        }
+        Ok(value)
+    }
+
+    fn lane(&mut self) -> Result<u8, ParseError> {
+        let (integer, span) = self.integer()?;
+        match integer.magnitude() {
+            Some((false, lane)) if lane <= u8::MAX as u64 => Ok(lane as u8),
+            _ => Err(ParseError::new("lane index out of range", span)),
+        }
+    }
+
+    // tabletype ::= limits reftype
+    fn table_type(&mut self) -> Result<TableType, ParseError> {
+        let limits = self.limits()?;
+        let elem_type = self.ref_type()?;
+        Ok(TableType { limits, elem_type })
+    }
+
+    // limits ::= u32 u32?
+    fn limits(&mut self) -> Result<Limits, ParseError> {
+        let min = self.u32()?;
+        let max = if self.peek_index() { Some(self.u32()?) } else { None };
+        Ok(Limits { min, max })
+    }
+
+    // globaltype ::= valtype | '(' 'mut' valtype ')'
+    fn global_type(&mut self) -> Result<GlobalType, ParseError> {
+        if self.peek_keyword_field("mut") {
+            self.expect_left_paren()?;
+            self.expect_keyword("mut")?;
+            let value_type = self.value_type()?;
+            self.expect_right_paren()?;
+            Ok(GlobalType { mutable: true, value_type })
+        } else {
+            Ok(GlobalType { mutable: false, value_type: self.value_type()? })
+        }
+    }
+
+    // reftype ::= 'funcref' | 'externref'
+    fn ref_type(&mut self) -> Result<ReferenceType, ParseError> {
+        let (keyword, span) = self.keyword()?;
+        match keyword {
+            "funcref" => Ok(ReferenceType::FuncRef),
+            "externref" => Ok(ReferenceType::ExternRef),
+            _ => Err(ParseError::new(format!("unknown reference type `{}`", keyword), span)),
+        }
+    }
+
+    // valtype ::= 'i32' | 'i64' | 'f32' | 'f64' | 'v128' | 'funcref' | 'externref'
+    fn value_type(&mut self) -> Result<ValueType, ParseError> {
+        let (keyword, span) = self.keyword()?;
+        match keyword {
+            "i32" => Ok(ValueType::NumberType(NumberType::I32)),
+            "i64" => Ok(ValueType::NumberType(NumberType::I64)),
+            "f32" => Ok(ValueType::NumberType(NumberType::F32)),
+            "f64" => Ok(ValueType::NumberType(NumberType::F64)),
+            "v128" => Ok(ValueType::VectorType(VectorType::V128)),
+            "funcref" => Ok(ValueType::ReferenceType(ReferenceType::FuncRef)),
+            "externref" => Ok(ValueType::ReferenceType(ReferenceType::ExternRef)),
+            _ => Err(ParseError::new(format!("unknown value type `{}`", keyword), span)),
+        }
+    }
+
+    fn func_index(&mut self) -> Result<usize, ParseError> {
+        self.index(|p, id, span| p.func_ids.resolve(id, span))
+    }
+
+    fn local_index(&mut self) -> Result<usize, ParseError> {
+        self.index(|p, id, span| p.local_ids.resolve(id, span))
+    }
+
+    fn global_index(&mut self) -> Result<usize, ParseError> {
+        self.index(|p, id, span| p.global_ids.resolve(id, span))
+    }
+
+    fn elem_index(&mut self) -> Result<usize, ParseError> {
+        self.index(|p, id, span| p.elem_ids.resolve(id, span))
+    }
+
+    fn data_index(&mut self) -> Result<usize, ParseError> {
+        self.index(|p, id, span| p.data_ids.resolve(id, span))
+    }
+
+    // Table indices of instructions are optional and default to 0.
+    fn table_index(&mut self) -> Result<usize, ParseError> {
+        if self.peek_index() {
+            self.index(|p, id, span| p.table_ids.resolve(id, span))
+        } else {
+            Ok(0)
+        }
+    }
+
+    // Labels are referenced by their relative depth, the innermost label being 0.
+    fn label(&mut self) -> Result<usize, ParseError> {
+        self.index(|p, id, span| {
+            p.labels.iter().rev().position(|label| *label == Some(id))
+                .ok_or_else(|| ParseError::new(format!("unknown label `{}`", id), span))
+        })
+    }
+
+    fn peek_index(&self) -> bool {
+        self.peek_index_at(self.pos)
+    }
+
+    fn peek_index_at(&self, pos: usize) -> bool {
+        matches!(
+            self.tokens.get(pos).map(|t| t.kind),
+            Some(TokenKind::Integer(_)) | Some(TokenKind::Identifier(_))
+        )
+    }
+
+    // idx ::= u32 | id
+    fn index<F>(&mut self, lookup: F) -> Result<usize, ParseError>
+    where
+        F: FnOnce(&Self, &'a str, Span) -> Result<usize, ParseError>,
+    {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Integer(integer) => integer.to_u32()
+                .map(|idx| idx as usize)
+                .ok_or_else(|| ParseError::new("index out of range", token.span)),
+            TokenKind::Identifier(id) => lookup(self, id, token.span),
+            _ => Err(ParseError::new("expected an index", token.span)),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let (integer, span) = self.integer()?;
+        integer.to_u32().ok_or_else(|| ParseError::new("u32 constant out of range", span))
+    }
+
+    fn i32(&mut self) -> Result<i32, ParseError> {
+        let (integer, span) = self.integer()?;
+        integer.to_i32().ok_or_else(|| ParseError::new("i32 constant out of range", span))
+    }
+
+    fn i64(&mut self) -> Result<i64, ParseError> {
+        let (integer, span) = self.integer()?;
+        integer.to_i64().ok_or_else(|| ParseError::new("i64 constant out of range", span))
+    }
+
+    // Returns the two's complement bit pattern of an integer literal in `bits` bits.
+    fn int_bits(&mut self, bits: u32) -> Result<u64, ParseError> {
+        let (integer, span) = self.integer()?;
+        integer.to_bits(bits).ok_or_else(|| ParseError::new(format!("i{} constant out of range", bits), span))
+    }
+
+    fn integer(&mut self) -> Result<(IntegerKind<'a>, Span), ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Integer(integer) => Ok((integer, token.span)),
+            _ => Err(ParseError::new("expected an integer", token.span)),
+        }
+    }
+
+    // Returns the bit pattern of an f32 literal.
+    fn f32(&mut self) -> Result<u32, ParseError> {
+        let token = self.next()?;
+        let bits = match token.kind {
+            TokenKind::Float(float) => float.to_f32_bits(),
+            TokenKind::Integer(integer) => integer.to_f32_bits(),
+            _ => return Err(ParseError::new("expected a float", token.span)),
+        };
+        bits.ok_or_else(|| ParseError::new("f32 constant out of range", token.span))
+    }
+
+    // Returns the bit pattern of an f64 literal.
+    fn f64(&mut self) -> Result<u64, ParseError> {
+        let token = self.next()?;
+        let bits = match token.kind {
+            TokenKind::Float(float) => float.to_f64_bits(),
+            TokenKind::Integer(integer) => integer.to_f64_bits(),
+            _ => return Err(ParseError::new("expected a float", token.span)),
+        };
+        bits.ok_or_else(|| ParseError::new("f64 constant out of range", token.span))
+    }
+
+    // name ::= b*:string (if b* is valid UTF-8)
+    fn name(&mut self) -> Result<String, ParseError> {
+        let span = self.tokens.get(self.pos).map_or(self.eof, |t| t.span);
+        String::from_utf8(self.string()?).map_err(|_| ParseError::new("malformed UTF-8 encoding", span))
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::String(string) => Ok(string.bytes()),
+            _ => Err(ParseError::new("expected a string", token.span)),
+        }
+    }
+
+    fn id(&mut self) -> Option<&'a str> {
+        match self.tokens.get(self.pos).map(|t| t.kind) {
+            Some(TokenKind::Identifier(id)) => {
+                self.pos += 1;
+                Some(id)
+            },
+            _ => None,
+        }
+    }
+
+    fn id_with_span(&mut self) -> Option<(&'a str, Span)> {
+        let span = self.tokens.get(self.pos).map(|t| t.span)?;
+        self.id().map(|id| (id, span))
+    }
+
+    fn keyword(&mut self) -> Result<(&'a str, Span), ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Keyword(keyword) => Ok((keyword, token.span)),
+            _ => Err(ParseError::new("expected a keyword", token.span)),
+        }
+    }
+
+    fn expect_keyword(&mut self, expected: &str) -> Result<(), ParseError> {
+        let (keyword, span) = self.keyword()?;
+        if keyword != expected {
+            return Err(ParseError::new(format!("expected `{}`, found `{}`", expected, keyword), span));
+        }
+        Ok(())
+    }
+
+    fn expect_left_paren(&mut self) -> Result<(), ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::LeftParen => Ok(()),
+            _ => Err(ParseError::new("expected `(`", token.span)),
+        }
+    }
+
+    fn expect_right_paren(&mut self) -> Result<(), ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::RightParen => Ok(()),
+            _ => Err(ParseError::new("expected `)`", token.span)),
+        }
+    }
+
+    fn peek_right_paren(&self) -> bool {
+        matches!(self.tokens.get(self.pos).map(|t| t.kind), Some(TokenKind::RightParen))
+    }
+
+    // Whether the form whose contents start at the current position has a `(keyword ...)`
+    // child, without moving.
+    fn peek_child_field(&self, keyword: &str) -> bool {
+        let mut depth = 0;
+        for (pos, token) in self.tokens.iter().enumerate().skip(self.pos) {
+            match token.kind {
+                TokenKind::LeftParen if depth == 0 && self.peek_field_at(pos) == Some(keyword) => return true,
+                TokenKind::LeftParen => depth += 1,
+                TokenKind::RightParen if depth == 0 => return false,
+                TokenKind::RightParen => depth -= 1,
+                _ => {},
+            }
+        }
+        false
+    }
+
+    fn peek_field_at(&self, pos: usize) -> Option<&'a str> {
+        match self.tokens.get(pos + 1).map(|t| t.kind) {
+            Some(TokenKind::Keyword(keyword)) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    // Returns the keyword of the `(keyword ...)` form starting at the current position.
+    fn peek_field(&self) -> Result<(&'a str, Span), ParseError> {
+        match (self.tokens.get(self.pos), self.tokens.get(self.pos + 1)) {
+            (Some(Token { kind: TokenKind::LeftParen, .. }), Some(Token { kind: TokenKind::Keyword(keyword), span })) => {
+                Ok((keyword, *span))
+            },
+            (Some(token), _) => Err(ParseError::new("expected a module field", token.span)),
+            (None, _) => Err(ParseError::new("unexpected end of input", self.eof)),
+        }
+    }
+
+    fn peek_keyword_field(&self, expected: &str) -> bool {
+        matches!(self.peek_field(), Ok((keyword, _)) if keyword == expected)
+    }
+
+    // Skips tokens up to and including the paren that closes the current form.
+    fn skip_to_right_paren(&mut self) -> Result<(), ParseError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()?.kind {
+                TokenKind::LeftParen => depth += 1,
+                TokenKind::RightParen => depth -= 1,
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, ParseError> {
+        let token = self.tokens.get(self.pos).copied()
+            .ok_or_else(|| ParseError::new("unexpected end of input", self.eof))?;
+        self.pos += 1;
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::loader;
+
+    fn error(source: &str) -> (String, &str) {
+        let error = parse(source).unwrap_err();
+        (error.message, &source[error.span])
+    }
+
+    #[test]
+    fn parses_functions_and_exports() {
+        const I32: ValueType = ValueType::NumberType(NumberType::I32);
+
+        let module = parse(include_str!("../test2.wat")).unwrap();
+        assert_eq!(module.types, [(vec![I32, I32], vec![I32])]);
+        assert_eq!(module.funcs.len(), 1);
+        assert_eq!(module.funcs[0].f_type, 0);
+        assert!(module.funcs[0].locals.is_empty());
+        assert_eq!(module.funcs[0].body, [Instr::LocalGet(0), Instr::LocalGet(1), Instr::I32Add]);
+        assert_eq!(module.exports, [Export { name: "add".to_string(), desc: ExportDesc::Func(0) }]);
+    }
+
+    #[test]
+    fn reports_errors_with_their_span() {
+        assert_eq!(error("(module (func (param i33)))"), ("unknown value type `i33`".to_string(), "i33"));
+        assert_eq!(error("(module (memoir))"), ("unexpected module field `memoir`".to_string(), "memoir"));
+        assert_eq!(error("(module (func) func)"), ("expected a module field".to_string(), "func"));
+    }
+
+    #[test]
+    fn leaves_unknown_type_indices_to_the_validator() {
+        let module = parse("(module (table 0 funcref) (func (call_indirect (type 1) (i32.const 0))))").unwrap();
+        assert_eq!(module.funcs[0].body, [Instr::I32Const(0), Instr::CallIndirect(0, 1)]);
+        assert_eq!(
+            error("(module (type (func)) (func (type 0) (param i32)))"),
+            ("inline function type does not match type definition".to_string(), "0"),
+        );
+    }
+
+    #[test]
+    fn resolves_identifiers_per_namespace() {
+        let module = parse(r#"
+            (module $m
+              (type $f (func (param i32)))
+              (import "env" "g" (func $g (type $f)))
+              (global $f i32 i32.const 0)
+              (func $f (param $x i32) (local $y i32)
+                block $f
+                  local.get $x
+                  call $g
+                  br $f
+                end))
+        "#).unwrap();
+        let custom = module.customs.iter().find(|custom| custom.name == "name").unwrap();
+        let names = loader::decode_names(&custom.data).unwrap();
+        assert_eq!(names.module.as_deref(), Some("m"));
+        assert_eq!(names.funcs, NameMap::from([(0, "g".to_string()), (1, "f".to_string())]));
+        assert_eq!(names.locals[&1], NameMap::from([(0, "x".to_string()), (1, "y".to_string())]));
+        assert_eq!(names.types[&0], "f");
+        assert_eq!(names.globals[&0], "f");
+
+        assert!(parse("(module (func))").unwrap().customs.is_empty());
+    }
+
+    #[test]
+    fn reports_duplicate_and_unknown_identifiers() {
+        assert_eq!(error("(module (func $a) (func $a))"), ("duplicate function `$a`".to_string(), "$a"));
+        assert_eq!(error("(module (type $t (func)) (type $t (func)))"), ("duplicate type `$t`".to_string(), "$t"));
+        assert_eq!(error("(module (func (param $x i32) (local $x i32)))"), ("duplicate local `$x`".to_string(), "$x"));
+        assert_eq!(error("(module (func call $h))"), ("unknown function `$h`".to_string(), "$h"));
+        assert_eq!(error("(module (global $g i32 i32.const 0) (func global.get $h drop))"),
+            ("unknown global `$h`".to_string(), "$h"));
+        assert_eq!(error("(module (func block $l br $k end))"), ("unknown label `$k`".to_string(), "$k"));
+    }
+
+    #[test]
+    fn desugars_abbreviations() {
+        let abbreviated = parse(r#"
+            (module
+              (func $print (import "env" "print") (param i32))
+              (global $start (import "env" "start") i32)
+              (memory (export "memory") (data "hi" "!"))
+              (table $t (export "table") funcref (elem $print $main))
+              (global (export "len") (mut i32) (i32.const 3))
+              (data (global.get $start) "abc")
+              (elem (i32.const 0) $main)
+              (func $main (export "main") (export "start") (param $x i32) (result i32)
+                (call $print (i32.add (local.get $x) (global.get $start)))
+                (if (result i32) (local.get $x)
+                  (then (block $b (result i32) (br $b (i32.const 1))))
+                  (else (loop (result i32) (i32.const 2))))))
+        "#).unwrap();
+        let canonical = parse(r#"
+            (module
+              (type (func (param i32)))
+              (type (func (param i32) (result i32)))
+              (import "env" "print" (func (type 0)))
+              (import "env" "start" (global i32))
+              (memory 1 1)
+              (table 2 2 funcref)
+              (global (mut i32) i32.const 3)
+              (export "memory" (memory 0))
+              (export "table" (table 0))
+              (export "len" (global 1))
+              (export "main" (func 1))
+              (export "start" (func 1))
+              (elem (table 0) (offset i32.const 0) funcref (item ref.func 0) (item ref.func 1))
+              (elem (table 0) (offset i32.const 0) funcref (item ref.func 1))
+              (func (type 1)
+                local.get 0
+                global.get 0
+                i32.add
+                call 0
+                local.get 0
+                if (result i32)
+                  block (result i32)
+                    i32.const 1
+                    br 0
+                  end
+                else
+                  loop (result i32)
+                    i32.const 2
+                  end
+                end)
+              (data (memory 0) (offset i32.const 0) "hi!")
+              (data (memory 0) (offset global.get 0) "abc"))
+        "#).unwrap();
+        assert_eq!(Module { customs: vec![], ..abbreviated }, canonical);
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize, open: &str, close: &str| {
+            format!("(module (func {} {}))", open.repeat(depth), close.repeat(depth))
+        };
+        for (open, close) in [("block ", "end "), ("(block ", ")"), ("(i32.eqz ", ")"), ("if ", "end ")] {
+            assert!(parse(&nested(MAX_NESTING_DEPTH, open, close)).is_ok(), "{}", open);
+            let source = nested(MAX_NESTING_DEPTH + 1, open, close);
+            let error = parse(&source).unwrap_err();
+            assert_eq!(error.message, "instructions nested too deeply");
+            assert_eq!(error.span.start as usize, "(module (func ".len() + MAX_NESTING_DEPTH * open.len());
+            assert!(parse(&nested(20000, open, close)).is_err());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::parsing::rules::{Rule, get_error_wat, get_rules_wast, get_rules_wat};
+use crate::parsing::token::Span;
+
+// Configures `Lexer`: the rules in order of priority, and the function that explains input
+// no rule matches, returning the error and how many bytes to skip.
+#[derive(Clone)]
+pub struct Grammar {
+    pub rules: Vec<Rule>,
+    pub error: fn(&str, usize) -> (Error, usize),
+}
+
+impl Grammar {
+    // A grammar that reports input no rule matches one character at a time.
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            error: unexpected_character,
+        }
+    }
+
+    pub fn wat() -> Self {
+        Self {
+            rules: get_rules_wat(),
+            error: get_error_wat,
+        }
+    }
+
+    pub fn wast() -> Self {
+        Self {
+            rules: get_rules_wast(),
+            error: get_error_wat,
+        }
+    }
+}
+
+fn unexpected_character(input: &str, start: usize) -> (Error, usize) {
+    let len = input.chars().next().map_or(0, char::len_utf8);
+    (Error::UnexpectedCharacter { span: Span::from(start..start + len) }, len)
+}
+
+// Grammars by the extension of the files they lex. `.wat` and `.wast` are registered by
+// default, other grammars can be added at runtime.
+pub struct Grammars {
+    by_extension: HashMap<String, Grammar>,
+}
+
+impl Grammars {
+    pub fn new() -> Self {
+        let mut grammars = Self { by_extension: HashMap::new() };
+        grammars.register("wat", Grammar::wat());
+        grammars.register("wast", Grammar::wast());
+        grammars
+    }
+
+    // Registers `grammar` for files ending in `.extension`, replacing any grammar registered
+    // for it before.
+    pub fn register(&mut self, extension: impl Into<String>, grammar: Grammar) {
+        self.by_extension.insert(extension.into(), grammar);
+    }
+
+    pub fn get(&self, extension: &str) -> Option<&Grammar> {
+        self.by_extension.get(extension)
+    }
+
+    pub fn for_path(&self, path: impl AsRef<Path>) -> Option<&Grammar> {
+        self.get(path.as_ref().extension()?.to_str()?)
+    }
+}
+
+impl Default for Grammars {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::Lexer;
+    use crate::parsing::token::TokenKind;
+
+    fn tokens<'a>(grammar: &Grammar, source: &'a str) -> Vec<(TokenKind, &'a str)> {
+        Lexer::with_grammar(source, grammar.clone())
+            .map(Result::unwrap)
+            .filter(|token| !token.kind.is_trivia())
+            .map(|token| (token.kind, token.text(source)))
+            .collect()
+    }
+
+    #[test]
+    fn selects_grammar_by_extension() {
+        let source = "(assert_return (invoke \"f\") (get))";
+        let grammars = Grammars::new();
+
+        let wat = tokens(grammars.for_path("a/b.wat").unwrap(), source);
+        assert_eq!(wat[1], (TokenKind::Keyword, "assert_return"));
+
+        let wast = tokens(grammars.for_path("a/b.wast").unwrap(), source);
+        assert_eq!(wast[1], (TokenKind::Directive, "assert_return"));
+        assert_eq!(wast[3], (TokenKind::Directive, "invoke"));
+        assert_eq!(wast[7], (TokenKind::Directive, "get"));
+        assert_eq!(tokens(&Grammar::wast(), "assert_returns"), [(TokenKind::Keyword, "assert_returns")]);
+
+        assert!(grammars.for_path("b.txt").is_none());
+    }
+
+    #[test]
+    fn registers_grammars() {
+        fn word(input: &str) -> Option<u32> {
+            Some(input.find(|c: char| !c.is_alphabetic()).unwrap_or(input.len()) as u32)
+        }
+        fn space(input: &str) -> Option<u32> {
+            Some(input.find(|c| c != ' ').unwrap_or(input.len()) as u32)
+        }
+
+        let mut grammars = Grammars::new();
+        grammars.register("words", Grammar::new(vec![
+            Rule { kind: TokenKind::Keyword, matches: word },
+            Rule { kind: TokenKind::Whitespace, matches: space },
+        ]));
+        let grammar = grammars.for_path("notes.words").unwrap();
+        assert_eq!(tokens(grammar, "ab cd"), [(TokenKind::Keyword, "ab"), (TokenKind::Keyword, "cd")]);
+
+        let errors: Vec<_> = Lexer::with_grammar("a1", grammar.clone()).collect();
+        assert_eq!(errors[1], Err(Error::UnexpectedCharacter { span: Span { start: 1, end: 2 } }));
+    }
+}
@@ -1,162 +1,139 @@
-use super::rules::{Rule, get_rules_wat};
+use crate::error::Error;
 
-pub enum Token {
-    // Keywords
-    Module,
-    Func,
-    Param,
-    Result,
-    Local,
-    Global,
-    Table,
-    Memory,
-    Export,
-    Import,
-    Type,
-    Start,
-    Elem,
-    Data,
-    Offset,
-    Mut,
-    If,
-    Then,
-    Else,
-    End,
-    // Types
-    I32,
-    I64,
-    F32,
-    F64,
-    V128,
-    Funcref,
-    Externref,
-    // Symbols
-    Lparen,
-    Rparen,
-    Lbrace,
-    Rbrace,
-    Lbracket,
-    Rbracket,
-    Lt,
-    Gt,
-    Comma,
-    Semicolon,
-    Colon,
-    Equal,
-    Minus,
-    Plus,
-    Star,
-    Slash,
-    Percent,
-    Exclamation,
-    Ampersand,
-    Pipe,
-    Caret,
-    Tilde,
-    Dollar,
-    Hash,
-    At,
-    // Literals
-    IntLiteral(i32),
-    FloatLiteral(f32),
-    StringLiteral(String),
-    // Identifiers
-    Identifier(String),
-    // Misc
-    Whitespace,
-    Comment,
-    Newline,
-    Eof,
-}
-
-struct Span {
-    start: usize,
-    end: usize,
-}
+use super::grammar::Grammar;
+use super::token::{Token, TokenKind};
 
+// Table-driven lexer: at every position each rule of the grammar reports how long a token it
+// matches, the longest match wins and ties go to the rule listed first. When no rule
+// matches, the error function of the grammar explains why and how much input to skip.
 pub struct Lexer<'input> {
     input: &'input str,
     position: usize,
-    rules: Vec<Rule>,
+    grammar: Grammar,
 }
 
 impl<'input> Lexer<'input> {
-    pub fn new (input: &'input str) -> Self {
+    pub fn new(input: &'input str) -> Self {
+        Self::with_grammar(input, Grammar::wat())
+    }
+
+    pub fn with_grammar(input: &'input str, grammar: Grammar) -> Self {
         Self {
             input,
             position: 0,
-            rules: get_rules_wat(),
+            grammar,
         }
     }
 }
 
-pub struct SyntaxError {
-    pub message: String,
-    pub span: Span,
-}
-
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Result<(Token, Span), SyntaxError>;
+    type Item = Result<Token, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        todo!()
+        let input = &self.input[self.position..];
+        if input.is_empty() {
+            return None;
+        }
+
+        let start = self.position;
+        let mut longest: Option<(TokenKind, u32)> = None;
+        for rule in &self.grammar.rules {
+            if let Some(len) = (rule.matches)(input) {
+                if len > 0 && longest.is_none_or(|(_, longest)| len > longest) {
+                    longest = Some((rule.kind, len));
+                }
+            }
+        }
+
+        match longest {
+            Some((kind, len)) => {
+                self.position += len as usize;
+                Some(Ok(Token { kind, span: (start..self.position).into() }))
+            },
+            None => {
+                let (error, len) = (self.grammar.error)(input, start);
+                self.position += len;
+                Some(Err(error))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+    use crate::token;
+
+    // Lexes `source` with this lexer and with `lexer::Lexer`, whose tokens are reduced to
+    // their kinds.
+    fn assert_same_tokens(source: &str) {
+        let expected: Vec<_> = lexer::Lexer::new(source)
+            .map(|token| token.map(|token| Token { kind: kind(token.kind), span: token.span }))
+            .collect();
+        let actual: Vec<_> = Lexer::new(source).collect();
+        assert_eq!(actual, expected, "{:?}", source);
+    }
+
+    fn kind(kind: token::TokenKind) -> TokenKind {
+        match kind {
+            token::TokenKind::LeftParen => TokenKind::LeftParen,
+            token::TokenKind::RightParen => TokenKind::RightParen,
+            token::TokenKind::Identifier(_) => TokenKind::Identifier,
+            token::TokenKind::String(_) => TokenKind::String,
+            token::TokenKind::Integer(_) => TokenKind::Integer,
+            token::TokenKind::Float(_) => TokenKind::Float,
+            token::TokenKind::Keyword(_) => TokenKind::Keyword,
+            token::TokenKind::Reserved(_) => TokenKind::Reserved,
+            token::TokenKind::Whitespace => TokenKind::Whitespace,
+            token::TokenKind::LineComment(_) => TokenKind::LineComment,
+            token::TokenKind::BlockComment(_) => TokenKind::BlockComment,
+            token::TokenKind::Eof => unreachable!("the lexer does not produce `Eof`"),
+        }
+    }
+
+    #[test]
+    fn matches_lexer() {
+        assert_same_tokens(r#"
+            (module $m ;; comment
+              (; block (; nested ;) ;)
+              (memory 1)
+              (data (i32.const 8) "a\n\t\u{1F600}\ff λ")
+              (func $f (export "f") (param $x i32) (result f64)
+                local.get $x
+                i32.load offset=4 align=2
+                drop
+                f64.const -0x1.8p-3
+                f64.const +1.e+5
+                f64.const nan:0x1_0
+                f64.const -inf
+                i64.const 0xFF_ff
+                i64.const 1_000))
+        "#);
+        for source in [
+            "1__0", "1_", "0x", "-0x", "0x1p", "1e", "1.5_", "+-1", "nan:0x", "infinity", "$", "$$",
+            "a;b", "(;)", "(;;)", "((;x;)", ";", "[", "λ", "\"abc", "\"a\\qb\"", "\"\\u{d800}\"",
+            "\"a\tb\"", "\"\\\"\"", "(; (; ;)", "x\r\n;; end",
+        ] {
+            assert_same_tokens(source);
+        }
+    }
+
+    #[test]
+    fn prefers_longest_match_then_rule_order() {
+        let source = "inf infinity $x 12 1.5 (;c;)(";
+        let tokens: Vec<_> = Lexer::new(source)
+            .map(Result::unwrap)
+            .filter(|token| !token.kind.is_trivia())
+            .map(|token| (token.kind, token.text(source)))
+            .collect();
+        assert_eq!(tokens, [
+            (TokenKind::Float, "inf"),
+            (TokenKind::Keyword, "infinity"),
+            (TokenKind::Identifier, "$x"),
+            (TokenKind::Integer, "12"),
+            (TokenKind::Float, "1.5"),
+            (TokenKind::LeftParen, "("),
+        ]);
     }
 }
-//    pub fn next(&mut self) -> Option<char> {
-//        let c = self.input.chars().nth(self.pos);
-//        if let Some(c) = c {
-//            self.pos += 1;
-//            if c == '\n' {
-//                self.line += 1;
-//                self.col = 1;
-//            } else {
-//                self.col += 1;
-//            }
-//        }
-//        c
-//    }
-//
-//    pub fn peek(&self) -> Option<char> {
-//        self.input.chars().nth(self.pos)
-//    }
-//
-//    pub fn eof(&self) -> bool {
-//        self.peek().is_none()
-//    }
-//
-//    pub fn tokenize(&mut self) -> Vec<Token> {
-//        let mut tokens = Vec::new();
-//        while !self.eof() {
-//            let token = self.next_token();
-//            tokens.push(token);
-//        }
-//        tokens
-//    }
-//
-//    pub fn is_whitespace(&self, c: char) -> bool {
-//        c == ' ' || c == '\t' || c == '\n' || c == '\r'
-//    }
-//
-//    pub fn is_digit(&self, c: char) -> bool {
-//        c >= '0' && c <= '9'
-//    }
-//
-//    pub fn is_hex_digit(&self, c: char) -> bool {
-//        self.is_digit(c) || (c >= 'a' && c <= 'f') || (c >= 'A' && c <= 'F')
-//    }
-//
-//    pub fn is_alpha(&self, c: char) -> bool {
-//        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z')
-//    }
-//
-//    pub fn is_alphanumeric(&self, c: char) -> bool {
-//        self.is_alpha(c) || self.is_digit(c)
-//    }
-//
-//    pub fn is_symbol(&self, c: char) -> bool {
-//        match c {
-//            '(' | ')' | '{' | '}' | '[' | ']' | '<' | '>' | ',' | ';' | ':' | '=' | '-' | '+' | '*' | '/' | '%' | '!' | '&' | '|' | '^' | '~' | '$' | '#' | '@' | '\'' | '"' => true,
-//            _ => false,
-//        }
-//    }
-//}
\ No newline at end of file
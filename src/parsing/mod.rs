@@ -1,4 +1,6 @@
+pub use grammar::{Grammar, Grammars};
 pub use lexer::Lexer;
+pub mod grammar;
 pub mod rules;
 pub mod token;
-pub mod lexer;
\ No newline at end of file
+pub mod lexer;
@@ -1,9 +1,15 @@
 use crate::parsing::token::TokenKind;
 
+// Matches a token of `kind` at the start of the input, returning the length of the match in
+// bytes.
+#[derive(Clone, Copy)]
 pub struct Rule {
     pub kind: TokenKind,
     pub matches: fn(&str) -> Option<u32>,
 }
 
+pub mod rules_wast;
 pub mod rules_wat;
-pub use rules_wat::get_rules as get_rules_wat;
\ No newline at end of file
+pub use rules_wast::get_rules as get_rules_wast;
+pub use rules_wat::get_rules as get_rules_wat;
+pub use rules_wat::get_error as get_error_wat;
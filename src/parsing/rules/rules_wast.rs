@@ -0,0 +1,29 @@
+use crate::parsing::rules::{Rule, rules_wat};
+use crate::parsing::token::TokenKind;
+
+// The tokens of spec test scripts: the text format plus the keywords of the script commands,
+// which take precedence over plain keywords.
+pub fn get_rules() -> Vec<Rule> {
+    let mut rules = vec![Rule { kind: TokenKind::Directive, matches: directive }];
+    rules.extend(rules_wat::get_rules());
+    rules
+}
+
+const DIRECTIVES: &[&str] = &[
+    "assert_return",
+    "assert_trap",
+    "assert_exhaustion",
+    "assert_invalid",
+    "assert_malformed",
+    "assert_unlinkable",
+    "register",
+    "invoke",
+    "get",
+    "binary",
+    "quote",
+];
+
+fn directive(input: &str) -> Option<u32> {
+    let len = rules_wat::idchars(input);
+    DIRECTIVES.contains(&&input[..len]).then_some(len as u32)
+}
@@ -1,8 +1,226 @@
-//fn match_
+use crate::error::Error;
 use crate::parsing::rules::Rule;
+use crate::parsing::token::{Span, TokenKind};
+use crate::token::decode_string;
 
+// The tokens of the text format. Rules listed first win ties between matches of the same
+// length, so `inf` is a float rather than a keyword and `$x` an identifier rather than a
+// reserved token.
 pub fn get_rules() -> Vec<Rule> {
     vec![
-        // Rule {}
+        Rule { kind: TokenKind::LeftParen, matches: left_paren },
+        Rule { kind: TokenKind::RightParen, matches: right_paren },
+        Rule { kind: TokenKind::Whitespace, matches: whitespace },
+        Rule { kind: TokenKind::LineComment, matches: line_comment },
+        Rule { kind: TokenKind::BlockComment, matches: block_comment },
+        Rule { kind: TokenKind::String, matches: string },
+        Rule { kind: TokenKind::Integer, matches: integer },
+        Rule { kind: TokenKind::Float, matches: float },
+        Rule { kind: TokenKind::Keyword, matches: keyword },
+        Rule { kind: TokenKind::Identifier, matches: identifier },
+        Rule { kind: TokenKind::Reserved, matches: reserved },
     ]
-}
\ No newline at end of file
+}
+
+// Describes why no rule matches `input`, which starts at byte `start` of the source, and
+// returns how many bytes to skip before lexing resumes.
+pub fn get_error(input: &str, start: usize) -> (Error, usize) {
+    let span = |len: usize| Span::from(start..start + len);
+    if input.starts_with("(;") {
+        return (Error::UnterminatedComment { span: span(2) }, input.len());
+    }
+    if input.starts_with('"') {
+        return match closing_quote(input) {
+            Some(end) => {
+                let error = decode_string(&input[1..end], start + 1).expect_err("string rule did not match");
+                (error, end + 1)
+            },
+            None => (Error::UnterminatedString { span: span(input.len()) }, input.len()),
+        };
+    }
+    let len = idchars(input);
+    if is_number_like(&input[..len]) {
+        return (Error::MalformedNumber { span: span(len) }, len);
+    }
+    let len = input.chars().next().map_or(0, char::len_utf8);
+    (Error::UnexpectedCharacter { span: span(len) }, len)
+}
+
+fn left_paren(input: &str) -> Option<u32> {
+    (input.starts_with('(') && !input.starts_with("(;")).then_some(1)
+}
+
+fn right_paren(input: &str) -> Option<u32> {
+    input.starts_with(')').then_some(1)
+}
+
+fn whitespace(input: &str) -> Option<u32> {
+    let len = input.len() - input.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    (len > 0).then_some(len as u32)
+}
+
+// linecomment ::= ';;' linechar* ('\n' | eof)
+fn line_comment(input: &str) -> Option<u32> {
+    if !input.starts_with(";;") {
+        return None;
+    }
+    Some(input.find('\n').unwrap_or(input.len()) as u32)
+}
+
+// blockcomment ::= '(;' blockchar* ';)'
+fn block_comment(input: &str) -> Option<u32> {
+    if !input.starts_with("(;") {
+        return None;
+    }
+    let bytes = input.as_bytes();
+    let mut depth = 1;
+    let mut pos = 2;
+    while pos < bytes.len() {
+        if bytes[pos..].starts_with(b"(;") {
+            depth += 1;
+            pos += 2;
+        } else if bytes[pos..].starts_with(b";)") {
+            depth -= 1;
+            pos += 2;
+            if depth == 0 {
+                return Some(pos as u32);
+            }
+        } else {
+            pos += 1;
+        }
+    }
+    None
+}
+
+// string ::= '"' stringelem* '"'
+fn string(input: &str) -> Option<u32> {
+    let end = closing_quote(input)?;
+    decode_string(&input[1..end], 0).ok()?;
+    Some(end as u32 + 1)
+}
+
+// Returns the position of the quote closing the string at the start of `input`.
+fn closing_quote(input: &str) -> Option<usize> {
+    let bytes = input.as_bytes();
+    if bytes.first() != Some(&b'"') {
+        return None;
+    }
+    let mut pos = 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return Some(pos),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+// int ::= sign? num | sign? '0x' hexnum
+fn integer(input: &str) -> Option<u32> {
+    token(input, |src| {
+        let src = strip_sign(src);
+        match src.strip_prefix("0x") {
+            Some(hex) => is_num(hex, true),
+            None => is_num(src, false),
+        }
+    })
+}
+
+// float ::= sign? (num '.' frac? | num ('.' frac?)? ('E' | 'e') sign? num
+//                 | '0x' hexnum '.' hexfrac? | '0x' hexnum ('.' hexfrac?)? ('P' | 'p') sign? num
+//                 | 'inf' | 'nan' | 'nan:0x' hexnum)
+fn float(input: &str) -> Option<u32> {
+    token(input, |src| {
+        let src = strip_sign(src);
+        if src == "inf" || src == "nan" {
+            return true;
+        }
+        if let Some(payload) = src.strip_prefix("nan:0x") {
+            return is_num(payload, true);
+        }
+        let (hex, src) = match src.strip_prefix("0x") {
+            Some(src) => (true, src),
+            None => (false, src),
+        };
+        let integral = digits(src, hex);
+        if integral == 0 {
+            return false;
+        }
+        let mut rest = &src[integral..];
+        let dot = rest.starts_with('.');
+        if dot {
+            rest = &rest[1..];
+            rest = &rest[digits(rest, hex)..];
+        }
+        let marks = if hex { ['p', 'P'] } else { ['e', 'E'] };
+        match rest.strip_prefix(marks) {
+            Some(exponent) => is_num(strip_sign(exponent), false),
+            None => dot && rest.is_empty(),
+        }
+    })
+}
+
+// keyword ::= ('a' ... 'z') idchar*
+fn keyword(input: &str) -> Option<u32> {
+    token(input, |src| src.starts_with(|c: char| c.is_ascii_lowercase()))
+}
+
+// id ::= '$' idchar+
+fn identifier(input: &str) -> Option<u32> {
+    token(input, |src| src.len() > 1 && src.starts_with('$'))
+}
+
+// reserved ::= idchar+
+// Reserved tokens that look like numbers are malformed numbers.
+fn reserved(input: &str) -> Option<u32> {
+    token(input, |src| !is_number_like(src))
+}
+
+// Matches the leading idchars of `input` if they satisfy `accept`.
+fn token(input: &str, accept: impl Fn(&str) -> bool) -> Option<u32> {
+    let len = idchars(input);
+    (len > 0 && accept(&input[..len])).then_some(len as u32)
+}
+
+pub(super) fn idchars(input: &str) -> usize {
+    input.find(|c| !is_idchar(c)).unwrap_or(input.len())
+}
+
+// idchar ::= '0' ... '9' | 'A' ... 'Z' | 'a' ... 'z' | '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+'
+//          | '-' | '.' | '/' | ':' | '<' | '=' | '>' | '?' | '@' | '\\' | '^' | '_' | '`' | '|' | '~'
+fn is_idchar(c: char) -> bool {
+    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+' | '-' | '.' | '/'
+        | ':' | '<' | '=' | '>' | '?' | '@' | '\\' | '^' | '_' | '`' | '|' | '~')
+}
+
+fn is_number_like(src: &str) -> bool {
+    src.trim_start_matches(['+', '-']).starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn strip_sign(src: &str) -> &str {
+    src.strip_prefix(['+', '-']).unwrap_or(src)
+}
+
+fn is_num(src: &str, hex: bool) -> bool {
+    let len = digits(src, hex);
+    len > 0 && len == src.len()
+}
+
+// num ::= digit ('_'? digit)*
+// Returns the length of the digits at the start of `src`, 0 if there are none.
+fn digits(src: &str, hex: bool) -> usize {
+    let mut len = 0;
+    let mut after_digit = false;
+    for (pos, c) in src.char_indices() {
+        if c.is_ascii_digit() || (hex && c.is_ascii_hexdigit()) {
+            after_digit = true;
+            len = pos + 1;
+        } else if c == '_' && after_digit {
+            after_digit = false;
+        } else {
+            break;
+        }
+    }
+    len
+}
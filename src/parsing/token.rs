@@ -1,63 +1,60 @@
 use std::fmt::{
+    self,
     Debug,
     Display,
     Formatter
 };
-use std::ops::{Index, Range};
 
-#[derive(Eq, PartialEq, Debug, Clone, Copy, Default, Hash)]
-pub struct Span {
-    // inclusive
-    pub start: u32,
-    // exclusive
-    pub end: u32,
-}
+pub use crate::token::Span;
 
-impl From<Span> for Range<usize> {
-    fn from(span: Span) -> Self {
-        span.start as usize..span.end as usize
-    }
+// Kinds of tokens that rules match. Tokens carry no payload, their text is the source they
+// span.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+pub enum TokenKind {
+    LeftParen,
+    RightParen,
+    // Literals
+    Identifier,
+    String,
+    Integer,
+    Float,
+    Keyword,
+    Reserved,
+    // Keywords of script commands in `.wast` files
+    Directive,
+    // Trivia
+    Whitespace,
+    LineComment,
+    BlockComment,
 }
 
-impl From<Range<usize>> for Span {
-    fn from(range: Range<usize>) -> Self {
-        Self {
-            start: range.start as u32,
-            end: range.end as u32,
-        }
+impl TokenKind {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment)
     }
 }
 
-impl Index<Span> for str {
-    type Output = str;
-
-    fn index(&self, index: Span) -> &Self::Output {
-        &self[Range::<usize>::from(index)]
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TokenKind::LeftParen => "`(`",
+            TokenKind::RightParen => "`)`",
+            TokenKind::Identifier => "identifier",
+            TokenKind::String => "string",
+            TokenKind::Integer => "integer",
+            TokenKind::Float => "float",
+            TokenKind::Keyword => "keyword",
+            TokenKind::Reserved => "reserved token",
+            TokenKind::Directive => "directive",
+            TokenKind::Whitespace => "whitespace",
+            TokenKind::LineComment => "line comment",
+            TokenKind::BlockComment => "block comment",
+        };
+        write!(f, "{}", name)
     }
 }
 
-#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
-pub enum TokenKind {
-    // Keywords
-    Let,
-    Fn,
-    If,
-    Else,
-    Return,
-    // Literals
-    IntLiteral(i32),
-    FloatLiteral(f32),
-    StringLiteral(String),
-    // Identifiers
-    Identifier(String),
-    // Misc
-    Whitespace,
-    Comment,
-    Newline,
-    Eof,
-}
-
-#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+#[derive(Eq, PartialEq, Clone, Copy, Hash)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
@@ -68,6 +65,10 @@ impl Token {
         (self.span.end - self.span.start) as usize
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.span.start == self.span.end
+    }
+
     pub fn text<'input>(&self, input: &'input str) -> &'input str {
         &input[self.span]
     }
@@ -83,4 +84,4 @@ impl Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.kind)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,648 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::ast::{
+    BlockType, DataMode, ElemMode, ExportDesc, Func, GlobalType, ImportDesc, Instr, Limits, MemArg, Module, NameMap,
+    Names, NumberType, ReferenceType, TableType, ValueType, VectorType,
+};
+use crate::lexer::Lexer;
+use crate::runtime::loader;
+use crate::validator;
+
+// Renders `module` in the text format, with its instructions either flat or folded into
+// s-expressions. Definitions are annotated with their index as a `(;N;)` comment, unless the
+// `name` custom section gives them a name that can be written as an identifier.
+pub fn print(module: &Module, folded: bool) -> String {
+    let names = module.customs.iter()
+        .find(|custom| custom.name == "name")
+        .and_then(|custom| loader::decode_names(&custom.data).ok())
+        .unwrap_or_default();
+    let ids = Names {
+        module: names.module.map(|name| format!("${}", name)).filter(|id| Lexer::is_identifier(id)),
+        funcs: identifiers(&names.funcs),
+        locals: names.locals.iter().map(|(idx, locals)| (*idx, identifiers(locals))).collect(),
+        types: identifiers(&names.types),
+        tables: identifiers(&names.tables),
+        mems: identifiers(&names.mems),
+        globals: identifiers(&names.globals),
+        elems: identifiers(&names.elems),
+        datas: identifiers(&names.datas),
+    };
+    let func_types = module.imports.iter()
+        .filter_map(|import| match import.desc {
+            ImportDesc::Func(type_idx) => Some(type_idx),
+            _ => None,
+        })
+        .chain(module.funcs.iter().map(|func| func.f_type))
+        .collect();
+
+    let mut printer = Printer {
+        module,
+        ids,
+        folded,
+        func_types,
+        local_ids: NameMap::new(),
+        labels: vec![],
+        results: 0,
+        out: String::new(),
+    };
+    printer.module();
+    printer.out
+}
+
+// Keeps the names that are valid identifiers, dropping repeated ones so that every identifier
+// refers to a single index.
+fn identifiers(names: &NameMap) -> NameMap {
+    let mut seen = HashSet::new();
+    names.iter()
+        .map(|(idx, name)| (*idx, format!("${}", name)))
+        .filter(|(_, id)| Lexer::is_identifier(id) && seen.insert(id.clone()))
+        .collect()
+}
+
+// A folded instruction and the number of values it leaves on the stack, `None` when it cannot
+// be the operand of another instruction.
+struct Node {
+    lines: Vec<String>,
+    results: Option<usize>,
+}
+
+struct Printer<'a> {
+    module: &'a Module,
+    ids: Names,
+    folded: bool,
+    // Type index of every function, imports first
+    func_types: Vec<usize>,
+    // Identifiers of the locals of the function being printed
+    local_ids: NameMap,
+    // Branch arities of the enclosing blocks, innermost last
+    labels: Vec<usize>,
+    // Number of results of the function being printed
+    results: usize,
+    out: String,
+}
+
+impl<'a> Printer<'a> {
+    fn module(&mut self) {
+        let module = self.module;
+        match &self.ids.module {
+            Some(id) => self.line(0, &format!("(module {}", id)),
+            None => self.line(0, "(module"),
+        }
+
+        for (idx, (params, results)) in module.types.iter().enumerate() {
+            let signature = value_types("param", params, 0, None) + &value_types("result", results, 0, None);
+            self.line(1, &format!("(type {} (func{}))", binder(&self.ids.types, idx), signature));
+        }
+
+        // Imports come first in their index spaces
+        let (mut funcs, mut tables, mut mems, mut globals) = (0, 0, 0, 0);
+        for import in &module.imports {
+            let desc = match &import.desc {
+                ImportDesc::Func(type_idx) => {
+                    funcs += 1;
+                    format!("(func {} {})", binder(&self.ids.funcs, funcs - 1), self.type_use(funcs - 1, *type_idx))
+                },
+                ImportDesc::Table(table_type) => {
+                    tables += 1;
+                    format!("(table {} {})", binder(&self.ids.tables, tables - 1), self.table_type(table_type))
+                },
+                ImportDesc::Mem(mem_type) => {
+                    mems += 1;
+                    format!("(memory {} {})", binder(&self.ids.mems, mems - 1), limits(mem_type))
+                },
+                ImportDesc::Global(global_type) => {
+                    globals += 1;
+                    format!("(global {} {})", binder(&self.ids.globals, globals - 1), self.global_type(global_type))
+                },
+            };
+            let text = format!("(import {} {} {})", string(import.module.as_bytes()), string(import.name.as_bytes()), desc);
+            self.line(1, &text);
+        }
+
+        for (idx, func) in module.funcs.iter().enumerate() {
+            self.func(funcs + idx, func);
+        }
+        for (idx, table) in module.tables.iter().enumerate() {
+            let text = format!("(table {} {})", binder(&self.ids.tables, tables + idx), self.table_type(&table.table_type));
+            self.line(1, &text);
+        }
+        for (idx, mem) in module.mems.iter().enumerate() {
+            let text = format!("(memory {} {})", binder(&self.ids.mems, mems + idx), limits(&mem.mem_type));
+            self.line(1, &text);
+        }
+        for (idx, global) in module.globals.iter().enumerate() {
+            let text = format!("(global {} {} {})", binder(&self.ids.globals, globals + idx),
+                self.global_type(&global.global_type), self.inline_expr(&global.init));
+            self.line(1, &text);
+        }
+
+        for export in &module.exports {
+            let desc = match export.desc {
+                ExportDesc::Func(idx) => format!("func {}", index(&self.ids.funcs, idx)),
+                ExportDesc::Table(idx) => format!("table {}", index(&self.ids.tables, idx)),
+                ExportDesc::Mem(idx) => format!("memory {}", index(&self.ids.mems, idx)),
+                ExportDesc::Global(idx) => format!("global {}", index(&self.ids.globals, idx)),
+            };
+            self.line(1, &format!("(export {} ({}))", string(export.name.as_bytes()), desc));
+        }
+        if let Some(start) = &module.start {
+            self.line(1, &format!("(start {})", index(&self.ids.funcs, start.func)));
+        }
+
+        for (idx, elem) in module.elem.iter().enumerate() {
+            let mode = match &elem.mode {
+                ElemMode::Passive => String::new(),
+                ElemMode::Active { table, offset } => {
+                    format!(" (table {}) (offset {})", index(&self.ids.tables, *table), self.inline_expr(offset))
+                },
+                ElemMode::Declarative => " declare".to_string(),
+            };
+            let items: String = elem.init.iter()
+                .map(|item| format!(" (item {})", self.inline_expr(item)))
+                .collect();
+            let text = format!("(elem {}{} {}{})", binder(&self.ids.elems, idx), mode, ref_type(elem.elem_type), items);
+            self.line(1, &text);
+        }
+        for (idx, data) in module.data.iter().enumerate() {
+            let mode = match &data.mode {
+                DataMode::Passive => String::new(),
+                DataMode::Active { memory, offset } => {
+                    format!(" (memory {}) (offset {})", index(&self.ids.mems, *memory), self.inline_expr(offset))
+                },
+            };
+            let text = format!("(data {}{} {})", binder(&self.ids.datas, idx), mode, string(&data.init));
+            self.line(1, &text);
+        }
+
+        self.close();
+    }
+
+    // func ::= '(' 'func' id? typeuse local* instr* ')'
+    fn func(&mut self, idx: usize, func: &Func) {
+        let header = format!("(func {} {}", binder(&self.ids.funcs, idx), self.type_use(idx, func.f_type));
+        self.line(1, &header);
+
+        let (params, results) = self.type_arity(func.f_type);
+        self.local_ids = self.ids.locals.get(&idx).cloned().unwrap_or_default();
+        self.results = results;
+        let locals = value_types("local", &func.locals, params, Some(&self.local_ids));
+        if !locals.is_empty() {
+            self.line(2, locals.trim_start());
+        }
+        for line in self.instrs(&func.body) {
+            self.line(2, &line);
+        }
+        self.close();
+        self.local_ids.clear();
+    }
+
+    // The type index followed by the parameters and results it stands for, naming the
+    // parameters of function `func`.
+    fn type_use(&self, func: usize, type_idx: usize) -> String {
+        let mut text = format!("(type {})", index(&self.ids.types, type_idx));
+        if let Some((params, results)) = self.module.types.get(type_idx) {
+            text += &value_types("param", params, 0, self.ids.locals.get(&func));
+            text += &value_types("result", results, 0, None);
+        }
+        text
+    }
+
+    fn table_type(&self, table_type: &TableType) -> String {
+        format!("{} {}", limits(&table_type.limits), ref_type(table_type.elem_type))
+    }
+
+    fn global_type(&self, global_type: &GlobalType) -> String {
+        if global_type.mutable {
+            format!("(mut {})", value_type(global_type.value_type))
+        } else {
+            value_type(global_type.value_type).to_string()
+        }
+    }
+
+    // Constant expressions fit on the line of the field that contains them.
+    fn inline_expr(&mut self, instrs: &[Instr]) -> String {
+        let lines = self.instrs(instrs);
+        lines.iter().map(|line| line.trim()).collect::<Vec<_>>().join(" ")
+    }
+
+    // Returns the lines of an instruction sequence, indented relative to its first line.
+    fn instrs(&mut self, instrs: &[Instr]) -> Vec<String> {
+        if self.folded {
+            self.folded_instrs(instrs)
+        } else {
+            let mut lines = vec![];
+            self.flat_instrs(instrs, &mut lines, 0);
+            lines
+        }
+    }
+
+    fn flat_instrs(&mut self, instrs: &[Instr], lines: &mut Vec<String>, depth: usize) {
+        let pad = "  ".repeat(depth);
+        for instr in instrs {
+            match instr {
+                Instr::Block(block_type, body) | Instr::Loop(block_type, body) => {
+                    let keyword = if matches!(instr, Instr::Block(..)) { "block" } else { "loop" };
+                    lines.push(format!("{}{}{}", pad, keyword, self.block_type(block_type)));
+                    self.flat_instrs(body, lines, depth + 1);
+                    lines.push(format!("{}end", pad));
+                },
+                Instr::If(block_type, then, otherwise) => {
+                    lines.push(format!("{}if{}", pad, self.block_type(block_type)));
+                    self.flat_instrs(then, lines, depth + 1);
+                    if !otherwise.is_empty() {
+                        lines.push(format!("{}else", pad));
+                        self.flat_instrs(otherwise, lines, depth + 1);
+                    }
+                    lines.push(format!("{}end", pad));
+                },
+                _ => lines.push(format!("{}{}", pad, self.instr(instr))),
+            }
+        }
+    }
+
+    // Folds every instruction with the preceding instructions that produce exactly its
+    // operands. Unfolding gives back the original sequence, so the arities only decide how
+    // readable the result is.
+    fn folded_instrs(&mut self, instrs: &[Instr]) -> Vec<String> {
+        let mut nodes: Vec<Node> = vec![];
+        for instr in instrs {
+            let (params, results) = self.arity(instr);
+            let mut count = 0;
+            let mut start = nodes.len();
+            while count < params && start > 0 {
+                match nodes[start - 1].results {
+                    Some(n) if n > 0 => count += n,
+                    _ => break,
+                }
+                start -= 1;
+            }
+            let operands = if count == params { nodes.split_off(start) } else { vec![] };
+            let node = self.node(instr, operands, results);
+            nodes.push(node);
+        }
+        nodes.into_iter().flat_map(|node| node.lines).collect()
+    }
+
+    // plaininstr ::= '(' instr foldedinstr* ')'
+    // blockinstr ::= '(' 'block' blocktype instr* ')' | '(' 'loop' blocktype instr* ')'
+    //              | '(' 'if' blocktype foldedinstr* '(' 'then' instr* ')' ('(' 'else' instr* ')')? ')'
+    fn node(&mut self, instr: &Instr, operands: Vec<Node>, results: Option<usize>) -> Node {
+        let mut lines = match instr {
+            Instr::Block(block_type, body) | Instr::Loop(block_type, body) => {
+                let keyword = if matches!(instr, Instr::Block(..)) { "block" } else { "loop" };
+                let (params, results) = self.block_arity(block_type);
+                self.labels.push(if keyword == "loop" { params } else { results });
+                let mut lines = vec![format!("({}{}", keyword, self.block_type(block_type))];
+                lines.extend(indent(self.folded_instrs(body)));
+                self.labels.pop();
+                lines
+            },
+            Instr::If(block_type, then, otherwise) => {
+                let (_, results) = self.block_arity(block_type);
+                self.labels.push(results);
+                let mut lines = vec![format!("(if{}", self.block_type(block_type))];
+                lines.extend(indent(operands.into_iter().flat_map(|node| node.lines).collect()));
+                let mut then_lines = vec!["(then".to_string()];
+                then_lines.extend(indent(self.folded_instrs(then)));
+                close(&mut then_lines);
+                lines.extend(indent(then_lines));
+                if !otherwise.is_empty() {
+                    let mut else_lines = vec!["(else".to_string()];
+                    else_lines.extend(indent(self.folded_instrs(otherwise)));
+                    close(&mut else_lines);
+                    lines.extend(indent(else_lines));
+                }
+                self.labels.pop();
+                lines
+            },
+            _ => {
+                let mut lines = vec![format!("({}", self.instr(instr))];
+                lines.extend(indent(operands.into_iter().flat_map(|node| node.lines).collect()));
+                lines
+            },
+        };
+        close(&mut lines);
+        Node { lines, results }
+    }
+
+    // Returns the number of operands that can be folded into `instr` and the number of values
+    // it produces.
+    fn arity(&self, instr: &Instr) -> (usize, Option<usize>) {
+        let (params, results) = match instr {
+            // Block parameters are taken from outside the folded block
+            Instr::Block(block_type, _) | Instr::Loop(block_type, _) => {
+                let (params, results) = self.block_arity(block_type);
+                return (0, if params == 0 { Some(results) } else { None });
+            },
+            Instr::If(block_type, ..) => {
+                let (params, results) = self.block_arity(block_type);
+                return if params == 0 { (1, Some(results)) } else { (0, None) };
+            },
+            Instr::Unreachable | Instr::Nop => (0, 0),
+            Instr::Br(label) => (self.label_arity(*label), 0),
+            Instr::BrIf(label) => (self.label_arity(*label) + 1, self.label_arity(*label)),
+            Instr::BrTable(_, default) => (self.label_arity(*default) + 1, 0),
+            Instr::Return => (self.results, 0),
+            Instr::Call(idx) => self.func_types.get(*idx).map_or((0, 0), |type_idx| self.type_arity(*type_idx)),
+            Instr::CallIndirect(_, type_idx) => {
+                let (params, results) = self.type_arity(*type_idx);
+                (params + 1, results)
+            },
+            Instr::RefNull(_) | Instr::RefFunc(_) => (0, 1),
+            Instr::RefIsNull => (1, 1),
+            Instr::Drop => (1, 0),
+            Instr::Select(_) => (3, 1),
+            Instr::LocalGet(_) | Instr::GlobalGet(_) => (0, 1),
+            Instr::LocalSet(_) | Instr::GlobalSet(_) => (1, 0),
+            Instr::LocalTee(_) => (1, 1),
+            Instr::TableGet(_) => (1, 1),
+            Instr::TableSet(_) => (2, 0),
+            Instr::TableSize(_) | Instr::MemorySize => (0, 1),
+            Instr::TableGrow(_) => (2, 1),
+            Instr::MemoryGrow => (1, 1),
+            Instr::TableFill(_) | Instr::TableCopy(..) | Instr::TableInit(..) | Instr::MemoryFill
+            | Instr::MemoryCopy | Instr::MemoryInit(_) => (3, 0),
+            Instr::ElemDrop(_) | Instr::DataDrop(_) => (0, 0),
+            Instr::I32Const(_) | Instr::I64Const(_) | Instr::F32Const(_) | Instr::F64Const(_)
+            | Instr::V128Const(_) => (0, 1),
+            Instr::I8x16Shuffle(_) => (2, 1),
+            _ => validator::table_signature(instr).map_or((0, 0), |(params, results)| (params.len(), results.len())),
+        };
+        (params, Some(results))
+    }
+
+    fn label_arity(&self, label: usize) -> usize {
+        // The outermost label is the function body
+        self.labels.iter().rev().chain([&self.results]).nth(label).copied().unwrap_or(0)
+    }
+
+    fn block_arity(&self, block_type: &BlockType) -> (usize, usize) {
+        match block_type {
+            BlockType::Empty => (0, 0),
+            BlockType::Value(_) => (0, 1),
+            BlockType::Type(idx) => self.type_arity(*idx),
+        }
+    }
+
+    fn type_arity(&self, type_idx: usize) -> (usize, usize) {
+        self.module.types.get(type_idx).map_or((0, 0), |(params, results)| (params.len(), results.len()))
+    }
+
+    // blocktype ::= (result valtype)? | typeuse
+    fn block_type(&self, block_type: &BlockType) -> String {
+        match block_type {
+            BlockType::Empty => String::new(),
+            BlockType::Value(value_type) => format!(" (result {})", self::value_type(*value_type)),
+            BlockType::Type(idx) => format!(" (type {})", index(&self.ids.types, *idx)),
+        }
+    }
+
+    // Returns an instruction other than a block with its immediates.
+    fn instr(&self, instr: &Instr) -> String {
+        let ids = &self.ids;
+        match instr {
+            // Control instructions, labels are written as relative depths
+            Instr::Br(label) => format!("br {}", label),
+            Instr::BrIf(label) => format!("br_if {}", label),
+            Instr::BrTable(labels, default) => {
+                let labels: Vec<_> = labels.iter().chain([default]).map(ToString::to_string).collect();
+                format!("br_table {}", labels.join(" "))
+            },
+            Instr::Call(idx) => format!("call {}", index(&ids.funcs, *idx)),
+            Instr::CallIndirect(table, type_idx) => {
+                format!("call_indirect {} (type {})", index(&ids.tables, *table), index(&ids.types, *type_idx))
+            },
+
+            // Reference instructions
+            Instr::RefNull(ReferenceType::FuncRef) => "ref.null func".to_string(),
+            Instr::RefNull(ReferenceType::ExternRef) => "ref.null extern".to_string(),
+            Instr::RefFunc(idx) => format!("ref.func {}", index(&ids.funcs, *idx)),
+
+            // Parametric instructions
+            Instr::Select(None) => "select".to_string(),
+            Instr::Select(Some(types)) => format!("select{}", value_types("result", types, 0, None)),
+
+            // Variable instructions
+            Instr::LocalGet(idx) => format!("local.get {}", index(&self.local_ids, *idx)),
+            Instr::LocalSet(idx) => format!("local.set {}", index(&self.local_ids, *idx)),
+            Instr::LocalTee(idx) => format!("local.tee {}", index(&self.local_ids, *idx)),
+            Instr::GlobalGet(idx) => format!("global.get {}", index(&ids.globals, *idx)),
+            Instr::GlobalSet(idx) => format!("global.set {}", index(&ids.globals, *idx)),
+
+            // Table instructions
+            Instr::TableGet(idx) => format!("table.get {}", index(&ids.tables, *idx)),
+            Instr::TableSet(idx) => format!("table.set {}", index(&ids.tables, *idx)),
+            Instr::TableSize(idx) => format!("table.size {}", index(&ids.tables, *idx)),
+            Instr::TableGrow(idx) => format!("table.grow {}", index(&ids.tables, *idx)),
+            Instr::TableFill(idx) => format!("table.fill {}", index(&ids.tables, *idx)),
+            Instr::TableCopy(dst, src) => {
+                format!("table.copy {} {}", index(&ids.tables, *dst), index(&ids.tables, *src))
+            },
+            Instr::TableInit(table, elem) => {
+                format!("table.init {} {}", index(&ids.tables, *table), index(&ids.elems, *elem))
+            },
+            Instr::ElemDrop(idx) => format!("elem.drop {}", index(&ids.elems, *idx)),
+
+            // Memory instructions
+            Instr::MemorySize => "memory.size".to_string(),
+            Instr::MemoryGrow => "memory.grow".to_string(),
+            Instr::MemoryFill => "memory.fill".to_string(),
+            Instr::MemoryCopy => "memory.copy".to_string(),
+            Instr::MemoryInit(idx) => format!("memory.init {}", index(&ids.datas, *idx)),
+            Instr::DataDrop(idx) => format!("data.drop {}", index(&ids.datas, *idx)),
+
+            // Numeric instructions
+            Instr::I32Const(value) => format!("i32.const {}", value),
+            Instr::I64Const(value) => format!("i64.const {}", value),
+            Instr::F32Const(bits) => format!("f32.const {}", f32(*bits)),
+            Instr::F64Const(bits) => format!("f64.const {}", f64(*bits)),
+
+            // Vector instructions
+            Instr::V128Const(value) => {
+                let lanes: Vec<_> = (0..4).map(|lane| format!("0x{:08x}", (value >> (lane * 32)) as u32)).collect();
+                format!("v128.const i32x4 {}", lanes.join(" "))
+            },
+            Instr::I8x16Shuffle(lanes) => {
+                let lanes: Vec<_> = lanes.iter().map(ToString::to_string).collect();
+                format!("i8x16.shuffle {}", lanes.join(" "))
+            },
+
+            _ => table_instr(instr),
+        }
+    }
+
+    fn line(&mut self, depth: usize, text: &str) {
+        for _ in 0..depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    // Closes the last field on the line it ends on.
+    fn close(&mut self) {
+        self.out.pop();
+        self.out.push_str(")\n");
+    }
+}
+
+// Returns an instruction described by the tables of `ast::Instr` with its immediates.
+fn table_instr(instr: &Instr) -> String {
+    if let Some((_, name, _)) = instr.plain_info() {
+        name.to_string()
+    } else if let Some((_, name, natural_align)) = instr.memory_info() {
+        format!("{}{}", name, memarg(instr.memarg().unwrap(), natural_align))
+    } else if let Some((_, name, (natural_align, _))) = instr.memory_lane_info() {
+        format!("{}{} {}", name, memarg(instr.memarg().unwrap(), natural_align), instr.lane().unwrap())
+    } else if let Some((_, name, _)) = instr.lane_info() {
+        format!("{} {}", name, instr.lane().unwrap())
+    } else {
+        unreachable!("{:?} is not in an instruction table", instr);
+    }
+}
+
+// memarg ::= ('offset=' u32)? ('align=' u32)?
+// Both are left out when they have their default value.
+fn memarg(memarg: &MemArg, natural_align: u32) -> String {
+    let mut text = String::new();
+    if memarg.offset != 0 {
+        write!(text, " offset={}", memarg.offset).unwrap();
+    }
+    if memarg.align != natural_align {
+        write!(text, " align={}", 1u64.checked_shl(memarg.align).unwrap_or(0)).unwrap();
+    }
+    text
+}
+
+fn indent(lines: Vec<String>) -> Vec<String> {
+    lines.into_iter().map(|line| format!("  {}", line)).collect()
+}
+
+fn close(lines: &mut [String]) {
+    if let Some(last) = lines.last_mut() {
+        last.push(')');
+    }
+}
+
+// The identifier of a definition, or its index as a comment.
+fn binder(ids: &NameMap, idx: usize) -> String {
+    ids.get(&idx).cloned().unwrap_or_else(|| format!("(;{};)", idx))
+}
+
+// A reference to a definition by identifier, or by index.
+fn index(ids: &NameMap, idx: usize) -> String {
+    ids.get(&idx).cloned().unwrap_or_else(|| idx.to_string())
+}
+
+// Writes ` (keyword t*)` for a list of parameters, results or locals, with one field per
+// type when some of them have an identifier. `first` is the index of the first type.
+fn value_types(keyword: &str, types: &[ValueType], first: usize, ids: Option<&NameMap>) -> String {
+    if types.is_empty() {
+        return String::new();
+    }
+    let id = |i: usize| ids.and_then(|ids| ids.get(&(first + i)));
+    if (0..types.len()).any(|i| id(i).is_some()) {
+        types.iter().enumerate()
+            .map(|(i, t)| match id(i) {
+                Some(id) => format!(" ({} {} {})", keyword, id, value_type(*t)),
+                None => format!(" ({} {})", keyword, value_type(*t)),
+            })
+            .collect()
+    } else {
+        let types: Vec<_> = types.iter().map(|t| value_type(*t)).collect();
+        format!(" ({} {})", keyword, types.join(" "))
+    }
+}
+
+fn value_type(value_type: ValueType) -> &'static str {
+    match value_type {
+        ValueType::NumberType(NumberType::I32) => "i32",
+        ValueType::NumberType(NumberType::I64) => "i64",
+        ValueType::NumberType(NumberType::F32) => "f32",
+        ValueType::NumberType(NumberType::F64) => "f64",
+        ValueType::VectorType(VectorType::V128) => "v128",
+        ValueType::ReferenceType(ref_type) => self::ref_type(ref_type),
+    }
+}
+
+fn ref_type(ref_type: ReferenceType) -> &'static str {
+    match ref_type {
+        ReferenceType::FuncRef => "funcref",
+        ReferenceType::ExternRef => "externref",
+    }
+}
+
+// limits ::= u32 u32?
+fn limits(limits: &Limits) -> String {
+    match limits.max {
+        Some(max) => format!("{} {}", limits.min, max),
+        None => limits.min.to_string(),
+    }
+}
+
+// Writes `bytes` as a string literal, escaping everything but printable ASCII.
+fn string(bytes: &[u8]) -> String {
+    let mut text = String::from('"');
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                text.push('\\');
+                text.push(byte as char);
+            },
+            0x20..=0x7E => text.push(byte as char),
+            _ => write!(text, "\\{:02x}", byte).unwrap(),
+        }
+    }
+    text.push('"');
+    text
+}
+
+// Formats the bit pattern of an f32 so that it reads back as the same bits. Finite values use
+// the shortest decimal that rounds to them, with an exponent if they are very large or small.
+fn f32(bits: u32) -> String {
+    let sign = if bits >> 31 == 1 { "-" } else { "" };
+    let payload = bits & 0x7F_FFFF;
+    if bits & 0x7F80_0000 != 0x7F80_0000 {
+        format!("{}{:?}", sign, f32::from_bits(bits & 0x7FFF_FFFF))
+    } else if payload == 0 {
+        format!("{}inf", sign)
+    } else if payload == 0x40_0000 {
+        format!("{}nan", sign)
+    } else {
+        format!("{}nan:0x{:x}", sign, payload)
+    }
+}
+
+fn f64(bits: u64) -> String {
+    let sign = if bits >> 63 == 1 { "-" } else { "" };
+    let payload = bits & 0xF_FFFF_FFFF_FFFF;
+    if bits & 0x7FF0_0000_0000_0000 != 0x7FF0_0000_0000_0000 {
+        format!("{}{:?}", sign, f64::from_bits(bits & 0x7FFF_FFFF_FFFF_FFFF))
+    } else if payload == 0 {
+        format!("{}inf", sign)
+    } else if payload == 0x8_0000_0000_0000 {
+        format!("{}nan", sign)
+    } else {
+        format!("{}nan:0x{:x}", sign, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_floats() {
+        assert_eq!(f32(0x7FC0_0000), "nan");
+        assert_eq!(f32(0xFF80_0001), "-nan:0x1");
+        assert_eq!(f32(0xFF80_0000), "-inf");
+        assert_eq!(f32(0.1f32.to_bits()), "0.1");
+        assert_eq!(f64(0x7FF8_0000_0000_0000), "nan");
+        assert_eq!(f64(0x7FF4_0000_0000_0000), "nan:0x4000000000000");
+        assert_eq!(f64((-0.0f64).to_bits()), "-0.0");
+        assert_eq!(f64(1e300f64.to_bits()), "1e300");
+        assert_eq!(f32(1e-45f32.to_bits()), "1e-45");
+        assert_eq!(f64(1.5f64.to_bits()), "1.5");
+    }
+}
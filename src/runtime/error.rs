@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+pub enum RuntimeError {
+    InvalidModuleLength,
+    InvalidMagicNumber,
+    InvalidVersionNumber,
+    InvalidSectionCode,
+    InvalidSectionSize,
+    InvalidSectionOrder,
+    InvalidFunctionType,
+    FunctionCodeMismatch,
+    DataCountMismatch,
+    DataCountRequired,
+    InvalidName,
+    IntegerTooLong,
+    IntegerTooLarge,
+    TooManyLocals,
+    InvalidValueType,
+    InvalidExportType,
+    InvalidExportName,
+    InvalidImportType,
+    InvalidLimits,
+    InvalidMutability,
+    InvalidSegmentKind,
+    InvalidInstruction,
+    InvalidBlockType,
+    NestingTooDeep,
+    ZeroByteExpected,
+    ExportNotFound,
+    UnknownImport { module: String, name: String },
+    IncompatibleImportType { module: String, name: String },
+    InvalidArgNumber,
+    InvalidArgType,
+    InvalidLocalIndex,
+    InvalidFunctionIndex,
+    InvalidMemoryIndex,
+    InvalidGlobalIndex,
+    InvalidTableIndex,
+    InvalidElemIndex,
+    InvalidDataIndex,
+    ImmutableGlobal,
+    InvalidHostResult,
+    TypeMismatch,
+    UnsupportedInstruction,
+    UnexpectedEof { offset: usize },
+}
+
+impl RuntimeError {
+    fn message(&self) -> &str {
+        match self {
+            Self::InvalidModuleLength => "Invalid module length",
+            Self::InvalidMagicNumber => "Magic header not detected",
+            Self::InvalidVersionNumber => "Unknown binary version",
+            Self::InvalidSectionCode => "Invalid section code",
+            Self::InvalidSectionSize => "Invalid section size",
+            Self::InvalidSectionOrder => "Invalid section order",
+            Self::InvalidFunctionType => "Invalid function type",
+            Self::FunctionCodeMismatch => "Function and code section have inconsistent lengths",
+            Self::DataCountMismatch => "Data count and data section have inconsistent lengths",
+            Self::DataCountRequired => "Data count section required",
+            Self::InvalidName => "Invalid UTF-8 name",
+            Self::IntegerTooLong => "Integer representation too long",
+            Self::IntegerTooLarge => "Integer too large",
+            Self::TooManyLocals => "Too many locals",
+            Self::InvalidValueType => "Invalid value type",
+            Self::InvalidExportType => "Invalid export type",
+            Self::InvalidExportName => "Invalid export name",
+            Self::InvalidImportType => "Invalid import type",
+            Self::InvalidLimits => "Invalid limits",
+            Self::InvalidMutability => "Invalid mutability",
+            Self::InvalidSegmentKind => "Invalid segment kind",
+            Self::InvalidInstruction => "Invalid instruction",
+            Self::InvalidBlockType => "Invalid block type",
+            Self::NestingTooDeep => "Blocks nested too deeply",
+            Self::ZeroByteExpected => "Zero byte expected",
+            Self::ExportNotFound => "Export not found",
+            Self::UnknownImport { .. } => "Unknown import",
+            Self::IncompatibleImportType { .. } => "Incompatible import type",
+            Self::InvalidArgNumber => "Invalid argument number",
+            Self::InvalidArgType => "Invalid argument type",
+            Self::InvalidLocalIndex => "Invalid local index",
+            Self::InvalidFunctionIndex => "Invalid function index",
+            Self::InvalidMemoryIndex => "Invalid memory index",
+            Self::InvalidGlobalIndex => "Invalid global index",
+            Self::InvalidTableIndex => "Invalid table index",
+            Self::InvalidElemIndex => "Invalid element segment index",
+            Self::InvalidDataIndex => "Invalid data segment index",
+            Self::ImmutableGlobal => "Global is immutable",
+            Self::InvalidHostResult => "Host function returned values of the wrong types",
+            Self::TypeMismatch => "Operand type mismatch",
+            Self::UnsupportedInstruction => "Unsupported instruction",
+            Self::UnexpectedEof { .. } => "Unexpected end of input",
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::UnexpectedEof { offset } => write!(f, "{} at offset {:#x}", self.message(), offset),
+            Self::UnknownImport { module, name } | Self::IncompatibleImportType { module, name } => {
+                write!(f, "{} `{}` `{}`", self.message(), module, name)
+            },
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl Debug for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+pub enum Trap {
+    Unreachable,
+    CallStackExhausted,
+    IntegerDivideByZero,
+    IntegerOverflow,
+    InvalidConversionToInteger,
+    OutOfBoundsMemoryAccess,
+    OutOfBoundsTableAccess,
+    UndefinedElement,
+    UninitializedElement,
+    IndirectCallTypeMismatch,
+    Runtime(RuntimeError),
+}
+
+impl Trap {
+    fn message(&self) -> &str {
+        match self {
+            Self::Unreachable => "Unreachable executed",
+            Self::CallStackExhausted => "Call stack exhausted",
+            Self::IntegerDivideByZero => "Integer divide by zero",
+            Self::IntegerOverflow => "Integer overflow",
+            Self::InvalidConversionToInteger => "Invalid conversion to integer",
+            Self::OutOfBoundsMemoryAccess => "Out of bounds memory access",
+            Self::OutOfBoundsTableAccess => "Out of bounds table access",
+            Self::UndefinedElement => "Undefined element",
+            Self::UninitializedElement => "Uninitialized element",
+            Self::IndirectCallTypeMismatch => "Indirect call type mismatch",
+            Self::Runtime(e) => e.message(),
+        }
+    }
+}
+
+impl From<RuntimeError> for Trap {
+    fn from(error: RuntimeError) -> Self {
+        Self::Runtime(error)
+    }
+}
+
+impl Error for Trap {}
+
+impl Display for Trap {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Runtime(e) => Display::fmt(e, f),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl Debug for Trap {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,23 @@
+use std::rc::Rc;
+
+use crate::runtime::store::StoreInner;
+use crate::runtime::{Memory, Trap, Value};
+
+// The code of a function defined by the embedder. It receives arguments matching the
+// parameter types of the function and has to return values matching its result types.
+pub(crate) type HostFunc = Rc<dyn Fn(&mut Caller, &[Value]) -> Result<Vec<Value>, Trap>>;
+
+// What a host function can reach of the instance calling it.
+pub struct Caller<'a> {
+    pub(crate) store: &'a mut StoreInner,
+    // `None` when the embedder invokes the host function directly
+    pub(crate) module: Option<usize>,
+}
+
+impl Caller<'_> {
+    // The memory of the calling instance, defined or imported, if it has one.
+    pub fn memory(&mut self) -> Option<&mut Memory> {
+        let addr = *self.store.modules[self.module?].mem_addrs.first()?;
+        Some(&mut self.store.mems[addr])
+    }
+}
@@ -0,0 +1,378 @@
+use std::cell::RefMut;
+use std::rc::Rc;
+
+use crate::ast::{DataMode, ElemMode, ExportDesc, FuncType, ImportDesc, Limits, Module};
+use crate::runtime::interpreter::{self, Code};
+use crate::runtime::store::{ExportInst, ExternVal, FuncInst, GlobalInst, ModuleInst, Store};
+use crate::runtime::{Memory, RuntimeError, Table, Trap, Value};
+
+// A module instantiated in a store. The instance only records the address of its module
+// instance, all runtime state lives in the store.
+pub struct Instance {
+    store: Store,
+    addr: usize,
+}
+
+impl Instance {
+    // Instantiates a module without imports, `Linker` resolves the imports of the others.
+    pub fn new(store: &Store, module: &Module) -> Result<Self, Trap> {
+        Self::with_imports(store, module, &[])
+    }
+
+    // Instantiates `module` with the external values `imports` supplies for its imports, in
+    // order. They come first in the index spaces of the module. Instantiation traps if a segment
+    // does not fit or the start function traps, errors are wrapped in `Trap::Runtime`.
+    pub(crate) fn with_imports(store: &Store, module: &Module, imports: &[ExternVal]) -> Result<Self, Trap> {
+        let mut inner = store.inner.borrow_mut();
+        let addr = inner.modules.len();
+
+        let mut func_addrs = vec![];
+        let mut table_addrs = vec![];
+        let mut mem_addrs = vec![];
+        let mut global_addrs = vec![];
+        for (idx, import) in module.imports.iter().enumerate() {
+            let unlinkable = |unknown: bool| -> Trap {
+                let (module, name) = (import.module.clone(), import.name.clone());
+                if unknown {
+                    RuntimeError::UnknownImport { module, name }.into()
+                } else {
+                    RuntimeError::IncompatibleImportType { module, name }.into()
+                }
+            };
+            let value = *imports.get(idx).ok_or_else(|| unlinkable(true))?;
+            match (&import.desc, value) {
+                (ImportDesc::Func(type_idx), ExternVal::Func(func_addr)) => {
+                    let func_type = module.types.get(*type_idx).ok_or(RuntimeError::InvalidFunctionType)?;
+                    if inner.funcs[func_addr].func_type() != func_type {
+                        return Err(unlinkable(false));
+                    }
+                    func_addrs.push(func_addr);
+                },
+                (ImportDesc::Table(table_type), ExternVal::Table(table_addr)) => {
+                    let table = &inner.tables[table_addr];
+                    if table.elem_type() != table_type.elem_type || !limits_match(table.size(), table.max(), &table_type.limits) {
+                        return Err(unlinkable(false));
+                    }
+                    table_addrs.push(table_addr);
+                },
+                (ImportDesc::Mem(limits), ExternVal::Mem(mem_addr)) => {
+                    let mem = &inner.mems[mem_addr];
+                    if !limits_match(mem.size(), mem.max(), limits) {
+                        return Err(unlinkable(false));
+                    }
+                    mem_addrs.push(mem_addr);
+                },
+                (ImportDesc::Global(global_type), ExternVal::Global(global_addr)) => {
+                    if inner.globals[global_addr].global_type != *global_type {
+                        return Err(unlinkable(false));
+                    }
+                    global_addrs.push(global_addr);
+                },
+                _ => return Err(unlinkable(false)),
+            }
+        }
+
+        for func in &module.funcs {
+            let func_type = module.types.get(func.f_type)
+                .ok_or(RuntimeError::InvalidFunctionType)?;
+            func_addrs.push(inner.funcs.len());
+            inner.funcs.push(FuncInst::Wasm {
+                func_type: func_type.clone(),
+                module: addr,
+                code: Rc::new(Code::new(func.locals.clone(), &func.body)),
+            });
+        }
+
+        for table in &module.tables {
+            table_addrs.push(inner.tables.len());
+            inner.tables.push(Table::new(&table.table_type));
+        }
+
+        for mem in &module.mems {
+            mem_addrs.push(inner.mems.len());
+            inner.mems.push(Memory::new(&mem.mem_type));
+        }
+
+        let mut data_addrs = vec![];
+        for data in &module.data {
+            data_addrs.push(inner.datas.len());
+            inner.datas.push(data.init.clone());
+        }
+
+        inner.modules.push(ModuleInst {
+            types: module.types.clone(),
+            func_addrs,
+            table_addrs,
+            mem_addrs,
+            global_addrs,
+            elem_addrs: vec![],
+            data_addrs,
+            exports: vec![],
+        });
+
+        // Initializers see the imported globals and the functions of the module
+        for global in &module.globals {
+            let value = interpreter::eval_const(&mut inner, addr, &global.init).map_err(|_| RuntimeError::TypeMismatch)?;
+            let global_addr = inner.globals.len();
+            inner.globals.push(GlobalInst { global_type: global.global_type, value });
+            inner.modules[addr].global_addrs.push(global_addr);
+        }
+
+        for elem in &module.elem {
+            let elems = elem.init.iter()
+                .map(|expr| interpreter::eval_const(&mut inner, addr, expr))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| RuntimeError::TypeMismatch)?;
+            let elem_addr = inner.elems.len();
+            inner.elems.push(elems);
+            inner.modules[addr].elem_addrs.push(elem_addr);
+        }
+
+        let instance = &inner.modules[addr];
+        let mut exports = vec![];
+        for export in &module.exports {
+            let value = match export.desc {
+                ExportDesc::Func(idx) => instance.func_addrs.get(idx).map(|addr| ExternVal::Func(*addr)),
+                ExportDesc::Mem(idx) => instance.mem_addrs.get(idx).map(|addr| ExternVal::Mem(*addr)),
+                ExportDesc::Global(idx) => instance.global_addrs.get(idx).map(|addr| ExternVal::Global(*addr)),
+                ExportDesc::Table(idx) => instance.table_addrs.get(idx).map(|addr| ExternVal::Table(*addr)),
+            };
+            let value = value.ok_or(RuntimeError::ExportNotFound)?;
+            exports.push(ExportInst { name: export.name.clone(), value });
+        }
+        inner.modules[addr].exports = exports;
+
+        // Active element segments are copied into their table like `table.init` and dropped
+        // afterwards, as are declarative segments which only declare function references
+        for (idx, elem) in module.elem.iter().enumerate() {
+            let elem_addr = inner.modules[addr].elem_addrs[idx];
+            match &elem.mode {
+                ElemMode::Active { table, offset } => {
+                    let offset = match interpreter::eval_const(&mut inner, addr, offset) {
+                        Ok(Value::I32(offset)) => offset as u32,
+                        _ => return Err(RuntimeError::TypeMismatch.into()),
+                    };
+                    let table_addr = *inner.modules[addr].table_addrs.get(*table).ok_or(RuntimeError::InvalidTableIndex)?;
+                    let elems = std::mem::take(&mut inner.elems[elem_addr]);
+                    inner.tables[table_addr].init(offset, &elems)?;
+                },
+                ElemMode::Declarative => inner.elems[elem_addr].clear(),
+                ElemMode::Passive => {},
+            }
+        }
+
+        // Active data segments are copied into their memory in order and dropped like active
+        // element segments. A segment that does not fit aborts the instantiation but leaves the
+        // segments before it in place.
+        for (idx, data) in module.data.iter().enumerate() {
+            if let DataMode::Active { memory, offset } = &data.mode {
+                let offset = match interpreter::eval_const(&mut inner, addr, offset) {
+                    Ok(Value::I32(offset)) => offset as u32 as usize,
+                    _ => return Err(RuntimeError::TypeMismatch.into()),
+                };
+                let mem_addr = *inner.modules[addr].mem_addrs.get(*memory).ok_or(RuntimeError::InvalidMemoryIndex)?;
+                let data_addr = inner.modules[addr].data_addrs[idx];
+                let bytes = std::mem::take(&mut inner.datas[data_addr]);
+                inner.mems[mem_addr].write(offset, &bytes)?;
+            }
+        }
+
+        // The start function runs once the segments are in place
+        if let Some(start) = &module.start {
+            let func_addr = *inner.modules[addr].func_addrs.get(start.func).ok_or(RuntimeError::InvalidFunctionIndex)?;
+            interpreter::invoke(&mut inner, func_addr, &[])?;
+        }
+
+        Ok(Self {
+            store: store.clone(),
+            addr,
+        })
+    }
+
+    // Returns the signature of the exported function `name`.
+    pub fn func_type(&self, name: &str) -> Result<FuncType, RuntimeError> {
+        let inner = self.store.inner.borrow();
+        let addr = self.func_addr(name)?;
+        Ok(inner.funcs[addr].func_type().clone())
+    }
+
+    // Calls the exported function `name` with `args` and returns its results.
+    pub fn invoke(&self, name: &str, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        let addr = self.func_addr(name)?;
+        let mut inner = self.store.inner.borrow_mut();
+
+        let (params, _) = inner.funcs[addr].func_type();
+        if params.len() != args.len() {
+            return Err(RuntimeError::InvalidArgNumber.into());
+        }
+        if params.iter().zip(args).any(|(param, arg)| *param != arg.value_type()) {
+            return Err(RuntimeError::InvalidArgType.into());
+        }
+
+        interpreter::invoke(&mut inner, addr, args)
+    }
+
+    // Gives the host access to the exported memory `name`. The store stays borrowed until
+    // the returned reference is dropped.
+    pub fn memory(&self, name: &str) -> Result<RefMut<'_, Memory>, RuntimeError> {
+        let addr = match self.export(name)? {
+            ExternVal::Mem(addr) => addr,
+            _ => return Err(RuntimeError::ExportNotFound),
+        };
+        Ok(RefMut::map(self.store.inner.borrow_mut(), |inner| &mut inner.mems[addr]))
+    }
+
+    // Gives the host access to the exported table `name`, like `memory`.
+    pub fn table(&self, name: &str) -> Result<RefMut<'_, Table>, RuntimeError> {
+        let addr = match self.export(name)? {
+            ExternVal::Table(addr) => addr,
+            _ => return Err(RuntimeError::ExportNotFound),
+        };
+        Ok(RefMut::map(self.store.inner.borrow_mut(), |inner| &mut inner.tables[addr]))
+    }
+
+    // Reads the exported global `name`.
+    pub fn global(&self, name: &str) -> Result<Value, RuntimeError> {
+        let addr = self.global_addr(name)?;
+        Ok(self.store.inner.borrow().globals[addr].value)
+    }
+
+    // Writes the exported global `name`, which has to be mutable. Every instance the global
+    // is imported into sees the new value.
+    pub fn set_global(&self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        let addr = self.global_addr(name)?;
+        let global = &mut self.store.inner.borrow_mut().globals[addr];
+        if !global.global_type.mutable {
+            return Err(RuntimeError::ImmutableGlobal);
+        }
+        if global.global_type.value_type != value.value_type() {
+            return Err(RuntimeError::TypeMismatch);
+        }
+        global.value = value;
+        Ok(())
+    }
+
+    pub fn exports(&self) -> Vec<ExportInst> {
+        self.store.inner.borrow().modules[self.addr].exports.clone()
+    }
+
+    fn func_addr(&self, name: &str) -> Result<usize, RuntimeError> {
+        match self.export(name)? {
+            ExternVal::Func(addr) => Ok(addr),
+            _ => Err(RuntimeError::ExportNotFound),
+        }
+    }
+
+    fn global_addr(&self, name: &str) -> Result<usize, RuntimeError> {
+        match self.export(name)? {
+            ExternVal::Global(addr) => Ok(addr),
+            _ => Err(RuntimeError::ExportNotFound),
+        }
+    }
+
+    fn export(&self, name: &str) -> Result<ExternVal, RuntimeError> {
+        let inner = self.store.inner.borrow();
+        let export = inner.modules[self.addr].exports.iter()
+            .find(|export| export.name == name)
+            .ok_or(RuntimeError::ExportNotFound)?;
+        Ok(export.value)
+    }
+}
+
+// An imported entity with limits `min` and `max` matches the `expected` limits of the import
+// if it is at least as large and at most as large as they allow.
+fn limits_match(min: u32, max: Option<u32>, expected: &Limits) -> bool {
+    min >= expected.min && match (max, expected.max) {
+        (_, None) => true,
+        (Some(max), Some(expected)) => max <= expected,
+        (None, Some(_)) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::runtime::Linker;
+
+    fn instantiate(source: &str) -> Instance {
+        let module = parser::parse(source).unwrap();
+        Instance::new(&Store::new(), &module).unwrap()
+    }
+
+    #[test]
+    fn invokes_exported_functions() {
+        let instance = instantiate(include_str!("../../test2.wat"));
+        assert_eq!(instance.invoke("add", &[Value::I32(2), Value::I32(3)]).unwrap(), [Value::I32(5)]);
+        assert_eq!(instance.invoke("add", &[Value::I32(i32::MAX), Value::I32(1)]).unwrap(), [Value::I32(i32::MIN)]);
+    }
+
+    #[test]
+    fn reports_traps_and_invalid_calls() {
+        let instance = instantiate(r#"
+            (module
+              (func $div (param i32 i32) (result i32) local.get 0 local.get 1 i32.div_s)
+              (export "div" (func $div)))
+        "#);
+        assert_eq!(instance.invoke("div", &[Value::I32(7), Value::I32(2)]).unwrap(), [Value::I32(3)]);
+        assert!(matches!(
+            instance.invoke("div", &[Value::I32(7), Value::I32(0)]),
+            Err(Trap::IntegerDivideByZero),
+        ));
+        assert!(matches!(
+            instance.invoke("div", &[Value::I32(i32::MIN), Value::I32(-1)]),
+            Err(Trap::IntegerOverflow),
+        ));
+        assert!(matches!(
+            instance.invoke("mul", &[Value::I32(7), Value::I32(2)]),
+            Err(Trap::Runtime(RuntimeError::ExportNotFound)),
+        ));
+        assert!(matches!(
+            instance.invoke("div", &[Value::I32(7)]),
+            Err(Trap::Runtime(RuntimeError::InvalidArgNumber)),
+        ));
+        assert!(matches!(
+            instance.invoke("div", &[Value::I32(7), Value::I64(2)]),
+            Err(Trap::Runtime(RuntimeError::InvalidArgType)),
+        ));
+    }
+
+    #[test]
+    fn shares_mutable_globals() {
+        let store = Store::new();
+        let mut linker = Linker::new(&store);
+        linker.global("env", "limit", false, Value::I32(7));
+
+        let module = parser::parse(r#"
+            (module
+              (import "env" "limit" (global $limit i32))
+              (global (export "count") (mut i32) (i32.mul (global.get $limit) (i32.const 2)))
+              (global (export "limit") i32 (global.get $limit))
+              (func (export "next") (result i32)
+                (global.set 1 (i32.add (global.get 1) (i32.const 1)))
+                (global.get 1)))
+        "#).unwrap();
+        let counter = linker.instantiate(&module).unwrap();
+        assert_eq!(counter.global("count").unwrap(), Value::I32(14));
+        assert!(counter.set_global("limit", Value::I32(0)).is_err());
+        assert!(counter.set_global("count", Value::I64(0)).is_err());
+        linker.instance("counter", &counter);
+
+        let module = parser::parse(r#"
+            (module
+              (import "counter" "count" (global $count (mut i32)))
+              (func (export "get") (result i32)
+                (global.get $count))
+              (func (export "set") (param i32)
+                (global.set $count (local.get 0))))
+        "#).unwrap();
+        let user = linker.instantiate(&module).unwrap();
+        assert_eq!(user.invoke("get", &[]).unwrap(), [Value::I32(14)]);
+        counter.set_global("count", Value::I32(41)).unwrap();
+        assert_eq!(counter.invoke("next", &[]).unwrap(), [Value::I32(42)]);
+        assert_eq!(user.invoke("get", &[]).unwrap(), [Value::I32(42)]);
+        user.invoke("set", &[Value::I32(99)]).unwrap();
+        assert_eq!(counter.global("count").unwrap(), Value::I32(99));
+        assert_eq!(counter.invoke("next", &[]).unwrap(), [Value::I32(100)]);
+    }
+}
@@ -0,0 +1,899 @@
+use std::rc::Rc;
+
+use crate::ast::{BlockType, FuncType, Instr, MemArg, ReferenceType, ValueType};
+use crate::runtime::host::HostFunc;
+use crate::runtime::store::{FuncInst, StoreInner};
+use crate::runtime::{Caller, RuntimeError, Trap, Value};
+
+// Nested calls beyond this depth trap. Frames live on the heap, so the limit only bounds the
+// memory a runaway recursion takes.
+const MAX_CALL_DEPTH: usize = 10000;
+
+// A function body flattened for execution. Blocks, loops and ifs become instructions that
+// jump to the positions of their `else` and `end`, which lets the interpreter keep its control
+// state in `frames` and `labels` instead of recursing on the native stack.
+pub(crate) struct Code {
+    locals: Vec<ValueType>,
+    ops: Vec<Op>,
+}
+
+enum Op {
+    // `end` is the position of the matching `End`
+    Block { block_type: BlockType, end: usize },
+    Loop { block_type: BlockType },
+    // A zero condition continues at `otherwise`, after the `Else` or at the `End` if there
+    // is no else branch
+    If { block_type: BlockType, otherwise: usize, end: usize },
+    // Leaves the then branch of an if for its `End`
+    Else { end: usize },
+    // Pops the label of the innermost block, loop or if
+    End,
+    Instr(Instr),
+}
+
+impl Code {
+    pub(crate) fn new(locals: Vec<ValueType>, body: &[Instr]) -> Self {
+        let mut ops = vec![];
+        flatten(body, &mut ops);
+        Self { locals, ops }
+    }
+}
+
+// Appends `instrs` to `ops`. The recursion is bounded by the nesting depth the parser and the
+// loader allow.
+fn flatten(instrs: &[Instr], ops: &mut Vec<Op>) {
+    for instr in instrs {
+        match instr {
+            Instr::Block(block_type, body) => {
+                let start = ops.len();
+                ops.push(Op::End);
+                flatten(body, ops);
+                ops[start] = Op::Block { block_type: *block_type, end: ops.len() };
+                ops.push(Op::End);
+            },
+            Instr::Loop(block_type, body) => {
+                ops.push(Op::Loop { block_type: *block_type });
+                flatten(body, ops);
+                ops.push(Op::End);
+            },
+            Instr::If(block_type, then, otherwise) => {
+                let start = ops.len();
+                ops.push(Op::End);
+                flatten(then, ops);
+                let mut else_pos = None;
+                if !otherwise.is_empty() {
+                    else_pos = Some(ops.len());
+                    ops.push(Op::End);
+                    flatten(otherwise, ops);
+                }
+                let end = ops.len();
+                if let Some(pos) = else_pos {
+                    ops[pos] = Op::Else { end };
+                }
+                let otherwise = else_pos.map_or(end, |pos| pos + 1);
+                ops[start] = Op::If { block_type: *block_type, otherwise, end };
+                ops.push(Op::End);
+            },
+            instr => ops.push(Op::Instr(instr.clone())),
+        }
+    }
+}
+
+// Frame ::= {locals val*, module moduleinst}
+// Also holds the code of the function and where to resume it after a call returns.
+struct Frame {
+    code: Rc<Code>,
+    pc: usize,
+    locals: Vec<Value>,
+    module: usize,
+    // The index of the label of the function body, below those of its blocks
+    label: usize,
+}
+
+// Label ::= {arity, height, continuation}
+// A branch keeps the top `arity` operands above `height` and continues at `target`, the `End`
+// of a block or if or the start of a loop body. Branches keep the label they target, `End`
+// pops it.
+#[derive(Clone, Copy)]
+struct Label {
+    arity: usize,
+    height: usize,
+    target: usize,
+}
+
+// Executes code on a single operand stack shared by all frames. Calls and blocks push frames
+// and labels rather than recursing, so deep nesting cannot overflow the native stack.
+struct Interpreter<'s> {
+    store: &'s mut StoreInner,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    labels: Vec<Label>,
+}
+
+// Calls the function at `addr` with arguments that already match its parameter types.
+pub(crate) fn invoke(store: &mut StoreInner, addr: usize, args: &[Value]) -> Result<Vec<Value>, Trap> {
+    let mut interpreter = Interpreter::new(store);
+    interpreter.stack.extend_from_slice(args);
+    interpreter.call(addr)?;
+    interpreter.run()?;
+    Ok(interpreter.stack)
+}
+
+// Evaluates a constant expression, such as the offset of a segment, in the context of the
+// module instance at `module`.
+pub(crate) fn eval_const(store: &mut StoreInner, module: usize, expr: &[Instr]) -> Result<Value, Trap> {
+    let mut interpreter = Interpreter::new(store);
+    interpreter.enter(Rc::new(Code::new(vec![], expr)), vec![], module, 1);
+    interpreter.run()?;
+    interpreter.pop()
+}
+
+macro_rules! unary {
+    ($self:ident, $pop:ident, $push:ident, |$a:ident| $e:expr) => {{
+        let $a = $self.$pop()?;
+        $self.stack.push(Value::$push($e));
+    }};
+}
+
+macro_rules! binary {
+    ($self:ident, $pop:ident, $push:ident, |$a:ident, $b:ident| $e:expr) => {{
+        let $b = $self.$pop()?;
+        let $a = $self.$pop()?;
+        $self.stack.push(Value::$push($e));
+    }};
+}
+
+// Loads a `$t` and converts it to the value `$push`, extending narrow integers according to
+// their signedness.
+macro_rules! load {
+    ($self:ident, $mem:expr, $memarg:expr, $t:ty, $push:ident) => {{
+        let bytes = $self.load($mem, $memarg)?;
+        $self.stack.push(Value::$push(<$t>::from_le_bytes(bytes) as _));
+    }};
+}
+
+// Stores the operand `$pop`, wrapped to a `$t`.
+macro_rules! store {
+    ($self:ident, $mem:expr, $memarg:expr, $pop:ident, $t:ty) => {{
+        let value = $self.$pop()? as $t;
+        $self.store($mem, $memarg, value.to_le_bytes())?;
+    }};
+}
+
+impl<'s> Interpreter<'s> {
+    fn new(store: &'s mut StoreInner) -> Self {
+        Self {
+            store,
+            stack: vec![],
+            frames: vec![],
+            labels: vec![],
+        }
+    }
+
+    // Calls the function at `addr` with the arguments on top of the stack. Host functions run
+    // to completion, Wasm functions get a frame that `run` executes.
+    fn call(&mut self, addr: usize) -> Result<(), Trap> {
+        if self.frames.len() == MAX_CALL_DEPTH {
+            return Err(Trap::CallStackExhausted);
+        }
+
+        let func = &self.store.funcs[addr];
+        let (params, results) = func.func_type();
+        let (num_params, num_results) = (params.len(), results.len());
+        if self.stack.len() < num_params {
+            return Err(RuntimeError::TypeMismatch.into());
+        }
+        match func {
+            FuncInst::Wasm { module, code, .. } => {
+                let (module, code) = (*module, Rc::clone(code));
+                let mut locals = self.stack.split_off(self.stack.len() - num_params);
+                locals.extend(code.locals.iter().map(|value_type| Value::default(*value_type)));
+                self.enter(code, locals, module, num_results);
+                Ok(())
+            },
+            FuncInst::Host { func_type, code } => {
+                let (func_type, code) = (func_type.clone(), Rc::clone(code));
+                let caller = self.frames.last().map(|frame| frame.module);
+                self.call_host(&func_type, &code, caller)
+            },
+        }
+    }
+
+    // Pushes the frame of a function whose body is a block with `arity` results.
+    fn enter(&mut self, code: Rc<Code>, locals: Vec<Value>, module: usize, arity: usize) {
+        self.labels.push(Label { arity, height: self.stack.len(), target: code.ops.len() });
+        self.frames.push(Frame { code, pc: 0, locals, module, label: self.labels.len() - 1 });
+    }
+
+    // Passes the arguments on the stack to a host function and pushes its results, which have
+    // to match the result types of the function.
+    fn call_host(&mut self, (params, results): &FuncType, code: &HostFunc, caller: Option<usize>) -> Result<(), Trap> {
+        let args = self.stack.split_off(self.stack.len() - params.len());
+        let values = code(&mut Caller { store: self.store, module: caller }, &args)?;
+        if values.len() != results.len() || values.iter().zip(results).any(|(value, result)| value.value_type() != *result) {
+            return Err(RuntimeError::InvalidHostResult.into());
+        }
+        self.stack.extend(values);
+        Ok(())
+    }
+
+    // Executes the frames on the call stack until all of them have returned.
+    fn run(&mut self) -> Result<(), Trap> {
+        'frames: while let Some(frame) = self.frames.last() {
+            let (code, module, body, mut pc) = (Rc::clone(&frame.code), frame.module, frame.label, frame.pc);
+
+            // Runs the current frame until it calls a function or its body ends
+            while let Some(op) = code.ops.get(pc) {
+                pc += 1;
+                let instr = match op {
+                    Op::Block { block_type, end } => {
+                        self.push_label(module, block_type, *end, false)?;
+                        continue;
+                    },
+                    Op::Loop { block_type } => {
+                        self.push_label(module, block_type, pc, true)?;
+                        continue;
+                    },
+                    Op::If { block_type, otherwise, end } => {
+                        let condition = self.pop_i32()?;
+                        self.push_label(module, block_type, *end, false)?;
+                        if condition == 0 {
+                            pc = *otherwise;
+                        }
+                        continue;
+                    },
+                    Op::Else { end } => {
+                        pc = *end;
+                        continue;
+                    },
+                    Op::End => {
+                        self.labels.pop();
+                        continue;
+                    },
+                    Op::Instr(instr) => instr,
+                };
+                match instr {
+                    Instr::Br(depth) => pc = self.branch(*depth)?,
+                    Instr::BrIf(depth) => {
+                        if self.pop_i32()? != 0 {
+                            pc = self.branch(*depth)?;
+                        }
+                    },
+                    Instr::BrTable(depths, default) => {
+                        let idx = self.pop_i32()? as u32 as usize;
+                        pc = self.branch(*depths.get(idx).unwrap_or(default))?;
+                    },
+                    // A branch to the label of the function body
+                    Instr::Return => pc = self.branch(self.labels.len() - 1 - body)?,
+                    Instr::Call(_) | Instr::CallIndirect(..) => {
+                        let addr = match instr {
+                            Instr::CallIndirect(table, type_idx) => self.indirect_callee(module, *table, *type_idx)?,
+                            Instr::Call(idx) => *self.store.modules[module].func_addrs.get(*idx)
+                                .ok_or(RuntimeError::InvalidFunctionIndex)?,
+                            _ => unreachable!(),
+                        };
+                        self.frame().pc = pc;
+                        self.call(addr)?;
+                        continue 'frames;
+                    },
+                    _ => self.instr(module, instr)?,
+                }
+            }
+
+            // The body ended or a branch targeted its label
+            let frame = self.frames.pop().expect("a frame is running");
+            let label = self.labels[frame.label];
+            self.unwind(label.height, label.arity)?;
+            self.labels.truncate(frame.label);
+        }
+        Ok(())
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a frame is running")
+    }
+
+    // Enters a block, loop or if whose parameters are on top of the stack. Branches to a loop
+    // restart it with its parameters, branches to a block or if exit it with its results.
+    fn push_label(&mut self, module: usize, block_type: &BlockType, target: usize, is_loop: bool) -> Result<(), Trap> {
+        let (num_params, num_results) = self.block_arity(module, block_type)?;
+        let height = self.stack.len().checked_sub(num_params).ok_or(RuntimeError::TypeMismatch)?;
+        let arity = if is_loop { num_params } else { num_results };
+        self.labels.push(Label { arity, height, target });
+        Ok(())
+    }
+
+    fn block_arity(&self, module: usize, block_type: &BlockType) -> Result<(usize, usize), Trap> {
+        match block_type {
+            BlockType::Empty => Ok((0, 0)),
+            BlockType::Value(_) => Ok((0, 1)),
+            BlockType::Type(idx) => {
+                let (params, results) = self.store.modules[module].types.get(*idx)
+                    .ok_or(RuntimeError::InvalidFunctionType)?;
+                Ok((params.len(), results.len()))
+            },
+        }
+    }
+
+    // Branches to the label `depth` levels up and returns the position to continue at. The
+    // labels of the blocks the branch leaves are popped.
+    fn branch(&mut self, depth: usize) -> Result<usize, Trap> {
+        let idx = self.labels.len().checked_sub(depth + 1)
+            .filter(|idx| *idx >= self.frames.last().map_or(0, |frame| frame.label))
+            .ok_or(RuntimeError::TypeMismatch)?;
+        let label = self.labels[idx];
+        self.unwind(label.height, label.arity)?;
+        self.labels.truncate(idx + 1);
+        Ok(label.target)
+    }
+
+    // Drops the operands between `height` and the top `arity` values of the stack.
+    fn unwind(&mut self, height: usize, arity: usize) -> Result<(), Trap> {
+        if self.stack.len() < height + arity {
+            return Err(RuntimeError::TypeMismatch.into());
+        }
+        self.stack.drain(height..self.stack.len() - arity);
+        Ok(())
+    }
+
+    // Executes an instruction other than a control instruction.
+    fn instr(&mut self, module: usize, instr: &Instr) -> Result<(), Trap> {
+        match instr {
+            Instr::Unreachable => return Err(Trap::Unreachable),
+            Instr::Nop => {},
+
+            // Reference instructions
+            Instr::RefNull(ReferenceType::FuncRef) => self.stack.push(Value::FuncRef(None)),
+            Instr::RefNull(ReferenceType::ExternRef) => self.stack.push(Value::ExternRef(None)),
+            Instr::RefIsNull => {
+                let is_null = match self.pop()? {
+                    Value::FuncRef(addr) | Value::ExternRef(addr) => addr.is_none(),
+                    _ => return Err(RuntimeError::TypeMismatch.into()),
+                };
+                self.stack.push(Value::I32(is_null as i32));
+            },
+            Instr::RefFunc(idx) => {
+                let addr = *self.store.modules[module].func_addrs.get(*idx)
+                    .ok_or(RuntimeError::InvalidFunctionIndex)?;
+                self.stack.push(Value::FuncRef(Some(addr)));
+            },
+
+            // Parametric instructions
+            Instr::Drop => {
+                self.pop()?;
+            },
+            Instr::Select(_) => {
+                let condition = self.pop_i32()?;
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.stack.push(if condition != 0 { a } else { b });
+            },
+
+            // Variable instructions
+            Instr::LocalGet(idx) => {
+                let value = *self.frame().locals.get(*idx).ok_or(RuntimeError::InvalidLocalIndex)?;
+                self.stack.push(value);
+            },
+            Instr::LocalSet(idx) => {
+                let value = self.pop()?;
+                *self.frame().locals.get_mut(*idx).ok_or(RuntimeError::InvalidLocalIndex)? = value;
+            },
+            Instr::LocalTee(idx) => {
+                let value = *self.stack.last().ok_or(RuntimeError::TypeMismatch)?;
+                *self.frame().locals.get_mut(*idx).ok_or(RuntimeError::InvalidLocalIndex)? = value;
+            },
+            Instr::GlobalGet(idx) | Instr::GlobalSet(idx) => self.global(module, instr, *idx)?,
+
+            Instr::TableGet(_) | Instr::TableSet(_) | Instr::TableSize(_) | Instr::TableGrow(_)
+            | Instr::TableFill(_) | Instr::TableCopy(..) | Instr::TableInit(..) | Instr::ElemDrop(_) => {
+                self.table(module, instr)?
+            },
+            Instr::MemorySize | Instr::MemoryGrow | Instr::MemoryFill | Instr::MemoryCopy | Instr::MemoryInit(_)
+            | Instr::DataDrop(_) => self.memory(module, instr)?,
+            _ if instr.memarg().is_some() => self.memory(module, instr)?,
+            _ => self.numeric(instr)?,
+        }
+        Ok(())
+    }
+
+    fn global(&mut self, module: usize, instr: &Instr, idx: usize) -> Result<(), Trap> {
+        let addr = *self.store.modules[module].global_addrs.get(idx)
+            .ok_or(RuntimeError::InvalidGlobalIndex)?;
+        if let Instr::GlobalSet(_) = instr {
+            self.store.globals[addr].value = self.pop()?;
+        } else {
+            self.stack.push(self.store.globals[addr].value);
+        }
+        Ok(())
+    }
+
+    // Pops the table index of `call_indirect` and returns the address of the function stored
+    // there. Tables hold functions of any type, so the signature is checked at runtime.
+    fn indirect_callee(&mut self, module: usize, table: usize, type_idx: usize) -> Result<usize, Trap> {
+        let table = self.table_addr(module, table)?;
+        let idx = self.pop_i32()? as u32;
+        let addr = match self.store.tables[table].get(idx) {
+            Ok(Value::FuncRef(Some(addr))) => addr,
+            Ok(Value::FuncRef(None)) => return Err(Trap::UninitializedElement),
+            Ok(_) => return Err(RuntimeError::TypeMismatch.into()),
+            Err(_) => return Err(Trap::UndefinedElement),
+        };
+        let func_type = self.store.modules[module].types.get(type_idx)
+            .ok_or(RuntimeError::InvalidFunctionType)?;
+        if self.store.funcs[addr].func_type() != func_type {
+            return Err(Trap::IndirectCallTypeMismatch);
+        }
+        Ok(addr)
+    }
+
+    fn table(&mut self, module: usize, instr: &Instr) -> Result<(), Trap> {
+        match instr {
+            Instr::TableGet(idx) => {
+                let table = self.table_addr(module, *idx)?;
+                let i = self.pop_i32()? as u32;
+                let value = self.store.tables[table].get(i)?;
+                self.stack.push(value);
+            },
+            Instr::TableSet(idx) => {
+                let table = self.table_addr(module, *idx)?;
+                let value = self.pop()?;
+                let i = self.pop_i32()? as u32;
+                self.store.tables[table].set(i, value)?;
+            },
+            Instr::TableSize(idx) => {
+                let table = self.table_addr(module, *idx)?;
+                let size = self.store.tables[table].size();
+                self.stack.push(Value::I32(size as i32));
+            },
+            // Like `memory.grow`, failing to grow returns -1
+            Instr::TableGrow(idx) => {
+                let table = self.table_addr(module, *idx)?;
+                let delta = self.pop_i32()? as u32;
+                let init = self.pop()?;
+                let size = self.store.tables[table].grow(delta, init);
+                self.stack.push(Value::I32(size.map_or(-1, |size| size as i32)));
+            },
+            Instr::TableFill(idx) => {
+                let table = self.table_addr(module, *idx)?;
+                let len = self.pop_i32()? as u32;
+                let value = self.pop()?;
+                let i = self.pop_i32()? as u32;
+                self.store.tables[table].fill(i, value, len)?;
+            },
+            // Both ranges are checked before any element is copied, the ranges may overlap
+            Instr::TableCopy(dst, src) => {
+                let (dst, src) = (self.table_addr(module, *dst)?, self.table_addr(module, *src)?);
+                let len = self.pop_i32()? as u32;
+                let s = self.pop_i32()? as u32;
+                let d = self.pop_i32()? as u32;
+                let values = self.store.tables[src].elements(s, len)?.to_vec();
+                self.store.tables[dst].init(d, &values)?;
+            },
+            Instr::TableInit(table, elem) => {
+                let (table, elem) = (self.table_addr(module, *table)?, self.elem_addr(module, *elem)?);
+                let len = self.pop_i32()? as u32 as usize;
+                let s = self.pop_i32()? as u32 as usize;
+                let d = self.pop_i32()? as u32;
+                let values = self.store.elems[elem].get(s..).and_then(|values| values.get(..len))
+                    .ok_or(Trap::OutOfBoundsTableAccess)?;
+                self.store.tables[table].init(d, values)?;
+            },
+            Instr::ElemDrop(idx) => {
+                let elem = self.elem_addr(module, *idx)?;
+                self.store.elems[elem] = vec![];
+            },
+            _ => return Err(RuntimeError::UnsupportedInstruction.into()),
+        }
+        Ok(())
+    }
+
+    fn table_addr(&self, module: usize, idx: usize) -> Result<usize, Trap> {
+        let addr = self.store.modules[module].table_addrs.get(idx).ok_or(RuntimeError::InvalidTableIndex)?;
+        Ok(*addr)
+    }
+
+    fn elem_addr(&self, module: usize, idx: usize) -> Result<usize, Trap> {
+        let addr = self.store.modules[module].elem_addrs.get(idx).ok_or(RuntimeError::InvalidElemIndex)?;
+        Ok(*addr)
+    }
+
+    fn memory(&mut self, module: usize, instr: &Instr) -> Result<(), Trap> {
+        // The only one of these instructions that is valid without a memory
+        if let Instr::DataDrop(idx) = instr {
+            let data = self.data_addr(module, *idx)?;
+            self.store.datas[data] = vec![];
+            return Ok(());
+        }
+
+        let mem = *self.store.modules[module].mem_addrs.first()
+            .ok_or(RuntimeError::InvalidMemoryIndex)?;
+        match instr {
+            Instr::I32Load(memarg) => load!(self, mem, memarg, i32, I32),
+            Instr::I64Load(memarg) => load!(self, mem, memarg, i64, I64),
+            Instr::F32Load(memarg) => load!(self, mem, memarg, f32, F32),
+            Instr::F64Load(memarg) => load!(self, mem, memarg, f64, F64),
+            Instr::I32Load8S(memarg) => load!(self, mem, memarg, i8, I32),
+            Instr::I32Load8U(memarg) => load!(self, mem, memarg, u8, I32),
+            Instr::I32Load16S(memarg) => load!(self, mem, memarg, i16, I32),
+            Instr::I32Load16U(memarg) => load!(self, mem, memarg, u16, I32),
+            Instr::I64Load8S(memarg) => load!(self, mem, memarg, i8, I64),
+            Instr::I64Load8U(memarg) => load!(self, mem, memarg, u8, I64),
+            Instr::I64Load16S(memarg) => load!(self, mem, memarg, i16, I64),
+            Instr::I64Load16U(memarg) => load!(self, mem, memarg, u16, I64),
+            Instr::I64Load32S(memarg) => load!(self, mem, memarg, i32, I64),
+            Instr::I64Load32U(memarg) => load!(self, mem, memarg, u32, I64),
+
+            Instr::I32Store(memarg) => store!(self, mem, memarg, pop_i32, i32),
+            Instr::I64Store(memarg) => store!(self, mem, memarg, pop_i64, i64),
+            Instr::F32Store(memarg) => store!(self, mem, memarg, pop_f32, f32),
+            Instr::F64Store(memarg) => store!(self, mem, memarg, pop_f64, f64),
+            Instr::I32Store8(memarg) => store!(self, mem, memarg, pop_i32, u8),
+            Instr::I32Store16(memarg) => store!(self, mem, memarg, pop_i32, u16),
+            Instr::I64Store8(memarg) => store!(self, mem, memarg, pop_i64, u8),
+            Instr::I64Store16(memarg) => store!(self, mem, memarg, pop_i64, u16),
+            Instr::I64Store32(memarg) => store!(self, mem, memarg, pop_i64, u32),
+
+            Instr::MemorySize => {
+                let size = self.store.mems[mem].size();
+                self.stack.push(Value::I32(size as i32));
+            },
+            // Failing to grow is not a trap, the instruction returns -1 instead
+            Instr::MemoryGrow => {
+                let delta = self.pop_i32()? as u32;
+                let size = self.store.mems[mem].grow(delta);
+                self.stack.push(Value::I32(size.map_or(-1, |size| size as i32)));
+            },
+            Instr::MemoryFill => {
+                let len = self.pop_i32()? as u32 as usize;
+                let value = self.pop_i32()? as u8;
+                let addr = self.pop_i32()? as u32 as u64;
+                self.store.mems[mem].fill(addr, value, len)?;
+            },
+            Instr::MemoryCopy => {
+                let len = self.pop_i32()? as u32 as usize;
+                let src = self.pop_i32()? as u32 as u64;
+                let dst = self.pop_i32()? as u32 as u64;
+                self.store.mems[mem].copy(dst, src, len)?;
+            },
+            Instr::MemoryInit(idx) => {
+                let data = self.data_addr(module, *idx)?;
+                let len = self.pop_i32()? as u32 as usize;
+                let s = self.pop_i32()? as u32 as usize;
+                let d = self.pop_i32()? as u32 as usize;
+                let bytes = self.store.datas[data].get(s..).and_then(|bytes| bytes.get(..len))
+                    .ok_or(Trap::OutOfBoundsMemoryAccess)?;
+                self.store.mems[mem].write(d, bytes)?;
+            },
+
+            _ => return Err(RuntimeError::UnsupportedInstruction.into()),
+        }
+        Ok(())
+    }
+
+    fn data_addr(&self, module: usize, idx: usize) -> Result<usize, Trap> {
+        let addr = self.store.modules[module].data_addrs.get(idx).ok_or(RuntimeError::InvalidDataIndex)?;
+        Ok(*addr)
+    }
+
+    // Pops the address operand and reads N bytes at the effective address, the address plus
+    // the static offset of the instruction.
+    fn load<const N: usize>(&mut self, mem: usize, memarg: &MemArg) -> Result<[u8; N], Trap> {
+        let addr = self.pop_i32()? as u32 as u64 + memarg.offset as u64;
+        self.store.mems[mem].load(addr)
+    }
+
+    fn store<const N: usize>(&mut self, mem: usize, memarg: &MemArg, bytes: [u8; N]) -> Result<(), Trap> {
+        let addr = self.pop_i32()? as u32 as u64 + memarg.offset as u64;
+        self.store.mems[mem].store(addr, bytes)
+    }
+
+    fn numeric(&mut self, instr: &Instr) -> Result<(), Trap> {
+        match instr {
+            Instr::I32Const(value) => self.stack.push(Value::I32(*value)),
+            Instr::I64Const(value) => self.stack.push(Value::I64(*value)),
+            Instr::F32Const(bits) => self.stack.push(Value::F32(f32::from_bits(*bits))),
+            Instr::F64Const(bits) => self.stack.push(Value::F64(f64::from_bits(*bits))),
+
+            Instr::I32Eqz => unary!(self, pop_i32, I32, |a| (a == 0) as i32),
+            Instr::I32Eq => binary!(self, pop_i32, I32, |a, b| (a == b) as i32),
+            Instr::I32Ne => binary!(self, pop_i32, I32, |a, b| (a != b) as i32),
+            Instr::I32LtS => binary!(self, pop_i32, I32, |a, b| (a < b) as i32),
+            Instr::I32LtU => binary!(self, pop_i32, I32, |a, b| ((a as u32) < (b as u32)) as i32),
+            Instr::I32GtS => binary!(self, pop_i32, I32, |a, b| (a > b) as i32),
+            Instr::I32GtU => binary!(self, pop_i32, I32, |a, b| ((a as u32) > (b as u32)) as i32),
+            Instr::I32LeS => binary!(self, pop_i32, I32, |a, b| (a <= b) as i32),
+            Instr::I32LeU => binary!(self, pop_i32, I32, |a, b| ((a as u32) <= (b as u32)) as i32),
+            Instr::I32GeS => binary!(self, pop_i32, I32, |a, b| (a >= b) as i32),
+            Instr::I32GeU => binary!(self, pop_i32, I32, |a, b| ((a as u32) >= (b as u32)) as i32),
+
+            Instr::I64Eqz => unary!(self, pop_i64, I32, |a| (a == 0) as i32),
+            Instr::I64Eq => binary!(self, pop_i64, I32, |a, b| (a == b) as i32),
+            Instr::I64Ne => binary!(self, pop_i64, I32, |a, b| (a != b) as i32),
+            Instr::I64LtS => binary!(self, pop_i64, I32, |a, b| (a < b) as i32),
+            Instr::I64LtU => binary!(self, pop_i64, I32, |a, b| ((a as u64) < (b as u64)) as i32),
+            Instr::I64GtS => binary!(self, pop_i64, I32, |a, b| (a > b) as i32),
+            Instr::I64GtU => binary!(self, pop_i64, I32, |a, b| ((a as u64) > (b as u64)) as i32),
+            Instr::I64LeS => binary!(self, pop_i64, I32, |a, b| (a <= b) as i32),
+            Instr::I64LeU => binary!(self, pop_i64, I32, |a, b| ((a as u64) <= (b as u64)) as i32),
+            Instr::I64GeS => binary!(self, pop_i64, I32, |a, b| (a >= b) as i32),
+            Instr::I64GeU => binary!(self, pop_i64, I32, |a, b| ((a as u64) >= (b as u64)) as i32),
+
+            Instr::F32Eq => binary!(self, pop_f32, I32, |a, b| (a == b) as i32),
+            Instr::F32Ne => binary!(self, pop_f32, I32, |a, b| (a != b) as i32),
+            Instr::F32Lt => binary!(self, pop_f32, I32, |a, b| (a < b) as i32),
+            Instr::F32Gt => binary!(self, pop_f32, I32, |a, b| (a > b) as i32),
+            Instr::F32Le => binary!(self, pop_f32, I32, |a, b| (a <= b) as i32),
+            Instr::F32Ge => binary!(self, pop_f32, I32, |a, b| (a >= b) as i32),
+
+            Instr::F64Eq => binary!(self, pop_f64, I32, |a, b| (a == b) as i32),
+            Instr::F64Ne => binary!(self, pop_f64, I32, |a, b| (a != b) as i32),
+            Instr::F64Lt => binary!(self, pop_f64, I32, |a, b| (a < b) as i32),
+            Instr::F64Gt => binary!(self, pop_f64, I32, |a, b| (a > b) as i32),
+            Instr::F64Le => binary!(self, pop_f64, I32, |a, b| (a <= b) as i32),
+            Instr::F64Ge => binary!(self, pop_f64, I32, |a, b| (a >= b) as i32),
+
+            Instr::I32Clz => unary!(self, pop_i32, I32, |a| a.leading_zeros() as i32),
+            Instr::I32Ctz => unary!(self, pop_i32, I32, |a| a.trailing_zeros() as i32),
+            Instr::I32Popcnt => unary!(self, pop_i32, I32, |a| a.count_ones() as i32),
+            Instr::I32Add => binary!(self, pop_i32, I32, |a, b| a.wrapping_add(b)),
+            Instr::I32Sub => binary!(self, pop_i32, I32, |a, b| a.wrapping_sub(b)),
+            Instr::I32Mul => binary!(self, pop_i32, I32, |a, b| a.wrapping_mul(b)),
+            Instr::I32DivS => binary!(self, pop_i32, I32, |a, b| {
+                if b == 0 {
+                    return Err(Trap::IntegerDivideByZero);
+                }
+                a.checked_div(b).ok_or(Trap::IntegerOverflow)?
+            }),
+            Instr::I32DivU => binary!(self, pop_i32, I32, |a, b| {
+                (a as u32).checked_div(b as u32).ok_or(Trap::IntegerDivideByZero)? as i32
+            }),
+            Instr::I32RemS => binary!(self, pop_i32, I32, |a, b| {
+                if b == 0 {
+                    return Err(Trap::IntegerDivideByZero);
+                }
+                a.wrapping_rem(b)
+            }),
+            Instr::I32RemU => binary!(self, pop_i32, I32, |a, b| {
+                (a as u32).checked_rem(b as u32).ok_or(Trap::IntegerDivideByZero)? as i32
+            }),
+            Instr::I32And => binary!(self, pop_i32, I32, |a, b| a & b),
+            Instr::I32Or => binary!(self, pop_i32, I32, |a, b| a | b),
+            Instr::I32Xor => binary!(self, pop_i32, I32, |a, b| a ^ b),
+            Instr::I32Shl => binary!(self, pop_i32, I32, |a, b| a.wrapping_shl(b as u32)),
+            Instr::I32ShrS => binary!(self, pop_i32, I32, |a, b| a.wrapping_shr(b as u32)),
+            Instr::I32ShrU => binary!(self, pop_i32, I32, |a, b| (a as u32).wrapping_shr(b as u32) as i32),
+            Instr::I32Rotl => binary!(self, pop_i32, I32, |a, b| a.rotate_left(b as u32)),
+            Instr::I32Rotr => binary!(self, pop_i32, I32, |a, b| a.rotate_right(b as u32)),
+
+            Instr::I64Clz => unary!(self, pop_i64, I64, |a| a.leading_zeros() as i64),
+            Instr::I64Ctz => unary!(self, pop_i64, I64, |a| a.trailing_zeros() as i64),
+            Instr::I64Popcnt => unary!(self, pop_i64, I64, |a| a.count_ones() as i64),
+            Instr::I64Add => binary!(self, pop_i64, I64, |a, b| a.wrapping_add(b)),
+            Instr::I64Sub => binary!(self, pop_i64, I64, |a, b| a.wrapping_sub(b)),
+            Instr::I64Mul => binary!(self, pop_i64, I64, |a, b| a.wrapping_mul(b)),
+            Instr::I64DivS => binary!(self, pop_i64, I64, |a, b| {
+                if b == 0 {
+                    return Err(Trap::IntegerDivideByZero);
+                }
+                a.checked_div(b).ok_or(Trap::IntegerOverflow)?
+            }),
+            Instr::I64DivU => binary!(self, pop_i64, I64, |a, b| {
+                (a as u64).checked_div(b as u64).ok_or(Trap::IntegerDivideByZero)? as i64
+            }),
+            Instr::I64RemS => binary!(self, pop_i64, I64, |a, b| {
+                if b == 0 {
+                    return Err(Trap::IntegerDivideByZero);
+                }
+                a.wrapping_rem(b)
+            }),
+            Instr::I64RemU => binary!(self, pop_i64, I64, |a, b| {
+                (a as u64).checked_rem(b as u64).ok_or(Trap::IntegerDivideByZero)? as i64
+            }),
+            Instr::I64And => binary!(self, pop_i64, I64, |a, b| a & b),
+            Instr::I64Or => binary!(self, pop_i64, I64, |a, b| a | b),
+            Instr::I64Xor => binary!(self, pop_i64, I64, |a, b| a ^ b),
+            Instr::I64Shl => binary!(self, pop_i64, I64, |a, b| a.wrapping_shl(b as u32)),
+            Instr::I64ShrS => binary!(self, pop_i64, I64, |a, b| a.wrapping_shr(b as u32)),
+            Instr::I64ShrU => binary!(self, pop_i64, I64, |a, b| (a as u64).wrapping_shr(b as u32) as i64),
+            Instr::I64Rotl => binary!(self, pop_i64, I64, |a, b| a.rotate_left(b as u32)),
+            Instr::I64Rotr => binary!(self, pop_i64, I64, |a, b| a.rotate_right(b as u32)),
+
+            Instr::F32Abs => unary!(self, pop_f32, F32, |a| a.abs()),
+            Instr::F32Neg => unary!(self, pop_f32, F32, |a| -a),
+            Instr::F32Ceil => unary!(self, pop_f32, F32, |a| a.ceil()),
+            Instr::F32Floor => unary!(self, pop_f32, F32, |a| a.floor()),
+            Instr::F32Trunc => unary!(self, pop_f32, F32, |a| a.trunc()),
+            Instr::F32Nearest => unary!(self, pop_f32, F32, |a| a.round_ties_even()),
+            Instr::F32Sqrt => unary!(self, pop_f32, F32, |a| a.sqrt()),
+            Instr::F32Add => binary!(self, pop_f32, F32, |a, b| a + b),
+            Instr::F32Sub => binary!(self, pop_f32, F32, |a, b| a - b),
+            Instr::F32Mul => binary!(self, pop_f32, F32, |a, b| a * b),
+            Instr::F32Div => binary!(self, pop_f32, F32, |a, b| a / b),
+            Instr::F32Min => binary!(self, pop_f32, F32, |a, b| f32_min(a, b)),
+            Instr::F32Max => binary!(self, pop_f32, F32, |a, b| f32_max(a, b)),
+            Instr::F32Copysign => binary!(self, pop_f32, F32, |a, b| a.copysign(b)),
+
+            Instr::F64Abs => unary!(self, pop_f64, F64, |a| a.abs()),
+            Instr::F64Neg => unary!(self, pop_f64, F64, |a| -a),
+            Instr::F64Ceil => unary!(self, pop_f64, F64, |a| a.ceil()),
+            Instr::F64Floor => unary!(self, pop_f64, F64, |a| a.floor()),
+            Instr::F64Trunc => unary!(self, pop_f64, F64, |a| a.trunc()),
+            Instr::F64Nearest => unary!(self, pop_f64, F64, |a| a.round_ties_even()),
+            Instr::F64Sqrt => unary!(self, pop_f64, F64, |a| a.sqrt()),
+            Instr::F64Add => binary!(self, pop_f64, F64, |a, b| a + b),
+            Instr::F64Sub => binary!(self, pop_f64, F64, |a, b| a - b),
+            Instr::F64Mul => binary!(self, pop_f64, F64, |a, b| a * b),
+            Instr::F64Div => binary!(self, pop_f64, F64, |a, b| a / b),
+            Instr::F64Min => binary!(self, pop_f64, F64, |a, b| f64_min(a, b)),
+            Instr::F64Max => binary!(self, pop_f64, F64, |a, b| f64_max(a, b)),
+            Instr::F64Copysign => binary!(self, pop_f64, F64, |a, b| a.copysign(b)),
+
+            Instr::I32WrapI64 => unary!(self, pop_i64, I32, |a| a as i32),
+            Instr::I32TruncF32S => unary!(self, pop_f32, I32, |a| trunc(a as f64, -2147483648.0, 2147483648.0)? as i32),
+            Instr::I32TruncF32U => unary!(self, pop_f32, I32, |a| trunc(a as f64, 0.0, 4294967296.0)? as u32 as i32),
+            Instr::I32TruncF64S => unary!(self, pop_f64, I32, |a| trunc(a, -2147483648.0, 2147483648.0)? as i32),
+            Instr::I32TruncF64U => unary!(self, pop_f64, I32, |a| trunc(a, 0.0, 4294967296.0)? as u32 as i32),
+            Instr::I64ExtendI32S => unary!(self, pop_i32, I64, |a| a as i64),
+            Instr::I64ExtendI32U => unary!(self, pop_i32, I64, |a| a as u32 as i64),
+            Instr::I64TruncF32S => unary!(self, pop_f32, I64, |a| trunc(a as f64, -9223372036854775808.0, 9223372036854775808.0)? as i64),
+            Instr::I64TruncF32U => unary!(self, pop_f32, I64, |a| trunc(a as f64, 0.0, 18446744073709551616.0)? as u64 as i64),
+            Instr::I64TruncF64S => unary!(self, pop_f64, I64, |a| trunc(a, -9223372036854775808.0, 9223372036854775808.0)? as i64),
+            Instr::I64TruncF64U => unary!(self, pop_f64, I64, |a| trunc(a, 0.0, 18446744073709551616.0)? as u64 as i64),
+            Instr::F32ConvertI32S => unary!(self, pop_i32, F32, |a| a as f32),
+            Instr::F32ConvertI32U => unary!(self, pop_i32, F32, |a| a as u32 as f32),
+            Instr::F32ConvertI64S => unary!(self, pop_i64, F32, |a| a as f32),
+            Instr::F32ConvertI64U => unary!(self, pop_i64, F32, |a| a as u64 as f32),
+            Instr::F32DemoteF64 => unary!(self, pop_f64, F32, |a| a as f32),
+            Instr::F64ConvertI32S => unary!(self, pop_i32, F64, |a| a as f64),
+            Instr::F64ConvertI32U => unary!(self, pop_i32, F64, |a| a as u32 as f64),
+            Instr::F64ConvertI64S => unary!(self, pop_i64, F64, |a| a as f64),
+            Instr::F64ConvertI64U => unary!(self, pop_i64, F64, |a| a as u64 as f64),
+            Instr::F64PromoteF32 => unary!(self, pop_f32, F64, |a| a as f64),
+            Instr::I32ReinterpretF32 => unary!(self, pop_f32, I32, |a| a.to_bits() as i32),
+            Instr::I64ReinterpretF64 => unary!(self, pop_f64, I64, |a| a.to_bits() as i64),
+            Instr::F32ReinterpretI32 => unary!(self, pop_i32, F32, |a| f32::from_bits(a as u32)),
+            Instr::F64ReinterpretI64 => unary!(self, pop_i64, F64, |a| f64::from_bits(a as u64)),
+
+            Instr::I32Extend8S => unary!(self, pop_i32, I32, |a| a as i8 as i32),
+            Instr::I32Extend16S => unary!(self, pop_i32, I32, |a| a as i16 as i32),
+            Instr::I64Extend8S => unary!(self, pop_i64, I64, |a| a as i8 as i64),
+            Instr::I64Extend16S => unary!(self, pop_i64, I64, |a| a as i16 as i64),
+            Instr::I64Extend32S => unary!(self, pop_i64, I64, |a| a as i32 as i64),
+
+            // Rust's float to integer casts saturate and map NaN to 0, exactly like trunc_sat
+            Instr::I32TruncSatF32S => unary!(self, pop_f32, I32, |a| a as i32),
+            Instr::I32TruncSatF32U => unary!(self, pop_f32, I32, |a| a as u32 as i32),
+            Instr::I32TruncSatF64S => unary!(self, pop_f64, I32, |a| a as i32),
+            Instr::I32TruncSatF64U => unary!(self, pop_f64, I32, |a| a as u32 as i32),
+            Instr::I64TruncSatF32S => unary!(self, pop_f32, I64, |a| a as i64),
+            Instr::I64TruncSatF32U => unary!(self, pop_f32, I64, |a| a as u64 as i64),
+            Instr::I64TruncSatF64S => unary!(self, pop_f64, I64, |a| a as i64),
+            Instr::I64TruncSatF64U => unary!(self, pop_f64, I64, |a| a as u64 as i64),
+
+            // Vectors have no runtime representation yet
+            _ => return Err(RuntimeError::UnsupportedInstruction.into()),
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Value, Trap> {
+        self.stack.pop().ok_or(Trap::Runtime(RuntimeError::TypeMismatch))
+    }
+
+    fn pop_i32(&mut self) -> Result<i32, Trap> {
+        match self.pop()? {
+            Value::I32(value) => Ok(value),
+            _ => Err(RuntimeError::TypeMismatch.into()),
+        }
+    }
+
+    fn pop_i64(&mut self) -> Result<i64, Trap> {
+        match self.pop()? {
+            Value::I64(value) => Ok(value),
+            _ => Err(RuntimeError::TypeMismatch.into()),
+        }
+    }
+
+    fn pop_f32(&mut self) -> Result<f32, Trap> {
+        match self.pop()? {
+            Value::F32(value) => Ok(value),
+            _ => Err(RuntimeError::TypeMismatch.into()),
+        }
+    }
+
+    fn pop_f64(&mut self) -> Result<f64, Trap> {
+        match self.pop()? {
+            Value::F64(value) => Ok(value),
+            _ => Err(RuntimeError::TypeMismatch.into()),
+        }
+    }
+}
+
+// Truncates `value` towards zero, trapping unless the result lies within [min, max).
+fn trunc(value: f64, min: f64, max: f64) -> Result<f64, Trap> {
+    if value.is_nan() {
+        return Err(Trap::InvalidConversionToInteger);
+    }
+    let value = value.trunc();
+    if value < min || value >= max {
+        return Err(Trap::IntegerOverflow);
+    }
+    Ok(value)
+}
+
+// Unlike `f32::min`, WebAssembly's min propagates NaNs and orders -0 below +0.
+fn f32_min(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_negative() { a } else { b }
+    } else {
+        a.min(b)
+    }
+}
+
+fn f32_max(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_positive() { a } else { b }
+    } else {
+        a.max(b)
+    }
+}
+
+fn f64_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_negative() { a } else { b }
+    } else {
+        a.min(b)
+    }
+}
+
+fn f64_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        if a.is_sign_positive() { a } else { b }
+    } else {
+        a.max(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{NumberType, MAX_NESTING_DEPTH};
+    use crate::parser;
+    use crate::runtime::{Instance, Store};
+
+    // Runs on the stack of the test thread, which is smaller than that of the main thread.
+    #[test]
+    fn runs_deep_calls_and_blocks() {
+        let mut module = parser::parse(r#"
+            (module
+              (func $loop (export "loop") call $loop)
+              (func $count (export "count") (param i32) (result i32)
+                (if (result i32) (local.get 0)
+                  (then (i32.add (call $count (i32.sub (local.get 0) (i32.const 1))) (i32.const 1)))
+                  (else (i32.const 0))))
+              (func (export "nested") (result i32)
+                unreachable))
+        "#).unwrap();
+        // Branches out of the innermost block with a value for the outermost one
+        let mut body = vec![Instr::I32Const(42), Instr::Br(MAX_NESTING_DEPTH - 1)];
+        for _ in 0..MAX_NESTING_DEPTH {
+            body = vec![Instr::Block(BlockType::Value(ValueType::NumberType(NumberType::I32)), body)];
+        }
+        module.funcs[2].body = body;
+
+        let instance = Instance::new(&Store::new(), &module).unwrap();
+        assert!(matches!(instance.invoke("loop", &[]), Err(Trap::CallStackExhausted)));
+        let depth = MAX_CALL_DEPTH as i32 - 1;
+        assert_eq!(instance.invoke("count", &[Value::I32(depth)]).unwrap(), [Value::I32(depth)]);
+        assert!(matches!(instance.invoke("count", &[Value::I32(depth + 1)]), Err(Trap::CallStackExhausted)));
+        assert_eq!(instance.invoke("nested", &[]).unwrap(), [Value::I32(42)]);
+    }
+}
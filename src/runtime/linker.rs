@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{FuncType, GlobalType, MemType, Module, TableType};
+use crate::runtime::store::{ExternVal, FuncInst, GlobalInst, Store};
+use crate::runtime::{Caller, Instance, Memory, RuntimeError, Table, Trap, Value};
+
+// Resolves the imports of modules by their module and field names. Definitions are host
+// functions, tables, memories and globals created by the embedder, or the exports of
+// registered instances.
+pub struct Linker {
+    store: Store,
+    definitions: HashMap<(String, String), ExternVal>,
+}
+
+impl Linker {
+    pub fn new(store: &Store) -> Self {
+        Self {
+            store: store.clone(),
+            definitions: HashMap::new(),
+        }
+    }
+
+    // Defines the host function `module.name` of type `func_type`, replacing any previous
+    // definition of that name.
+    pub fn func<F>(&mut self, module: &str, name: &str, func_type: FuncType, code: F)
+    where
+        F: Fn(&mut Caller, &[Value]) -> Result<Vec<Value>, Trap> + 'static,
+    {
+        let mut inner = self.store.inner.borrow_mut();
+        let addr = inner.funcs.len();
+        inner.funcs.push(FuncInst::Host { func_type, code: Rc::new(code) });
+        drop(inner);
+        self.define(module, name, ExternVal::Func(addr));
+    }
+
+    // Allocates a table of `table_type` with null elements in the store and defines it as
+    // `module.name`.
+    pub fn table(&mut self, module: &str, name: &str, table_type: &TableType) {
+        let mut inner = self.store.inner.borrow_mut();
+        let addr = inner.tables.len();
+        inner.tables.push(Table::new(table_type));
+        drop(inner);
+        self.define(module, name, ExternVal::Table(addr));
+    }
+
+    // Allocates a memory of `mem_type` in the store and defines it as `module.name`.
+    pub fn memory(&mut self, module: &str, name: &str, mem_type: &MemType) {
+        let mut inner = self.store.inner.borrow_mut();
+        let addr = inner.mems.len();
+        inner.mems.push(Memory::new(mem_type));
+        drop(inner);
+        self.define(module, name, ExternVal::Mem(addr));
+    }
+
+    // Allocates a global holding `value` in the store and defines it as `module.name`.
+    pub fn global(&mut self, module: &str, name: &str, mutable: bool, value: Value) {
+        let mut inner = self.store.inner.borrow_mut();
+        let addr = inner.globals.len();
+        let global_type = GlobalType { mutable, value_type: value.value_type() };
+        inner.globals.push(GlobalInst { global_type, value });
+        drop(inner);
+        self.define(module, name, ExternVal::Global(addr));
+    }
+
+    pub fn define(&mut self, module: &str, name: &str, value: ExternVal) {
+        self.definitions.insert((module.to_string(), name.to_string()), value);
+    }
+
+    pub fn get(&self, module: &str, name: &str) -> Option<ExternVal> {
+        self.definitions.get(&(module.to_string(), name.to_string())).copied()
+    }
+
+    // Makes every export of `instance` importable from the module `name`.
+    pub fn instance(&mut self, name: &str, instance: &Instance) {
+        for export in instance.exports() {
+            self.define(name, &export.name, export.value);
+        }
+    }
+
+    // Instantiates `module` with the definitions its imports name. Instantiation fails if an
+    // import is not defined or its definition does not match the type of the import, or if
+    // initializing the instance traps.
+    pub fn instantiate(&self, module: &Module) -> Result<Instance, Trap> {
+        let imports = module.imports.iter()
+            .map(|import| {
+                self.get(&import.module, &import.name).ok_or_else(|| RuntimeError::UnknownImport {
+                    module: import.module.clone(),
+                    name: import.name.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Instance::with_imports(&self.store, module, &imports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::ast::{Limits, NumberType, ValueType};
+    use crate::parser;
+
+    const I32: ValueType = ValueType::NumberType(NumberType::I32);
+
+    #[test]
+    fn links_host_functions_and_instances() {
+        let store = Store::new();
+        let mut linker = Linker::new(&store);
+        let printed = Rc::new(RefCell::new(String::new()));
+        let output = Rc::clone(&printed);
+        linker.func("env", "print_string", (vec![I32, I32], vec![]), move |caller, args| {
+            let (Value::I32(addr), Value::I32(len)) = (args[0], args[1]) else { unreachable!() };
+            let memory = caller.memory().expect("caller has a memory");
+            output.borrow_mut().push_str(&memory.read_string(addr as usize, len as usize)?);
+            Ok(vec![])
+        });
+        linker.memory("env", "buffer", &Limits { min: 1, max: None });
+
+        let lib = parser::parse(r#"
+            (module
+              (func (export "twice") (param i32) (result i32)
+                (i32.mul (local.get 0) (i32.const 2))))
+        "#).unwrap();
+        let lib = linker.instantiate(&lib).unwrap();
+        linker.instance("lib", &lib);
+
+        let main = parser::parse(r#"
+            (module
+              (import "env" "print_string" (func $print (param i32 i32)))
+              (import "env" "buffer" (memory 1))
+              (import "lib" "twice" (func $twice (param i32) (result i32)))
+              (data (i32.const 16) "hello world!")
+              (func (export "main") (result i32)
+                (call $print (i32.const 16) (call $twice (i32.const 6)))
+                (call $twice (i32.const 21))))
+        "#).unwrap();
+        let main = linker.instantiate(&main).unwrap();
+        assert_eq!(main.invoke("main", &[]).unwrap(), [Value::I32(42)]);
+        assert_eq!(*printed.borrow(), "hello world!");
+    }
+
+    #[test]
+    fn rejects_unlinkable_imports() {
+        let store = Store::new();
+        let mut linker = Linker::new(&store);
+        linker.func("env", "f", (vec![I32], vec![]), |_, _| Ok(vec![]));
+        linker.memory("env", "memory", &Limits { min: 1, max: Some(2) });
+
+        let error = |source: &str| {
+            let module = parser::parse(source).unwrap();
+            linker.instantiate(&module).err().unwrap().to_string()
+        };
+        assert_eq!(error(r#"(module (import "env" "g" (func)))"#), "Unknown import `env` `g`");
+        assert_eq!(error(r#"(module (import "env" "f" (func)))"#), "Incompatible import type `env` `f`");
+        assert_eq!(error(r#"(module (import "env" "f" (memory 1)))"#), "Incompatible import type `env` `f`");
+        assert_eq!(error(r#"(module (import "env" "memory" (memory 2)))"#), "Incompatible import type `env` `memory`");
+        assert_eq!(error(r#"(module (import "env" "memory" (memory 1 1)))"#), "Incompatible import type `env` `memory`");
+        assert!(linker.instantiate(&parser::parse(r#"(module (import "env" "memory" (memory 0 3)))"#).unwrap()).is_ok());
+    }
+}
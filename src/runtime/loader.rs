@@ -1,8 +1,11 @@
 
 use std::cell::Cell;
-use std::error::Error;
-use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
-use crate::ast::{ValueType, NumberType, VectorType, ReferenceType, Type};
+use crate::ast::{
+    BlockType, Custom, Data, DataMode, Elem, ElemMode, Export, ExportDesc, Func, Global, GlobalType, Import,
+    ImportDesc, Instr, Limits, Mem, MemArg, Module, NameMap, Names, NumberType, ReferenceType, ResultType, Start, Table, TableType,
+    Type, ValueType, VectorType, MAX_NESTING_DEPTH,
+};
+use crate::runtime::RuntimeError;
 
 pub struct Reader {
     data: Vec<u8>,
@@ -21,19 +24,115 @@ impl Reader {
         self.data.len()
     }
 
-    pub fn dword(&self) -> u32 {
-        let prev = self.pos.replace(self.pos.get() + 4);
-        u32::from_le_bytes(self.data[prev..self.pos.get()].try_into().unwrap())
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }
 
-    pub fn bytes(&self, num: usize) -> &[u8] {
-        let prev = self.pos.replace(self.pos.get() + num);
-        &self.data[prev..self.pos.get()]
+    pub fn dword(&self) -> Result<u32, RuntimeError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
     }
 
-    pub fn byte(&self) -> u8 {
-        let prev = self.pos.replace(self.pos.get() + 1);
-        self.data[prev]
+    pub fn qword(&self) -> Result<u64, RuntimeError> {
+        let bytes = self.bytes(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn bytes(&self, num: usize) -> Result<&[u8], RuntimeError> {
+        let prev = self.pos.get();
+        match prev.checked_add(num) {
+            Some(end) if end <= self.data.len() => {
+                self.pos.set(end);
+                Ok(&self.data[prev..end])
+            },
+            _ => Err(RuntimeError::UnexpectedEof { offset: prev }),
+        }
+    }
+
+    pub fn peek(&self) -> Result<u8, RuntimeError> {
+        let pos = self.pos.get();
+        self.data.get(pos).copied().ok_or(RuntimeError::UnexpectedEof { offset: pos })
+    }
+
+    pub fn byte(&self) -> Result<u8, RuntimeError> {
+        let prev = self.pos.get();
+        let byte = *self.data.get(prev).ok_or(RuntimeError::UnexpectedEof { offset: prev })?;
+        self.pos.set(prev + 1);
+        Ok(byte)
+    }
+
+    pub fn u32(&self) -> Result<u32, RuntimeError> {
+        Ok(self.unsigned(32)? as u32)
+    }
+
+    pub fn u64(&self) -> Result<u64, RuntimeError> {
+        self.unsigned(64)
+    }
+
+    pub fn i32(&self) -> Result<i32, RuntimeError> {
+        Ok(self.signed(32)? as i32)
+    }
+
+    // Block types encode type indices as a positive s33.
+    pub fn i33(&self) -> Result<i64, RuntimeError> {
+        self.signed(33)
+    }
+
+    pub fn i64(&self) -> Result<i64, RuntimeError> {
+        self.signed(64)
+    }
+
+    // Unsigned LEB128 with at most ceil(bits / 7) bytes. The unused bits of the last byte
+    // must be zero.
+    fn unsigned(&self, bits: u32) -> Result<u64, RuntimeError> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let remaining = bits - shift;
+            if remaining <= 7 {
+                if byte & 0x80 != 0 {
+                    return Err(RuntimeError::IntegerTooLong);
+                }
+                if (byte & 0x7F) >> remaining != 0 {
+                    return Err(RuntimeError::IntegerTooLarge);
+                }
+            }
+            result |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    // Signed LEB128 with at most ceil(bits / 7) bytes. The unused bits of the last byte
+    // must be a sign extension of the value.
+    fn signed(&self, bits: u32) -> Result<i64, RuntimeError> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let remaining = bits - shift;
+            if remaining <= 7 {
+                if byte & 0x80 != 0 {
+                    return Err(RuntimeError::IntegerTooLong);
+                }
+                let mask = (0xFFu8 << (remaining - 1)) & 0x7F;
+                let unused = byte & mask;
+                if unused != 0 && unused != mask {
+                    return Err(RuntimeError::IntegerTooLarge);
+                }
+            }
+            result |= ((byte & 0x7F) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+                return Ok(result);
+            }
+        }
     }
 }
 
@@ -42,52 +141,673 @@ fn check_header(wasm: &Reader) -> Result<(), RuntimeError> {
         return Err(RuntimeError::InvalidModuleLength);
     }
 
-    if wasm.bytes(4) != *b"\0asm" {
+    if wasm.bytes(4)? != *b"\0asm" {
         return Err(RuntimeError::InvalidMagicNumber);
     }
 
-    if wasm.dword() != 1 {
+    if wasm.dword()? != 1 {
         return Err(RuntimeError::InvalidVersionNumber);
     }
     Ok(())
 }
 
-fn parse_section(wasm: &Reader) -> Result<(), RuntimeError> {
-    let section_code = wasm.byte();
-    let size = wasm.dword();
-    let prev = wasm.pos.replace(wasm.pos.get() + size as usize);
+// Implementation limit on the number of locals of a single function. The binary format
+// allows up to 2^32, which would let a tiny module allocate gigabytes.
+const MAX_LOCALS: u64 = 50000;
+
+pub(crate) mod section {
+    pub const CUSTOM: u8 = 0;
+    pub const TYPE: u8 = 1;
+    pub const IMPORT: u8 = 2;
+    pub const FUNCTION: u8 = 3;
+    pub const TABLE: u8 = 4;
+    pub const MEMORY: u8 = 5;
+    pub const GLOBAL: u8 = 6;
+    pub const EXPORT: u8 = 7;
+    pub const START: u8 = 8;
+    pub const ELEMENT: u8 = 9;
+    pub const CODE: u8 = 10;
+    pub const DATA: u8 = 11;
+    pub const DATA_COUNT: u8 = 12;
+
+    // The order standard sections appear in. The data count section comes before the code
+    // section, although its id was assigned after those of the code and data sections.
+    pub const ORDER: [u8; 12] = [TYPE, IMPORT, FUNCTION, TABLE, MEMORY, GLOBAL, EXPORT, START, ELEMENT, DATA_COUNT, CODE, DATA];
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Module, RuntimeError> {
+    let wasm = Reader::new(bytes.to_vec());
+    check_header(&wasm)?;
+
+    let mut module = Module::default();
+    let mut last_section = section::CUSTOM;
+    let mut has_code = false;
+    while wasm.pos.get() < wasm.len() {
+        last_section = parse_section(&wasm, &mut module, last_section)?;
+        has_code |= last_section == section::CODE;
+    }
+
+    if !module.funcs.is_empty() && !has_code {
+        return Err(RuntimeError::FunctionCodeMismatch);
+    }
+    match module.data_count {
+        Some(count) if count as usize != module.data.len() => return Err(RuntimeError::DataCountMismatch),
+        None if module.uses_data_indices() => return Err(RuntimeError::DataCountRequired),
+        _ => {},
+    }
+    Ok(module)
+}
+
+// Decodes the contents of the `name` custom section.
+// namesec ::= section_0(namedata)
+// namedata ::= n:name (must be "name") namesubsection*
+// namesubsection_N(B) ::= N:byte size:u32 B
+//
+// Unknown subsections, such as label names, are skipped.
+pub fn decode_names(data: &[u8]) -> Result<Names, RuntimeError> {
+    let wasm = Reader::new(data.to_vec());
+    let mut names = Names::default();
+    while wasm.pos.get() < wasm.len() {
+        let id = wasm.byte()?;
+        let size = wasm.u32()? as usize;
+        let end = section_end(&wasm, size)?;
+        match id {
+            0 => names.module = Some(parse_name(&wasm)?),
+            1 => names.funcs = parse_namemap(&wasm)?,
+            2 => {
+                let num_funcs = wasm.u32()?;
+                for _ in 0..num_funcs {
+                    let idx = wasm.u32()? as usize;
+                    names.locals.insert(idx, parse_namemap(&wasm)?);
+                }
+            },
+            4 => names.types = parse_namemap(&wasm)?,
+            5 => names.tables = parse_namemap(&wasm)?,
+            6 => names.mems = parse_namemap(&wasm)?,
+            7 => names.globals = parse_namemap(&wasm)?,
+            8 => names.elems = parse_namemap(&wasm)?,
+            9 => names.datas = parse_namemap(&wasm)?,
+            _ => wasm.pos.set(end),
+        }
+        if wasm.pos.get() != end {
+            return Err(RuntimeError::InvalidSectionSize);
+        }
+    }
+    Ok(names)
+}
+
+// namemap ::= vec(nameassoc)
+// nameassoc ::= idx name
+fn parse_namemap(wasm: &Reader) -> Result<NameMap, RuntimeError> {
+    let num_names = wasm.u32()?;
+    let mut names = NameMap::new();
+    for _ in 0..num_names {
+        let idx = wasm.u32()? as usize;
+        names.insert(idx, parse_name(wasm)?);
+    }
+    Ok(names)
+}
+
+// Checks that a section or function body of `size` bytes starting at the current position
+// lies within the module.
+fn section_end(wasm: &Reader, size: usize) -> Result<usize, RuntimeError> {
+    let start = wasm.pos.get();
+    match start.checked_add(size) {
+        Some(end) if end <= wasm.len() => Ok(end),
+        _ => Err(RuntimeError::UnexpectedEof { offset: start }),
+    }
+}
+
+// Parses one section into `module` and returns the id of the last non-custom section seen,
+// which is used to enforce that standard sections appear at most once and in order.
+fn parse_section(wasm: &Reader, module: &mut Module, last_section: u8) -> Result<u8, RuntimeError> {
+    let section_code = wasm.byte()?;
+    let size = wasm.u32()? as usize;
+    let end = section_end(wasm, size)?;
+    if section_code != section::CUSTOM {
+        let position = |id| section::ORDER.iter().position(|section| *section == id);
+        match (position(section_code), position(last_section)) {
+            (None, _) => return Err(RuntimeError::InvalidSectionCode),
+            (Some(position), Some(last)) if position <= last => return Err(RuntimeError::InvalidSectionOrder),
+            _ => {},
+        }
+    }
+
     match section_code {
-        section::TYPE => parse_type_section(wasm),
-        section::IMPORT => parse_import_section(wasm),
-        section::FUNCTION => parse_function_section(wasm),
-        section::TABLE => parse_table_section(wasm),
-        section::MEMORY => parse_memory_section(wasm),
-        section::GLOBAL => parse_global_section(wasm),
-        section::EXPORT => parse_export_section(wasm),
-        section::START => parse_start_section(wasm),
-        section::ELEMENT => parse_element_section(wasm),
-        section::CODE => parse_code_section(wasm),
-        section::DATA => parse_data_section(wasm),
-        _ => Err(RuntimeError::InvalidSectionCode),
-    }?;
-    wasm.pos.replace(prev);
-    Ok(())
+        section::CUSTOM => module.customs.push(parse_custom_section(wasm, end)?),
+        section::TYPE => module.types = parse_type_section(wasm)?,
+        section::IMPORT => module.imports = parse_import_section(wasm)?,
+        section::FUNCTION => module.funcs = parse_function_section(wasm)?,
+        section::TABLE => module.tables = parse_table_section(wasm)?,
+        section::MEMORY => module.mems = parse_memory_section(wasm)?,
+        section::GLOBAL => module.globals = parse_global_section(wasm)?,
+        section::EXPORT => module.exports = parse_export_section(wasm)?,
+        section::START => module.start = Some(parse_start_section(wasm)?),
+        section::ELEMENT => module.elem = parse_element_section(wasm)?,
+        section::CODE => parse_code_section(wasm, &mut module.funcs)?,
+        section::DATA => module.data = parse_data_section(wasm)?,
+        // datacountsec ::= section_12(u32?)
+        section::DATA_COUNT => module.data_count = Some(wasm.u32()?),
+        _ => return Err(RuntimeError::InvalidSectionCode),
+    }
+
+    if wasm.pos.get() != end {
+        return Err(RuntimeError::InvalidSectionSize);
+    }
+    Ok(if section_code == section::CUSTOM { last_section } else { section_code })
+}
+
+// customsec ::= section_0(name byte*)
+fn parse_custom_section(wasm: &Reader, end: usize) -> Result<Custom, RuntimeError> {
+    let name = parse_name(wasm)?;
+    let remaining = end.checked_sub(wasm.pos.get()).ok_or(RuntimeError::InvalidSectionSize)?;
+    let data = wasm.bytes(remaining)?.to_vec();
+    Ok(Custom { name, data })
 }
 
+// typesec ::= section_1(vec(functype))
 fn parse_type_section(wasm: &Reader) -> Result<Vec<Type>, RuntimeError> {
-    if wasm.byte() != section::TYPE {
-        return Err(RuntimeError::InvalidSectionCode);
+    let num_types = wasm.u32()?;
+    let mut types = vec![];
+    for _ in 0..num_types {
+        types.push(parse_functype(wasm)?);
+    }
+    Ok(types)
+}
+
+// importsec ::= section_2(vec(import))
+// import ::= mod:name nm:name d:importdesc
+// importdesc ::= 0x00 x:typeidx | 0x01 tt:tabletype | 0x02 mt:memtype | 0x03 gt:globaltype
+fn parse_import_section(wasm: &Reader) -> Result<Vec<Import>, RuntimeError> {
+    let num_imports = wasm.u32()?;
+    let mut imports = vec![];
+    for _ in 0..num_imports {
+        let module = parse_name(wasm)?;
+        let name = parse_name(wasm)?;
+        let desc = match wasm.byte()? {
+            0x00 => ImportDesc::Func(wasm.u32()? as usize),
+            0x01 => ImportDesc::Table(parse_tabletype(wasm)?),
+            0x02 => ImportDesc::Mem(parse_limits(wasm)?),
+            0x03 => ImportDesc::Global(parse_globaltype(wasm)?),
+            _ => return Err(RuntimeError::InvalidImportType),
+        };
+        imports.push(Import { module, name, desc });
+    }
+    Ok(imports)
+}
+
+// funcsec ::= section_3(vec(typeidx))
+// The bodies are filled in by the code section.
+fn parse_function_section(wasm: &Reader) -> Result<Vec<Func>, RuntimeError> {
+    let num_funcs = wasm.u32()?;
+    let mut funcs = vec![];
+    for _ in 0..num_funcs {
+        funcs.push(Func {
+            f_type: wasm.u32()? as usize,
+            locals: vec![],
+            body: vec![],
+        });
+    }
+    Ok(funcs)
+}
+
+// tablesec ::= section_4(vec(table))
+// table ::= tt:tabletype
+fn parse_table_section(wasm: &Reader) -> Result<Vec<Table>, RuntimeError> {
+    let num_tables = wasm.u32()?;
+    let mut tables = vec![];
+    for _ in 0..num_tables {
+        tables.push(Table { table_type: parse_tabletype(wasm)? });
+    }
+    Ok(tables)
+}
+
+// memsec ::= section_5(vec(mem))
+// mem ::= mt:memtype
+fn parse_memory_section(wasm: &Reader) -> Result<Vec<Mem>, RuntimeError> {
+    let num_mems = wasm.u32()?;
+    let mut mems = vec![];
+    for _ in 0..num_mems {
+        mems.push(Mem { mem_type: parse_limits(wasm)? });
+    }
+    Ok(mems)
+}
+
+// globalsec ::= section_6(vec(global))
+// global ::= gt:globaltype e:expr
+fn parse_global_section(wasm: &Reader) -> Result<Vec<Global>, RuntimeError> {
+    let num_globals = wasm.u32()?;
+    let mut globals = vec![];
+    for _ in 0..num_globals {
+        let global_type = parse_globaltype(wasm)?;
+        let init = parse_expr(wasm)?;
+        globals.push(Global { global_type, init });
+    }
+    Ok(globals)
+}
+
+// exportsec ::= section_7(vec(export))
+// export ::= nm:name d:exportdesc
+fn parse_export_section(wasm: &Reader) -> Result<Vec<Export>, RuntimeError> {
+    let num_exports = wasm.u32()?;
+    let mut exports = vec![];
+    for _ in 0..num_exports {
+        let name = parse_name(wasm).map_err(|_| RuntimeError::InvalidExportName)?;
+        let desc = match wasm.byte()? {
+            0x00 => ExportDesc::Func(wasm.u32()? as usize),
+            0x01 => ExportDesc::Table(wasm.u32()? as usize),
+            0x02 => ExportDesc::Mem(wasm.u32()? as usize),
+            0x03 => ExportDesc::Global(wasm.u32()? as usize),
+            _ => return Err(RuntimeError::InvalidExportType),
+        };
+        exports.push(Export { name, desc });
+    }
+    Ok(exports)
+}
+
+// startsec ::= section_8(start)
+// start ::= x:funcidx
+fn parse_start_section(wasm: &Reader) -> Result<Start, RuntimeError> {
+    Ok(Start { func: wasm.u32()? as usize })
+}
+
+// elemsec ::= section_9(vec(elem))
+// elem ::= 0:u32 e:expr y*:vec(funcidx)
+//        | 1:u32 et:elemkind y*:vec(funcidx)
+//        | 2:u32 x:tableidx e:expr et:elemkind y*:vec(funcidx)
+//        | 3:u32 et:elemkind y*:vec(funcidx)
+//        | 4:u32 e:expr el*:vec(expr)
+//        | 5:u32 et:reftype el*:vec(expr)
+//        | 6:u32 x:tableidx e:expr et:reftype el*:vec(expr)
+//        | 7:u32 et:reftype el*:vec(expr)
+//
+// Bit 0 marks passive or declarative segments, bit 1 an explicit table index or the
+// declarative mode, and bit 2 initializers given as expressions rather than function indices.
+fn parse_element_section(wasm: &Reader) -> Result<Vec<Elem>, RuntimeError> {
+    let num_elems = wasm.u32()?;
+    let mut elems = vec![];
+    for _ in 0..num_elems {
+        let kind = wasm.u32()?;
+        if kind > 7 {
+            return Err(RuntimeError::InvalidSegmentKind);
+        }
+
+        let mode = match kind & 0b011 {
+            0b000 => ElemMode::Active { table: 0, offset: parse_expr(wasm)? },
+            0b010 => {
+                let table = wasm.u32()? as usize;
+                ElemMode::Active { table, offset: parse_expr(wasm)? }
+            },
+            0b001 => ElemMode::Passive,
+            _ => ElemMode::Declarative,
+        };
+
+        let uses_exprs = kind & 0b100 != 0;
+        // Segments of kind 0 and 4 have neither an element kind nor a type, they are funcref
+        let elem_type = match (kind & 0b011, uses_exprs) {
+            (0b000, _) => ReferenceType::FuncRef,
+            (_, true) => parse_reftype(wasm)?,
+            (_, false) => match wasm.byte()? {
+                0x00 => ReferenceType::FuncRef,
+                _ => return Err(RuntimeError::InvalidSegmentKind),
+            },
+        };
+
+        let num_items = wasm.u32()?;
+        let mut init = vec![];
+        for _ in 0..num_items {
+            if uses_exprs {
+                init.push(parse_expr(wasm)?);
+            } else {
+                init.push(vec![Instr::RefFunc(wasm.u32()? as usize)]);
+            }
+        }
+        elems.push(Elem { elem_type, init, mode });
+    }
+    Ok(elems)
+}
+
+// codesec ::= section_10(vec(code))
+// code ::= size:u32 code:func
+// func ::= (t*)*:vec(locals) e:expr
+// locals ::= n:u32 t:valtype
+fn parse_code_section(wasm: &Reader, funcs: &mut [Func]) -> Result<(), RuntimeError> {
+    let num_codes = wasm.u32()? as usize;
+    if num_codes != funcs.len() {
+        return Err(RuntimeError::FunctionCodeMismatch);
+    }
+    for func in funcs.iter_mut() {
+        let size = wasm.u32()? as usize;
+        let end = section_end(wasm, size)?;
+
+        let num_locals = wasm.u32()?;
+        let mut total_locals = 0u64;
+        for _ in 0..num_locals {
+            let count = wasm.u32()?;
+            total_locals += count as u64;
+            if total_locals > MAX_LOCALS {
+                return Err(RuntimeError::TooManyLocals);
+            }
+            let count = count as usize;
+            let value_type = parse_valuetype(wasm)?;
+            func.locals.extend(std::iter::repeat_n(value_type, count));
+        }
+        func.body = parse_expr(wasm)?;
+
+        if wasm.pos.get() != end {
+            return Err(RuntimeError::InvalidSectionSize);
+        }
+    }
+    Ok(())
+}
+
+// datasec ::= section_11(vec(data))
+// data ::= 0:u32 e:expr b*:vec(byte)
+//        | 1:u32 b*:vec(byte)
+//        | 2:u32 x:memidx e:expr b*:vec(byte)
+fn parse_data_section(wasm: &Reader) -> Result<Vec<Data>, RuntimeError> {
+    let num_datas = wasm.u32()?;
+    let mut datas = vec![];
+    for _ in 0..num_datas {
+        let mode = match wasm.u32()? {
+            0 => DataMode::Active { memory: 0, offset: parse_expr(wasm)? },
+            1 => DataMode::Passive,
+            2 => {
+                let memory = wasm.u32()? as usize;
+                DataMode::Active { memory, offset: parse_expr(wasm)? }
+            },
+            _ => return Err(RuntimeError::InvalidSegmentKind),
+        };
+        let len = wasm.u32()? as usize;
+        let init = wasm.bytes(len)?.to_vec();
+        datas.push(Data { init, mode });
+    }
+    Ok(datas)
+}
+
+// functype ::= 0x60 rt1:resulttype rt2:resulttype
+fn parse_functype(wasm: &Reader) -> Result<Type, RuntimeError> {
+    if wasm.byte()? != 0x60 {
+        return Err(RuntimeError::InvalidFunctionType);
     }
+    let params = parse_resulttype(wasm)?;
+    let results = parse_resulttype(wasm)?;
+    Ok((params, results))
+}
 
-    let size = wasm.byte();
-    let num_types = wasm.byte();
+// resulttype ::= t*:vec(valtype)
+fn parse_resulttype(wasm: &Reader) -> Result<ResultType, RuntimeError> {
+    let num_types = wasm.u32()?;
     let mut types = vec![];
+    for _ in 0..num_types {
+        types.push(parse_valuetype(wasm)?);
+    }
+    Ok(types)
+}
+
+// expr ::= (in:instr)* 0x0B
+// Blocks are decoded with an explicit stack of the enclosing blocks rather than by recursion,
+// so deeply nested input is rejected with an error instead of overflowing the native stack.
+fn parse_expr(wasm: &Reader) -> Result<Vec<Instr>, RuntimeError> {
+    let mut blocks: Vec<OpenBlock> = vec![];
+    let mut instrs = vec![];
+    loop {
+        let instr = match wasm.byte()? {
+            // Control instructions
+            opcode @ 0x02..=0x04 => {
+                if blocks.len() == MAX_NESTING_DEPTH {
+                    return Err(RuntimeError::NestingTooDeep);
+                }
+                let block_type = parse_blocktype(wasm)?;
+                let outer = std::mem::take(&mut instrs);
+                blocks.push(OpenBlock { opcode, block_type, outer, then: None });
+                continue;
+            },
+            0x05 => match blocks.last_mut() {
+                Some(OpenBlock { opcode: 0x04, then: then @ None, .. }) => {
+                    *then = Some(std::mem::take(&mut instrs));
+                    continue;
+                },
+                _ => return Err(RuntimeError::InvalidInstruction),
+            },
+            0x0B => match blocks.pop() {
+                None => return Ok(instrs),
+                Some(OpenBlock { opcode, block_type, outer, then }) => {
+                    let body = std::mem::replace(&mut instrs, outer);
+                    match (opcode, then) {
+                        (0x02, _) => Instr::Block(block_type, body),
+                        (0x03, _) => Instr::Loop(block_type, body),
+                        (_, Some(then)) => Instr::If(block_type, then, body),
+                        (_, None) => Instr::If(block_type, body, vec![]),
+                    }
+                },
+            },
+            opcode => parse_instr(wasm, opcode)?,
+        };
+        instrs.push(instr);
+    }
+}
+
+// A block, loop or if whose `end` has not been decoded yet, with the instructions preceding
+// it and, after an `else`, the instructions of the then branch.
+struct OpenBlock {
+    opcode: u8,
+    block_type: BlockType,
+    outer: Vec<Instr>,
+    then: Option<Vec<Instr>>,
+}
+
+// Decodes an instruction other than a block, loop, if, else or end.
+fn parse_instr(wasm: &Reader, opcode: u8) -> Result<Instr, RuntimeError> {
+    let instr = match opcode {
+        0x0C => Instr::Br(wasm.u32()? as usize),
+        0x0D => Instr::BrIf(wasm.u32()? as usize),
+        0x0E => {
+            let num_labels = wasm.u32()?;
+            let mut labels = vec![];
+            for _ in 0..num_labels {
+                labels.push(wasm.u32()? as usize);
+            }
+            Instr::BrTable(labels, wasm.u32()? as usize)
+        },
+        0x10 => Instr::Call(wasm.u32()? as usize),
+        0x11 => {
+            let type_idx = wasm.u32()? as usize;
+            Instr::CallIndirect(wasm.u32()? as usize, type_idx)
+        },
+
+        // Reference instructions
+        0xD0 => Instr::RefNull(parse_reftype(wasm)?),
+        0xD2 => Instr::RefFunc(wasm.u32()? as usize),
+
+        // Parametric instructions
+        0x1B => Instr::Select(None),
+        0x1C => Instr::Select(Some(parse_resulttype(wasm)?)),
+
+        // Variable instructions
+        0x20 => Instr::LocalGet(wasm.u32()? as usize),
+        0x21 => Instr::LocalSet(wasm.u32()? as usize),
+        0x22 => Instr::LocalTee(wasm.u32()? as usize),
+        0x23 => Instr::GlobalGet(wasm.u32()? as usize),
+        0x24 => Instr::GlobalSet(wasm.u32()? as usize),
+
+        // Table instructions
+        0x25 => Instr::TableGet(wasm.u32()? as usize),
+        0x26 => Instr::TableSet(wasm.u32()? as usize),
 
+        // Memory instructions
+        0x3F => {
+            parse_zero_byte(wasm)?;
+            Instr::MemorySize
+        },
+        0x40 => {
+            parse_zero_byte(wasm)?;
+            Instr::MemoryGrow
+        },
+
+        // Numeric instructions
+        0x41 => Instr::I32Const(wasm.i32()?),
+        0x42 => Instr::I64Const(wasm.i64()?),
+        0x43 => Instr::F32Const(wasm.dword()?),
+        0x44 => Instr::F64Const(wasm.qword()?),
+
+        0xFC => parse_prefixed_fc(wasm)?,
+        0xFD => parse_prefixed_fd(wasm)?,
+
+        opcode => parse_table_instr(wasm, opcode as u32)?,
+    };
+    Ok(instr)
+}
+
+// Instructions behind the 0xFC prefix other than the saturating truncations.
+fn parse_prefixed_fc(wasm: &Reader) -> Result<Instr, RuntimeError> {
+    let opcode = wasm.u32()?;
+    let instr = match opcode {
+        8 => {
+            let data_idx = wasm.u32()? as usize;
+            parse_zero_byte(wasm)?;
+            Instr::MemoryInit(data_idx)
+        },
+        9 => Instr::DataDrop(wasm.u32()? as usize),
+        10 => {
+            parse_zero_byte(wasm)?;
+            parse_zero_byte(wasm)?;
+            Instr::MemoryCopy
+        },
+        11 => {
+            parse_zero_byte(wasm)?;
+            Instr::MemoryFill
+        },
+        12 => {
+            let elem_idx = wasm.u32()? as usize;
+            Instr::TableInit(wasm.u32()? as usize, elem_idx)
+        },
+        13 => Instr::ElemDrop(wasm.u32()? as usize),
+        14 => {
+            let dst = wasm.u32()? as usize;
+            Instr::TableCopy(dst, wasm.u32()? as usize)
+        },
+        15 => Instr::TableGrow(wasm.u32()? as usize),
+        16 => Instr::TableSize(wasm.u32()? as usize),
+        17 => Instr::TableFill(wasm.u32()? as usize),
+        _ => return parse_table_instr(wasm, prefixed(0xFC, opcode)?),
+    };
+    Ok(instr)
+}
 
+// Vector instructions behind the 0xFD prefix with immediates that have no table.
+fn parse_prefixed_fd(wasm: &Reader) -> Result<Instr, RuntimeError> {
+    let opcode = wasm.u32()?;
+    let instr = match opcode {
+        12 => Instr::V128Const(u128::from_le_bytes(wasm.bytes(16)?.try_into().unwrap())),
+        13 => {
+            let lanes = wasm.bytes(16)?.try_into().unwrap();
+            Instr::I8x16Shuffle(lanes)
+        },
+        _ => return parse_table_instr(wasm, prefixed(0xFD, opcode)?),
+    };
+    Ok(instr)
+}
+
+fn prefixed(prefix: u8, opcode: u32) -> Result<u32, RuntimeError> {
+    if opcode > 0xFF_FFFF {
+        return Err(RuntimeError::InvalidInstruction);
+    }
+    Ok((prefix as u32) << 24 | opcode)
+}
+
+// Looks up `opcode` in the instruction tables of `ast::Instr` and parses the immediates
+// shared by that family.
+fn parse_table_instr(wasm: &Reader, opcode: u32) -> Result<Instr, RuntimeError> {
+    if let Some((instr, _)) = Instr::plain_by_opcode(opcode) {
+        Ok(instr)
+    } else if let Some((instr, _)) = Instr::memory_by_opcode(opcode) {
+        Ok(instr(parse_memarg(wasm)?))
+    } else if let Some((instr, _)) = Instr::memory_lane_by_opcode(opcode) {
+        let memarg = parse_memarg(wasm)?;
+        Ok(instr(memarg, wasm.byte()?))
+    } else if let Some((instr, _)) = Instr::lane_by_opcode(opcode) {
+        Ok(instr(wasm.byte()?))
+    } else {
+        Err(RuntimeError::InvalidInstruction)
+    }
+}
+
+// blocktype ::= 0x40 | t:valtype | x:s33
+fn parse_blocktype(wasm: &Reader) -> Result<BlockType, RuntimeError> {
+    match wasm.peek()? {
+        0x40 => {
+            wasm.byte()?;
+            Ok(BlockType::Empty)
+        },
+        0x7F | 0x7E | 0x7D | 0x7C | 0x7B | 0x70 | 0x6F => Ok(BlockType::Value(parse_valuetype(wasm)?)),
+        _ => match wasm.i33()? {
+            idx @ 0.. => Ok(BlockType::Type(idx as usize)),
+            _ => Err(RuntimeError::InvalidBlockType),
+        },
+    }
+}
+
+// memarg ::= a:u32 o:u32
+fn parse_memarg(wasm: &Reader) -> Result<MemArg, RuntimeError> {
+    let align = wasm.u32()?;
+    let offset = wasm.u32()?;
+    Ok(MemArg { offset, align })
+}
+
+// Memory instructions carry a reserved memory index that must be zero.
+fn parse_zero_byte(wasm: &Reader) -> Result<(), RuntimeError> {
+    match wasm.byte()? {
+        0x00 => Ok(()),
+        _ => Err(RuntimeError::ZeroByteExpected),
+    }
+}
+
+// tabletype ::= et:reftype lim:limits
+fn parse_tabletype(wasm: &Reader) -> Result<TableType, RuntimeError> {
+    let elem_type = parse_reftype(wasm)?;
+    let limits = parse_limits(wasm)?;
+    Ok(TableType { limits, elem_type })
+}
+
+// limits ::= 0x00 n:u32 | 0x01 n:u32 m:u32
+fn parse_limits(wasm: &Reader) -> Result<Limits, RuntimeError> {
+    match wasm.byte()? {
+        0x00 => Ok(Limits { min: wasm.u32()?, max: None }),
+        0x01 => Ok(Limits { min: wasm.u32()?, max: Some(wasm.u32()?) }),
+        _ => Err(RuntimeError::InvalidLimits),
+    }
+}
+
+// globaltype ::= t:valtype m:mut
+// mut ::= 0x00 | 0x01
+fn parse_globaltype(wasm: &Reader) -> Result<GlobalType, RuntimeError> {
+    let value_type = parse_valuetype(wasm)?;
+    let mutable = match wasm.byte()? {
+        0x00 => false,
+        0x01 => true,
+        _ => return Err(RuntimeError::InvalidMutability),
+    };
+    Ok(GlobalType { mutable, value_type })
+}
+
+// reftype ::= 0x70 | 0x6F
+fn parse_reftype(wasm: &Reader) -> Result<ReferenceType, RuntimeError> {
+    match wasm.byte()? {
+        0x70 => Ok(ReferenceType::FuncRef),
+        0x6F => Ok(ReferenceType::ExternRef),
+        _ => Err(RuntimeError::InvalidValueType),
+    }
+}
+
+// name ::= b*:vec(byte)
+fn parse_name(wasm: &Reader) -> Result<String, RuntimeError> {
+    let len = wasm.u32()? as usize;
+    String::from_utf8(wasm.bytes(len)?.to_vec()).map_err(|_| RuntimeError::InvalidName)
 }
 
 fn parse_valuetype(wasm: &Reader) -> Result<ValueType, RuntimeError> {
-    match wasm.byte() {
+    match wasm.byte()? {
         0x7F => Ok(ValueType::NumberType(NumberType::I32)),
         0x7E => Ok(ValueType::NumberType(NumberType::I64)),
         0x7D => Ok(ValueType::NumberType(NumberType::F32)),
@@ -95,50 +815,322 @@ fn parse_valuetype(wasm: &Reader) -> Result<ValueType, RuntimeError> {
         0x7B => Ok(ValueType::VectorType(VectorType::V128)),
         0x70 => Ok(ValueType::ReferenceType(ReferenceType::FuncRef)),
         0x6F => Ok(ValueType::ReferenceType(ReferenceType::ExternRef)),
-        _ => Err(RuntimeError::InvlaidValueType),
+        _ => Err(RuntimeError::InvalidValueType),
     }
 }
 
-pub enum RuntimeError {
-    InvalidModuleLength,
-    InvalidMagicNumber,
-    InvalidVersionNumber,
-    InvalidSectionCode,
-    InvlaidValueType,
-    InvalidExportType,
-    InvalidExportName,
-    InvalidInstruction,
-    ExportNotFound,
-    InvalidArgNumber,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl RuntimeError {
-    fn message(&self) -> &str {
-        match self {
-            Self::InvalidModuleLength => "Invalid module length",
-            Self::InvalidMagicNumber => "Invalid magic number",
-            Self::InvalidVersionNumber => "Invalid version number",
-            Self::InvalidSectionCode => "Invalid section code",
-            Self::InvlaidValueType => "Invalid value type",
-            Self::InvalidExportType => "Invalid export type",
-            Self::InvalidExportName => "Invalid export name",
-            Self::InvalidInstruction => "Invalid instruction",
-            Self::ExportNotFound => "Export not found",
-            Self::InvalidArgNumber => "Invalid argument number",
+    // The size of a section or function body.
+    fn size(len: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut len = len;
+        while len >= 0x80 {
+            bytes.push(len as u8 | 0x80);
+            len >>= 7;
         }
+        bytes.push(len as u8);
+        bytes
     }
-}
 
-impl Error for RuntimeError {}
+    fn reader(bytes: &[u8]) -> Reader {
+        Reader::new(bytes.to_vec())
+    }
+
+    // A module made of `sections`, each given by its id and contents.
+    fn module(sections: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+        for (id, contents) in sections {
+            bytes.push(*id);
+            bytes.extend(size(contents.len()));
+            bytes.extend(*contents);
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_every_section() {
+        const I32: ValueType = ValueType::NumberType(NumberType::I32);
+        const I64: ValueType = ValueType::NumberType(NumberType::I64);
+
+        // (func (param i32 i32) (result i32) (local i64) local.get 0 local.get 1 i32.add)
+        let body = [0x01, 0x01, 0x7E, 0x20, 0x00, 0x20, 0x01, 0x6A, 0x0B];
+        let mut code = vec![0x01];
+        code.extend(size(body.len()));
+        code.extend(body);
 
-impl Display for RuntimeError {
-    fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{}", self.message())
+        let bytes = module(&[
+            (section::CUSTOM, b"\x04meta\x01\x02"),
+            (section::TYPE, &[0x01, 0x60, 0x02, 0x7F, 0x7F, 0x01, 0x7F]),
+            (section::IMPORT, b"\x01\x03env\x01f\x00\x00"),
+            (section::FUNCTION, &[0x01, 0x00]),
+            (section::TABLE, &[0x01, 0x70, 0x00, 0x01]),
+            (section::MEMORY, &[0x01, 0x00, 0x01]),
+            (section::GLOBAL, &[0x01, 0x7F, 0x00, 0x41, 0x00, 0x0B]),
+            (section::EXPORT, b"\x01\x03add\x00\x00"),
+            (section::START, &[0x00]),
+            (section::ELEMENT, &[0x01, 0x00, 0x41, 0x00, 0x0B, 0x01, 0x00]),
+            (section::CODE, &code),
+            (section::DATA, b"\x01\x00\x41\x00\x0B\x02hi"),
+            (section::CUSTOM, b"\x04last"),
+        ]);
+        let module = decode(&bytes).unwrap();
+        assert_eq!(module.types, [(vec![I32, I32], vec![I32])]);
+        assert_eq!(module.funcs.len(), 1);
+        assert_eq!(module.funcs[0].f_type, 0);
+        assert_eq!(module.funcs[0].locals, [I64]);
+        assert_eq!(module.funcs[0].body, [Instr::LocalGet(0), Instr::LocalGet(1), Instr::I32Add]);
+        assert_eq!(module.exports, [Export { name: "add".to_string(), desc: ExportDesc::Func(0) }]);
+        assert_eq!(module.customs, [
+            Custom { name: "meta".to_string(), data: vec![0x01, 0x02] },
+            Custom { name: "last".to_string(), data: vec![] },
+        ]);
     }
-}
 
-impl Debug for RuntimeError {
-    fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{}", self.message())
+    #[test]
+    fn rejects_invalid_sections() {
+        let functype: &[u8] = &[0x01, 0x60, 0x00, 0x00];
+        assert!(matches!(decode(b"\0asm\x01\0\0"), Err(RuntimeError::InvalidModuleLength)));
+        assert!(matches!(decode(b"\0wasm\x01\0\0"), Err(RuntimeError::InvalidMagicNumber)));
+        assert!(matches!(decode(b"\0asm\x02\0\0\0"), Err(RuntimeError::InvalidVersionNumber)));
+        assert!(matches!(
+            decode(&module(&[(section::FUNCTION, &[0x01, 0x00]), (section::TYPE, functype)])),
+            Err(RuntimeError::InvalidSectionOrder),
+        ));
+        assert!(matches!(
+            decode(&module(&[(section::TYPE, functype), (section::TYPE, functype)])),
+            Err(RuntimeError::InvalidSectionOrder),
+        ));
+        assert!(matches!(decode(&module(&[(13, &[])])), Err(RuntimeError::InvalidSectionCode)));
+        assert!(matches!(
+            decode(&module(&[(section::TYPE, &[0x01, 0x60, 0x00, 0x00, 0x00])])),
+            Err(RuntimeError::InvalidSectionSize),
+        ));
+        assert!(matches!(
+            decode(&module(&[(section::TYPE, &[0x01, 0x61, 0x00, 0x00])])),
+            Err(RuntimeError::InvalidFunctionType),
+        ));
+        assert!(matches!(
+            decode(&module(&[(section::TYPE, functype), (section::FUNCTION, &[0x01, 0x00])])),
+            Err(RuntimeError::FunctionCodeMismatch),
+        ));
+        assert!(matches!(
+            decode(&module(&[(section::EXPORT, b"\x01\x01f\x04\x00")])),
+            Err(RuntimeError::InvalidExportType),
+        ));
+    }
+
+    #[test]
+    fn reads_unsigned_integers() {
+        assert_eq!(reader(&[0x00]).u32().unwrap(), 0);
+        assert_eq!(reader(&[0x7F]).u32().unwrap(), 127);
+        assert_eq!(reader(&[0x80, 0x01]).u32().unwrap(), 128);
+        assert_eq!(reader(&[0xE5, 0x8E, 0x26]).u32().unwrap(), 624485);
+        assert_eq!(reader(&[0x80, 0x80, 0x80, 0x80, 0x00]).u32().unwrap(), 0);
+        assert_eq!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]).u32().unwrap(), u32::MAX);
+        assert_eq!(
+            reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]).u64().unwrap(),
+            u64::MAX,
+        );
+
+        assert!(matches!(
+            reader(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]).u32(),
+            Err(RuntimeError::IntegerTooLong),
+        ));
+        assert!(matches!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x1F]).u32(), Err(RuntimeError::IntegerTooLarge)));
+        assert!(matches!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x4F]).u32(), Err(RuntimeError::IntegerTooLarge)));
+        assert!(matches!(
+            reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02]).u64(),
+            Err(RuntimeError::IntegerTooLarge),
+        ));
+        assert!(matches!(
+            reader(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]).u64(),
+            Err(RuntimeError::IntegerTooLong),
+        ));
+    }
+
+    #[test]
+    fn reads_signed_integers() {
+        assert_eq!(reader(&[0x3F]).i32().unwrap(), 63);
+        assert_eq!(reader(&[0x40]).i32().unwrap(), -64);
+        assert_eq!(reader(&[0x7F]).i32().unwrap(), -1);
+        assert_eq!(reader(&[0xC0, 0xBB, 0x78]).i32().unwrap(), -123456);
+        assert_eq!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x07]).i32().unwrap(), i32::MAX);
+        assert_eq!(reader(&[0x80, 0x80, 0x80, 0x80, 0x78]).i32().unwrap(), i32::MIN);
+        assert_eq!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]).i32().unwrap(), -1);
+        assert!(matches!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x4F]).i32(), Err(RuntimeError::IntegerTooLarge)));
+        assert!(matches!(reader(&[0x80, 0x80, 0x80, 0x80, 0x70]).i32(), Err(RuntimeError::IntegerTooLarge)));
+        assert!(matches!(
+            reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F]).i32(),
+            Err(RuntimeError::IntegerTooLong),
+        ));
+
+        // Block type indices go up to 2^32 - 1.
+        assert_eq!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]).i33().unwrap(), u32::MAX as i64);
+        assert_eq!(reader(&[0x80, 0x80, 0x80, 0x80, 0x70]).i33().unwrap(), -1 << 32);
+        assert_eq!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]).i33().unwrap(), -1);
+        assert!(matches!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0x1F]).i33(), Err(RuntimeError::IntegerTooLarge)));
+        assert!(matches!(reader(&[0x80, 0x80, 0x80, 0x80, 0x60]).i33(), Err(RuntimeError::IntegerTooLarge)));
+
+        let max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+        let min = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7F];
+        assert_eq!(reader(&max).i64().unwrap(), i64::MAX);
+        assert_eq!(reader(&min).i64().unwrap(), i64::MIN);
+        assert_eq!(reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F]).i64().unwrap(), -1);
+        assert!(matches!(
+            reader(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]).i64(),
+            Err(RuntimeError::IntegerTooLarge),
+        ));
+        assert!(matches!(
+            reader(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7F]).i64(),
+            Err(RuntimeError::IntegerTooLong),
+        ));
+    }
+
+    #[test]
+    fn decodes_counts_above_127() {
+        let mut types = size(200);
+        for _ in 0..200 {
+            types.extend([0x60, 0x00, 0x00]);
+        }
+        let module = decode(&module(&[(section::TYPE, &types)])).unwrap();
+        assert_eq!(module.types.len(), 200);
+    }
+
+    // (module
+    //   (type (func (param i32 i32) (result i32)))
+    //   (func (type 0) (local i64) local.get 0 local.get 1 i32.add)
+    //   (export "add" (func 0)))
+    // followed by a custom section named "name".
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+        0x01, 0x07, 0x01, 0x60, 0x02, 0x7F, 0x7F, 0x01, 0x7F,
+        0x03, 0x02, 0x01, 0x00,
+        0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00,
+        0x0A, 0x0B, 0x01, 0x09, 0x01, 0x01, 0x7E, 0x20, 0x00, 0x20, 0x01, 0x6A, 0x0B,
+        0x00, 0x07, 0x04, b'n', b'a', b'm', b'e', 0x01, 0x02,
+    ];
+
+    // Offsets at which a prefix of `MODULE` ends between two sections.
+    const SECTION_BOUNDARIES: &[usize] = &[8, 17, 21, 30, 43, 52];
+
+    // (module (func block ... end)) with `depth` nested blocks.
+    fn nested_module(depth: usize) -> Vec<u8> {
+        fn leb128(mut value: usize, bytes: &mut Vec<u8>) {
+            while value >= 0x80 {
+                bytes.push(value as u8 | 0x80);
+                value >>= 7;
+            }
+            bytes.push(value as u8);
+        }
+
+        let mut body = vec![0x00];
+        body.extend([0x02, 0x40].repeat(depth));
+        body.extend(vec![0x0B; depth + 1]);
+        let mut code = vec![0x01];
+        leb128(body.len(), &mut code);
+        code.extend(body);
+
+        let mut bytes = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00,
+            0x0A,
+        ];
+        leb128(code.len(), &mut bytes);
+        bytes.extend(code);
+        bytes
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn truncated_modules_are_rejected() {
+        for len in 0..MODULE.len() {
+            let result = decode(&MODULE[..len]);
+            if len == 21 || len == 30 {
+                // Cut between the function and code sections.
+                assert!(matches!(result, Err(RuntimeError::FunctionCodeMismatch)), "prefix of {} bytes", len);
+            } else if SECTION_BOUNDARIES.contains(&len) {
+                assert!(result.is_ok(), "prefix of {} bytes", len);
+            } else {
+                assert!(result.is_err(), "prefix of {} bytes", len);
+            }
+        }
+
+        let deep = nested_module(20000);
+        for len in (0..deep.len()).step_by(997) {
+            assert!(decode(&deep[..len]).is_err(), "prefix of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn limits_nesting() {
+        assert!(decode(&nested_module(MAX_NESTING_DEPTH)).is_ok());
+        assert!(matches!(decode(&nested_module(MAX_NESTING_DEPTH + 1)), Err(RuntimeError::NestingTooDeep)));
+        assert!(matches!(decode(&nested_module(20000)), Err(RuntimeError::NestingTooDeep)));
+    }
+
+    #[test]
+    fn truncation_reports_offset() {
+        // The code section claims 11 bytes starting at offset 32.
+        match decode(&MODULE[..40]) {
+            Err(RuntimeError::UnexpectedEof { offset }) => assert_eq!(offset, 32),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn checks_data_count() {
+        // (module (memory 1) (func data.drop 0) (data "")) with a data count section of
+        // `count` placed before or after the code section.
+        let module = |count: u8, before_code: bool| {
+            let mut bytes = vec![
+                0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+                0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+                0x03, 0x02, 0x01, 0x00,
+                0x05, 0x03, 0x01, 0x00, 0x01,
+            ];
+            let data_count = [0x0C, 0x01, count];
+            let code = [0x0A, 0x07, 0x01, 0x05, 0x00, 0xFC, 0x09, 0x00, 0x0B];
+            if before_code {
+                bytes.extend(data_count.into_iter().chain(code));
+            } else {
+                bytes.extend(code.into_iter().chain(data_count));
+            }
+            bytes.extend([0x0B, 0x03, 0x01, 0x01, 0x00]);
+            bytes
+        };
+        assert_eq!(decode(&module(1, true)).unwrap().data_count, Some(1));
+        assert!(matches!(decode(&module(1, false)), Err(RuntimeError::InvalidSectionOrder)));
+        assert!(matches!(decode(&module(2, true)), Err(RuntimeError::DataCountMismatch)));
+
+        let mut without_count = module(1, true);
+        without_count.drain(23..26);
+        assert!(matches!(decode(&without_count), Err(RuntimeError::DataCountRequired)));
+    }
+
+    #[test]
+    fn corrupted_modules_do_not_panic() {
+        // xorshift keeps the corpus deterministic without pulling in a fuzzing dependency
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let deep = nested_module(20000);
+        for (module, iterations) in [(MODULE, 10000), (&deep[..], 100)] {
+            for _ in 0..iterations {
+                let mut bytes = module.to_vec();
+                for _ in 0..1 + next() % 3 {
+                    let pos = 8 + (next() as usize) % (bytes.len() - 8);
+                    bytes[pos] = next() as u8;
+                }
+                let len = 8 + (next() as usize) % (bytes.len() - 7);
+                let _ = decode(&bytes[..len]);
+            }
+        }
+    }
+}
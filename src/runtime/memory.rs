@@ -0,0 +1,133 @@
+use std::ops::Range;
+
+use crate::ast::MemType;
+use crate::runtime::Trap;
+
+// Memories are sized in pages of 64 KiB and limited to 4 GiB.
+pub const PAGE_SIZE: usize = 0x10000;
+const MAX_PAGES: u32 = 65536;
+
+// MemInst ::= {type memtype, data vec(byte)}
+// Every access is bounds checked and traps if any of its bytes lies outside of the memory.
+pub struct Memory {
+    data: Vec<u8>,
+    max: Option<u32>,
+}
+
+impl Memory {
+    pub fn new(mem_type: &MemType) -> Self {
+        Self {
+            data: vec![0; mem_type.min as usize * PAGE_SIZE],
+            max: mem_type.max,
+        }
+    }
+
+    // The current size in pages.
+    pub fn size(&self) -> u32 {
+        (self.data.len() / PAGE_SIZE) as u32
+    }
+
+    // Grows the memory by `delta` zeroed pages and returns its previous size, or `None` if
+    // the memory would exceed its maximum or cannot be allocated.
+    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        let size = self.size();
+        let new_size = size.checked_add(delta).filter(|pages| *pages <= self.max.unwrap_or(MAX_PAGES).min(MAX_PAGES))?;
+        let new_len = new_size as usize * PAGE_SIZE;
+        self.data.try_reserve_exact(new_len - self.data.len()).ok()?;
+        self.data.resize(new_len, 0);
+        Some(size)
+    }
+
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn read(&self, addr: usize, buf: &mut [u8]) -> Result<(), Trap> {
+        let range = self.range(addr as u64, buf.len())?;
+        buf.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    pub fn write(&mut self, addr: usize, bytes: &[u8]) -> Result<(), Trap> {
+        let range = self.range(addr as u64, bytes.len())?;
+        self.data[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // Reads `len` bytes as a string, replacing invalid UTF-8 sequences.
+    pub fn read_string(&self, addr: usize, len: usize) -> Result<String, Trap> {
+        let range = self.range(addr as u64, len)?;
+        Ok(String::from_utf8_lossy(&self.data[range]).into_owned())
+    }
+
+    pub fn write_string(&mut self, addr: usize, string: &str) -> Result<(), Trap> {
+        self.write(addr, string.as_bytes())
+    }
+
+    // Copies `len` bytes from `src` to `dst`. The ranges may overlap, both are checked before
+    // any byte is copied.
+    pub(crate) fn copy(&mut self, dst: u64, src: u64, len: usize) -> Result<(), Trap> {
+        let src = self.range(src, len)?;
+        let dst = self.range(dst, len)?;
+        self.data.copy_within(src, dst.start);
+        Ok(())
+    }
+
+    pub(crate) fn fill(&mut self, addr: u64, value: u8, len: usize) -> Result<(), Trap> {
+        let range = self.range(addr, len)?;
+        self.data[range].fill(value);
+        Ok(())
+    }
+
+    // Reads the operand of a load at the effective address `addr`, which may lie beyond the
+    // 32-bit address space once the static offset is added.
+    pub(crate) fn load<const N: usize>(&self, addr: u64) -> Result<[u8; N], Trap> {
+        let range = self.range(addr, N)?;
+        Ok(self.data[range].try_into().expect("range has N bytes"))
+    }
+
+    pub(crate) fn store<const N: usize>(&mut self, addr: u64, bytes: [u8; N]) -> Result<(), Trap> {
+        let range = self.range(addr, N)?;
+        self.data[range].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn range(&self, addr: u64, len: usize) -> Result<Range<usize>, Trap> {
+        match addr.checked_add(len as u64) {
+            Some(end) if end <= self.data.len() as u64 => Ok(addr as usize..end as usize),
+            _ => Err(Trap::OutOfBoundsMemoryAccess),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Limits;
+
+    #[test]
+    fn grows_within_limits() {
+        let mut memory = Memory::new(&Limits { min: 1, max: Some(2) });
+        assert_eq!(memory.size(), 1);
+        assert_eq!(memory.grow(1), Some(1));
+        assert_eq!(memory.grow(1), None);
+        assert_eq!(memory.grow(0), Some(2));
+        assert_eq!(memory.bytes().len(), 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn checks_bounds() {
+        let mut memory = Memory::new(&Limits { min: 1, max: None });
+        memory.write_string(PAGE_SIZE - 5, "hello").unwrap();
+        assert_eq!(memory.read_string(PAGE_SIZE - 5, 5).unwrap(), "hello");
+        assert_eq!(memory.load::<4>(PAGE_SIZE as u64 - 4).unwrap(), *b"ello");
+        assert!(memory.load::<4>(PAGE_SIZE as u64 - 3).is_err());
+        assert!(memory.store(u64::MAX, [0]).is_err());
+        assert!(memory.write(PAGE_SIZE, &[]).is_ok());
+        assert!(memory.read(PAGE_SIZE + 1, &mut []).is_err());
+    }
+}
@@ -1 +1,19 @@
-pub mod loader;
\ No newline at end of file
+pub mod error;
+pub mod host;
+pub mod instance;
+pub mod interpreter;
+pub mod linker;
+pub mod loader;
+pub mod memory;
+pub mod store;
+pub mod table;
+pub mod value;
+
+pub use error::{RuntimeError, Trap};
+pub use host::Caller;
+pub use instance::Instance;
+pub use linker::Linker;
+pub use memory::Memory;
+pub use store::Store;
+pub use table::Table;
+pub use value::Value;
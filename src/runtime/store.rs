@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{FuncType, GlobalType};
+use crate::runtime::host::HostFunc;
+use crate::runtime::interpreter::Code;
+use crate::runtime::{Memory, Table, Value};
+
+// The store holds every function, table, memory, global, element and data segment instance
+// allocated by the instances created in it.
+// Cloning a `Store` is cheap and yields another handle to the same store.
+#[derive(Clone, Default)]
+pub struct Store {
+    pub(crate) inner: Rc<RefCell<StoreInner>>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// Store ::= {funcs funcinst*, tables tableinst*, mems meminst*, globals globalinst*, elems eleminst*,
+//            datas datainst*, modules moduleinst*}
+// ElemInst ::= {type reftype, elem ref*}, only the references are kept and dropping a segment
+// empties them. DataInst ::= {data byte*} is kept the same way.
+#[derive(Default)]
+pub(crate) struct StoreInner {
+    pub funcs: Vec<FuncInst>,
+    pub tables: Vec<Table>,
+    pub mems: Vec<Memory>,
+    pub globals: Vec<GlobalInst>,
+    pub elems: Vec<Vec<Value>>,
+    pub datas: Vec<Vec<u8>>,
+    pub modules: Vec<ModuleInst>,
+}
+
+// FuncInst ::= {type functype, module moduleaddr, code func} | {type functype, hostcode hostfunc}
+pub(crate) enum FuncInst {
+    Wasm { func_type: FuncType, module: usize, code: Rc<Code> },
+    Host { func_type: FuncType, code: HostFunc },
+}
+
+impl FuncInst {
+    pub fn func_type(&self) -> &FuncType {
+        match self {
+            Self::Wasm { func_type, .. } | Self::Host { func_type, .. } => func_type,
+        }
+    }
+}
+
+// GlobalInst ::= {type globaltype, value val}
+pub(crate) struct GlobalInst {
+    pub global_type: GlobalType,
+    pub value: Value,
+}
+
+// ModuleInst ::= {types functype*, funcaddrs funcaddr*, tableaddrs tableaddr*, memaddrs memaddr*,
+//                 globaladdrs globaladdr*, elemaddrs elemaddr*, dataaddrs dataaddr*, exports exportinst*}
+pub(crate) struct ModuleInst {
+    pub types: Vec<FuncType>,
+    pub func_addrs: Vec<usize>,
+    pub table_addrs: Vec<usize>,
+    pub mem_addrs: Vec<usize>,
+    pub global_addrs: Vec<usize>,
+    pub elem_addrs: Vec<usize>,
+    pub data_addrs: Vec<usize>,
+    pub exports: Vec<ExportInst>,
+}
+
+// ExternVal ::= func funcaddr | table tableaddr | mem memaddr | global globaladdr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternVal {
+    Func(usize),
+    Table(usize),
+    Mem(usize),
+    Global(usize),
+}
+
+// ExportInst ::= {name name, value externval}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportInst {
+    pub name: String,
+    pub value: ExternVal,
+}
@@ -0,0 +1,123 @@
+use std::ops::Range;
+
+use crate::ast::{ReferenceType, TableType, ValueType};
+use crate::runtime::{RuntimeError, Trap, Value};
+
+// Tables may not grow beyond this many elements, an implementation limit well below the 2^32
+// the spec allows.
+const MAX_ELEMS: u32 = 10_000_000;
+
+// TableInst ::= {type tabletype, elem ref*}
+// Every access is bounds checked and traps if any of its elements lies outside of the table.
+pub struct Table {
+    elements: Vec<Value>,
+    elem_type: ReferenceType,
+    max: Option<u32>,
+}
+
+impl Table {
+    // Creates a table of `table_type` whose elements are null.
+    pub fn new(table_type: &TableType) -> Self {
+        let null = Value::default(ValueType::ReferenceType(table_type.elem_type));
+        Self {
+            elements: vec![null; table_type.limits.min as usize],
+            elem_type: table_type.elem_type,
+            max: table_type.limits.max,
+        }
+    }
+
+    // The current size in elements.
+    pub fn size(&self) -> u32 {
+        self.elements.len() as u32
+    }
+
+    // Grows the table by `delta` elements set to `init` and returns its previous size, or
+    // `None` if the table would exceed its maximum, cannot be allocated or `init` is not a
+    // reference of its element type.
+    pub fn grow(&mut self, delta: u32, init: Value) -> Option<u32> {
+        if !self.holds(init) {
+            return None;
+        }
+        let size = self.size();
+        let new_size = size.checked_add(delta).filter(|len| *len <= self.max.unwrap_or(MAX_ELEMS).min(MAX_ELEMS))?;
+        self.elements.try_reserve_exact(delta as usize).ok()?;
+        self.elements.resize(new_size as usize, init);
+        Some(size)
+    }
+
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+
+    pub fn elem_type(&self) -> ReferenceType {
+        self.elem_type
+    }
+
+    pub fn get(&self, idx: u32) -> Result<Value, Trap> {
+        let range = self.range(idx, 1)?;
+        Ok(self.elements[range.start])
+    }
+
+    pub fn set(&mut self, idx: u32, value: Value) -> Result<(), Trap> {
+        if !self.holds(value) {
+            return Err(RuntimeError::TypeMismatch.into());
+        }
+        let range = self.range(idx, 1)?;
+        self.elements[range.start] = value;
+        Ok(())
+    }
+
+    // The `len` elements starting at `idx`.
+    pub(crate) fn elements(&self, idx: u32, len: u32) -> Result<&[Value], Trap> {
+        let range = self.range(idx, len)?;
+        Ok(&self.elements[range])
+    }
+
+    // Sets the elements starting at `idx` to `values`, which the validator guarantees to be
+    // of the element type.
+    pub(crate) fn init(&mut self, idx: u32, values: &[Value]) -> Result<(), Trap> {
+        let range = self.range(idx, values.len() as u32)?;
+        self.elements[range].copy_from_slice(values);
+        Ok(())
+    }
+
+    pub(crate) fn fill(&mut self, idx: u32, value: Value, len: u32) -> Result<(), Trap> {
+        let range = self.range(idx, len)?;
+        self.elements[range].fill(value);
+        Ok(())
+    }
+
+    fn holds(&self, value: Value) -> bool {
+        value.value_type() == ValueType::ReferenceType(self.elem_type)
+    }
+
+    fn range(&self, idx: u32, len: u32) -> Result<Range<usize>, Trap> {
+        match idx.checked_add(len) {
+            Some(end) if end <= self.size() => Ok(idx as usize..end as usize),
+            _ => Err(Trap::OutOfBoundsTableAccess),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Limits;
+
+    #[test]
+    fn grows_and_checks_bounds() {
+        let mut table = Table::new(&TableType { limits: Limits { min: 1, max: Some(3) }, elem_type: ReferenceType::FuncRef });
+        assert_eq!(table.get(0).unwrap(), Value::FuncRef(None));
+        assert_eq!(table.grow(1, Value::FuncRef(Some(7))), Some(1));
+        assert_eq!(table.grow(1, Value::ExternRef(None)), None);
+        assert_eq!(table.grow(2, Value::FuncRef(None)), None);
+        assert_eq!(table.get(1).unwrap(), Value::FuncRef(Some(7)));
+        assert!(table.get(2).is_err());
+        assert!(table.set(0, Value::I32(0)).is_err());
+        table.fill(0, Value::FuncRef(Some(1)), 2).unwrap();
+        assert_eq!(table.elements(0, 2).unwrap(), [Value::FuncRef(Some(1)); 2]);
+        assert!(table.elements(u32::MAX, 2).is_err());
+        assert!(table.init(2, &[]).is_ok());
+        assert!(table.init(1, &[Value::FuncRef(None); 2]).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::ast::{NumberType, ReferenceType, ValueType, VectorType};
+
+// Val ::= Num | Vec | Ref
+// References hold the store address of the referenced entity, or `None` for null.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    V128(u128),
+    FuncRef(Option<usize>),
+    ExternRef(Option<usize>),
+}
+
+impl Value {
+    // The zero value locals of type `value_type` are initialized with.
+    pub fn default(value_type: ValueType) -> Self {
+        match value_type {
+            ValueType::NumberType(NumberType::I32) => Self::I32(0),
+            ValueType::NumberType(NumberType::I64) => Self::I64(0),
+            ValueType::NumberType(NumberType::F32) => Self::F32(0.0),
+            ValueType::NumberType(NumberType::F64) => Self::F64(0.0),
+            ValueType::VectorType(VectorType::V128) => Self::V128(0),
+            ValueType::ReferenceType(ReferenceType::FuncRef) => Self::FuncRef(None),
+            ValueType::ReferenceType(ReferenceType::ExternRef) => Self::ExternRef(None),
+        }
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Self::I32(_) => ValueType::NumberType(NumberType::I32),
+            Self::I64(_) => ValueType::NumberType(NumberType::I64),
+            Self::F32(_) => ValueType::NumberType(NumberType::F32),
+            Self::F64(_) => ValueType::NumberType(NumberType::F64),
+            Self::V128(_) => ValueType::VectorType(VectorType::V128),
+            Self::FuncRef(_) => ValueType::ReferenceType(ReferenceType::FuncRef),
+            Self::ExternRef(_) => ValueType::ReferenceType(ReferenceType::ExternRef),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::I32(v) => write!(f, "{}", v),
+            Self::I64(v) => write!(f, "{}", v),
+            Self::F32(v) => write!(f, "{}", v),
+            Self::F64(v) => write!(f, "{}", v),
+            Self::V128(v) => write!(f, "{:#034x}", v),
+            Self::FuncRef(Some(addr)) | Self::ExternRef(Some(addr)) => write!(f, "ref {}", addr),
+            Self::FuncRef(None) | Self::ExternRef(None) => write!(f, "ref.null"),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::token::Span;
+
+// Identifies a file added to a `SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(u32);
+
+// A 1-based position in a file. Columns count characters, so a tab is one column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+struct SourceFile {
+    name: String,
+    source: String,
+    // Byte offset of the first character of every line
+    line_starts: Vec<usize>,
+}
+
+// Owns the sources that diagnostics refer to and converts the byte offsets of `Span`s into
+// lines and columns. The start of every line is indexed once when a file is added, so a
+// lookup is a binary search.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        let source = source.into();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(pos, _)| pos + 1))
+            .collect();
+        self.files.push(SourceFile { name: name.into(), source, line_starts });
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn name(&self, file: FileId) -> &str {
+        &self.files[file.0 as usize].name
+    }
+
+    pub fn source(&self, file: FileId) -> &str {
+        &self.files[file.0 as usize].source
+    }
+
+    pub fn location(&self, file: FileId, offset: u32) -> Location {
+        let file = &self.files[file.0 as usize];
+        let offset = (offset as usize).min(file.source.len());
+        let line = file.line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = file.source[file.line_starts[line]..offset].chars().count() + 1;
+        Location { line: line + 1, column }
+    }
+
+    // Renders a diagnostic for `span`, showing the line it starts on with the span
+    // underlined:
+    //
+    // error: malformed number
+    //  --> test.wat:3:15
+    //   |
+    // 3 |     i32.const 12x
+    //   |               ^^^
+    pub fn render(&self, file: FileId, span: Span, message: &str) -> String {
+        let Location { line, column } = self.location(file, span.start);
+        let name = self.name(file);
+        let source = self.source(file);
+        let line_start = self.files[file.0 as usize].line_starts[line - 1];
+        let line_end = source[line_start..].find('\n').map_or(source.len(), |pos| line_start + pos);
+        let text = source[line_start..line_end].trim_end_matches('\r');
+
+        // The underline reuses the tabs of the line so that it stays aligned
+        let start = (span.start as usize).min(source.len());
+        let padding: String = source[line_start..start].chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = (span.end as usize).clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            message, gutter, name, line, column, gutter, line, text, gutter, padding, "^".repeat(width),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_offsets() {
+        let mut map = SourceMap::new();
+        let a = map.add("a.wat", "(module\n\t(func))\n");
+        let b = map.add("b.wat", "λ\r\nx");
+        assert_eq!(map.location(a, 0), Location { line: 1, column: 1 });
+        assert_eq!(map.location(a, 7), Location { line: 1, column: 8 });
+        assert_eq!(map.location(a, 8), Location { line: 2, column: 1 });
+        assert_eq!(map.location(a, 10), Location { line: 2, column: 3 });
+        assert_eq!(map.location(a, 17), Location { line: 3, column: 1 });
+        assert_eq!(map.location(b, 2), Location { line: 1, column: 2 });
+        assert_eq!(map.location(b, 4), Location { line: 2, column: 1 });
+        assert_eq!(map.name(b), "b.wat");
+    }
+
+    #[test]
+    fn renders_spans() {
+        let mut map = SourceMap::new();
+        let file = map.add("test.wat", "(module\n\t(func i32.const 12x))");
+        let span = Span { start: 25, end: 28 };
+        assert_eq!(
+            map.render(file, span, "malformed number"),
+            "error: malformed number\n --> test.wat:2:18\n  |\n2 | \t(func i32.const 12x))\n  | \t                ^^^",
+        );
+    }
+}
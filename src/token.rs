@@ -1,4 +1,9 @@
-#[derive(Debug)]
+use std::ops::{Index, Range};
+use std::str::Chars;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Token<'a> {
     pub kind: TokenKind<'a>,
     pub span: Span,
@@ -13,13 +18,36 @@ impl<'a> Token<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Span {
     pub start: u32, // inclusive
     pub end: u32, // exclusive
 }
 
-#[derive(Debug)]
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start as usize..span.end as usize
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self {
+            start: range.start as u32,
+            end: range.end as u32,
+        }
+    }
+}
+
+impl Index<Span> for str {
+    type Output = str;
+
+    fn index(&self, index: Span) -> &Self::Output {
+        &self[Range::<usize>::from(index)]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenKind<'a> {
     // Single char tokens
     LeftParen,
@@ -27,18 +55,29 @@ pub enum TokenKind<'a> {
     
     // Literals
     Identifier(&'a str), // starts with $
-    String(&'a str),
+    String(StringLiteral<'a>),
     Integer(IntegerKind<'a>),
     Float(FloatKind<'a>),
 
     Keyword(&'a str),
     Reserved(&'a str),
 
+    // Trivia
     Whitespace,
+    LineComment(&'a str),
+    BlockComment(&'a str),
+
     Eof,
 }
 
-#[derive(Debug)]
+impl<'a> TokenKind<'a> {
+    // Trivia separates tokens but carries no meaning for the parser.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::LineComment(_) | TokenKind::BlockComment(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum IntegerKind<'a> {
     Decimal {
         src: &'a str,
@@ -50,7 +89,7 @@ pub enum IntegerKind<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FloatKind<'a> {
     Inf {
         src: &'a str,
@@ -61,11 +100,259 @@ pub enum FloatKind<'a> {
         negative: bool,
         value: Option<u64>,
     },
+    // The digits are kept as written, including `_` separators. The exponent is decimal and
+    // includes its sign, it scales by powers of 2 instead of 10 for hex floats.
     Val {
         src: &'a str,
         negative: bool,
+        hex: bool,
         integral: &'a str,
         fractional: &'a str,
         exponent: &'a str,
     }
-}
\ No newline at end of file
+}
+
+impl<'a> IntegerKind<'a> {
+    // Returns the sign and magnitude of the literal, or `None` if the magnitude does not fit
+    // in 64 bits.
+    pub fn magnitude(self) -> Option<(bool, u64)> {
+        let (src, negative, radix) = match self {
+            IntegerKind::Decimal { src, negative } => (src, negative, 10),
+            IntegerKind::Hex { src, negative } => (src, negative, 16),
+        };
+        let value = u64::from_str_radix(&src.replace('_', ""), radix).ok()?;
+        Some((negative, value))
+    }
+
+    // Returns the two's complement bit pattern of the literal as a `bits` wide integer.
+    // Integers may be written signed or unsigned, e.g. both -1 and 0xFFFFFFFF are valid i32s.
+    pub fn to_bits(self, bits: u32) -> Option<u64> {
+        let (negative, value) = self.magnitude()?;
+        let max = u64::MAX >> (64 - bits);
+        let in_range = if negative { value <= max / 2 + 1 } else { value <= max };
+        if !in_range {
+            return None;
+        }
+        Some(if negative { value.wrapping_neg() & max } else { value })
+    }
+
+    pub fn to_u32(self) -> Option<u32> {
+        match self.magnitude()? {
+            (false, value) => u32::try_from(value).ok(),
+            (true, _) => None,
+        }
+    }
+
+    pub fn to_i32(self) -> Option<i32> {
+        self.to_bits(32).map(|bits| bits as u32 as i32)
+    }
+
+    pub fn to_i64(self) -> Option<i64> {
+        self.to_bits(64).map(|bits| bits as i64)
+    }
+
+    // An integer is also a valid float literal without fraction or exponent.
+    pub fn to_f32_bits(self) -> Option<u32> {
+        self.to_float().to_f32_bits()
+    }
+
+    pub fn to_f64_bits(self) -> Option<u64> {
+        self.to_float().to_f64_bits()
+    }
+
+    fn to_float(self) -> FloatKind<'a> {
+        let (src, negative, hex) = match self {
+            IntegerKind::Decimal { src, negative } => (src, negative, false),
+            IntegerKind::Hex { src, negative } => (src, negative, true),
+        };
+        FloatKind::Val { src, negative, hex, integral: src, fractional: "", exponent: "" }
+    }
+}
+
+impl<'a> FloatKind<'a> {
+    // Returns the bit pattern of the nearest f32, or `None` if the literal rounds to infinity
+    // or its NaN payload does not fit.
+    pub fn to_f32_bits(self) -> Option<u32> {
+        self.to_bits(&F32_FORMAT).map(|bits| bits as u32)
+    }
+
+    pub fn to_f64_bits(self) -> Option<u64> {
+        self.to_bits(&F64_FORMAT)
+    }
+
+    fn to_bits(self, format: &FloatFormat) -> Option<u64> {
+        let exponent_mask = ((1 << format.exponent_bits) - 1) << format.mantissa_bits;
+        let (negative, bits) = match self {
+            FloatKind::Inf { negative, .. } => (negative, exponent_mask),
+            FloatKind::Nan { negative, value: None, .. } => (negative, exponent_mask | 1 << (format.mantissa_bits - 1)),
+            FloatKind::Nan { negative, value: Some(payload), .. } => {
+                if payload == 0 || payload >> format.mantissa_bits != 0 {
+                    return None;
+                }
+                (negative, exponent_mask | payload)
+            },
+            FloatKind::Val { negative, hex, integral, fractional, exponent, .. } => {
+                let bits = if hex {
+                    hex_float_bits(integral, fractional, exponent, format)?
+                } else {
+                    let src = format!("{}.{}e{}", integral, fractional, if exponent.is_empty() { "0" } else { exponent });
+                    (format.parse)(&src.replace('_', ""))?
+                };
+                if bits & exponent_mask == exponent_mask {
+                    return None;
+                }
+                (negative, bits)
+            },
+        };
+        let sign = 1 << (format.mantissa_bits + format.exponent_bits);
+        Some(if negative { bits | sign } else { bits })
+    }
+}
+
+// An IEEE 754 binary format and the correctly rounded parser for its decimal literals.
+struct FloatFormat {
+    mantissa_bits: u32,
+    exponent_bits: u32,
+    parse: fn(&str) -> Option<u64>,
+}
+
+const F32_FORMAT: FloatFormat = FloatFormat {
+    mantissa_bits: 23,
+    exponent_bits: 8,
+    parse: |src| src.parse::<f32>().ok().map(|value| value.to_bits() as u64),
+};
+
+const F64_FORMAT: FloatFormat = FloatFormat {
+    mantissa_bits: 52,
+    exponent_bits: 11,
+    parse: |src| src.parse::<f64>().ok().map(f64::to_bits),
+};
+
+// Rounds a hex float to the nearest value of `format`, ties to even. The leading 61 to 64
+// significant bits are kept exactly and the remaining digits only matter as a sticky bit.
+fn hex_float_bits(integral: &str, fractional: &str, exponent: &str, format: &FloatFormat) -> Option<u64> {
+    // Exponents beyond any format saturate, so that the result still rounds to 0 or infinity
+    let mut exp = match exponent.replace('_', "").parse::<i64>() {
+        _ if exponent.is_empty() => 0,
+        Ok(exp) => exp.clamp(-(1 << 40), 1 << 40),
+        Err(_) if exponent.starts_with('-') => -(1 << 40),
+        Err(_) => 1 << 40,
+    };
+    let mut mantissa = 0u64;
+    let mut sticky = false;
+    let digits = integral.chars().map(|c| (c, false)).chain(fractional.chars().map(|c| (c, true)));
+    for (c, is_fraction) in digits.filter(|(c, _)| *c != '_') {
+        let digit = c.to_digit(16)? as u64;
+        if mantissa >> 60 == 0 {
+            mantissa = mantissa << 4 | digit;
+            if is_fraction {
+                exp -= 4;
+            }
+        } else {
+            sticky |= digit != 0;
+            if !is_fraction {
+                exp += 4;
+            }
+        }
+    }
+    if mantissa == 0 {
+        return Some(0);
+    }
+
+    // The value is mantissa * 2^exp. Subnormals keep fewer bits, as their exponent is fixed.
+    let bias = (1 << (format.exponent_bits - 1)) - 1;
+    let mantissa_bits = format.mantissa_bits as i64;
+    let lead = 63 - mantissa.leading_zeros() as i64;
+    let min_exp = 1 - bias;
+    let shift = (lead + exp).max(min_exp) - mantissa_bits - exp;
+    let wide = mantissa as u128;
+    let (mut kept, round, rest) = if shift <= 0 {
+        (wide << -shift, false, false)
+    } else if shift > 127 {
+        (0, false, true)
+    } else {
+        let half = 1u128 << (shift - 1);
+        (wide >> shift, wide & half != 0, wide & (half - 1) != 0)
+    };
+    if round && (rest || sticky || kept & 1 == 1) {
+        kept += 1;
+    }
+
+    // A normal significand includes the implicit leading bit, so it adds one to the exponent
+    // field. Rounding up to the next power of two carries into the exponent the same way.
+    let bits = if lead + exp >= min_exp {
+        (((lead + exp + bias - 1) as u128) << mantissa_bits) + kept
+    } else {
+        kept
+    };
+    let infinity = ((1u128 << format.exponent_bits) - 1) << mantissa_bits;
+    Some(bits.min(infinity) as u64)
+}
+
+// A string literal whose escapes have been checked by the lexer. `src` includes the quotes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StringLiteral<'a> {
+    pub src: &'a str,
+}
+
+impl<'a> StringLiteral<'a> {
+    // Returns the bytes the literal stands for.
+    pub fn bytes(&self) -> Vec<u8> {
+        decode_string(&self.src[1..self.src.len() - 1], 1).expect("string literal was checked by the lexer")
+    }
+}
+
+// Decodes the contents of a string literal that start at byte `offset` of the source.
+// string ::= '"' (stringchar | '\' hexdigit hexdigit)* '"'
+// stringchar ::= c (c >= U+20, c != U+7F, c != '"', c != '\') | '\t' | '\n' | '\r' | '\"' | '\'' | '\\'
+//              | '\u{' hexnum '}'
+pub(crate) fn decode_string(contents: &str, offset: usize) -> Result<Vec<u8>, Error> {
+    let span = |start: usize, end: usize| Span { start: (offset + start) as u32, end: (offset + end) as u32 };
+    let mut bytes = vec![];
+    let mut chars = contents.chars();
+    loop {
+        let start = contents.len() - chars.as_str().len();
+        match chars.next() {
+            Some('\\') => {
+                if decode_escape(&mut chars, &mut bytes).is_none() {
+                    let end = contents.len() - chars.as_str().len();
+                    return Err(Error::InvalidEscape { span: span(start, end) });
+                }
+            },
+            Some(c @ ('"' | '\u{7F}' | '\0'..='\u{1F}')) => {
+                return Err(Error::UnexpectedCharacter { span: span(start, start + c.len_utf8()) });
+            },
+            Some(c) => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            None => return Ok(bytes),
+        }
+    }
+}
+
+// Decodes the escape sequence after a backslash.
+fn decode_escape(chars: &mut Chars, bytes: &mut Vec<u8>) -> Option<()> {
+    match chars.next()? {
+        't' => bytes.push(b'\t'),
+        'n' => bytes.push(b'\n'),
+        'r' => bytes.push(b'\r'),
+        '"' => bytes.push(b'"'),
+        '\'' => bytes.push(b'\''),
+        '\\' => bytes.push(b'\\'),
+        'u' => {
+            let rest = chars.as_str().strip_prefix('{')?;
+            let (hex, rest) = rest.split_once('}')?;
+            *chars = rest.chars();
+            let digits = hex.replace('_', "");
+            if hex.starts_with('_') || hex.ends_with('_') || hex.contains("__") || digits.is_empty() {
+                return None;
+            }
+            let code = char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?;
+            bytes.extend(code.encode_utf8(&mut [0; 4]).as_bytes());
+        },
+        high => {
+            let high = high.to_digit(16)?;
+            let low = chars.next()?.to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        },
+    }
+    Some(())
+}
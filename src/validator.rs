@@ -0,0 +1,1022 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::{
+    BlockType,
+    DataMode,
+    ElemMode,
+    ExportDesc,
+    FuncType,
+    GlobalType,
+    ImportDesc,
+    Instr,
+    Limits,
+    MemArg,
+    MemType,
+    Module,
+    NumberType,
+    ReferenceType,
+    TableType,
+    ValueType,
+    VectorType,
+};
+
+const I32: ValueType = ValueType::NumberType(NumberType::I32);
+const I64: ValueType = ValueType::NumberType(NumberType::I64);
+const F32: ValueType = ValueType::NumberType(NumberType::F32);
+const F64: ValueType = ValueType::NumberType(NumberType::F64);
+const V128: ValueType = ValueType::VectorType(VectorType::V128);
+
+// Memories are limited to 4 GiB, i.e. 65536 pages of 64 KiB.
+const MAX_PAGES: u32 = 65536;
+
+// Reports why a module is invalid. Failures inside a function body carry the index of the
+// function in the function index space and the position of the offending instruction, which
+// counts the instructions of the body in order, including those of nested blocks.
+#[derive(Debug)]
+pub struct ValidationError {
+    pub message: String,
+    pub func: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl ValidationError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            func: None,
+            offset: None,
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(func) = self.func {
+            write!(f, " in function {}", func)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at instruction {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationError {}
+
+// Checks that `module` is valid according to the validation rules of the specification.
+pub fn validate(module: &Module) -> Result<(), ValidationError> {
+    let context = Context::new(module);
+
+    for import in &module.imports {
+        match &import.desc {
+            ImportDesc::Func(type_idx) => {
+                context.func_type(*type_idx)?;
+            },
+            ImportDesc::Table(table_type) => check_table_type(table_type)?,
+            ImportDesc::Mem(mem_type) => check_mem_type(mem_type)?,
+            ImportDesc::Global(_) => {},
+        }
+    }
+    for table in &module.tables {
+        check_table_type(&table.table_type)?;
+    }
+    for mem in &module.mems {
+        check_mem_type(&mem.mem_type)?;
+    }
+    if context.mems.len() > 1 {
+        return Err(ValidationError::new("multiple memories"));
+    }
+
+    // Global initializers may only refer to imported globals
+    let num_imported_globals = context.globals.len() - module.globals.len();
+    for global in &module.globals {
+        context.const_expr(&global.init, global.global_type.value_type, num_imported_globals)?;
+    }
+
+    for elem in &module.elem {
+        for init in &elem.init {
+            context.const_expr(init, ValueType::ReferenceType(elem.elem_type), context.globals.len())?;
+        }
+        if let ElemMode::Active { table, offset } = &elem.mode {
+            let table_type = context.table(*table)?;
+            if table_type.elem_type != elem.elem_type {
+                return Err(ValidationError::new("type mismatch"));
+            }
+            context.const_expr(offset, I32, context.globals.len())?;
+        }
+    }
+
+    for data in &module.data {
+        if let DataMode::Active { memory, offset } = &data.mode {
+            context.mem(*memory)?;
+            context.const_expr(offset, I32, context.globals.len())?;
+        }
+    }
+
+    if let Some(start) = &module.start {
+        let type_idx = context.func(start.func)?;
+        let (params, results) = context.func_type(type_idx)?;
+        if !params.is_empty() || !results.is_empty() {
+            return Err(ValidationError::new("start function"));
+        }
+    }
+
+    let mut names = HashSet::new();
+    for export in &module.exports {
+        if !names.insert(export.name.as_str()) {
+            return Err(ValidationError::new("duplicate export name"));
+        }
+        match export.desc {
+            ExportDesc::Func(idx) => context.func(idx).map(|_| ())?,
+            ExportDesc::Table(idx) => context.table(idx).map(|_| ())?,
+            ExportDesc::Mem(idx) => context.mem(idx).map(|_| ())?,
+            ExportDesc::Global(idx) => context.global(idx).map(|_| ())?,
+        }
+    }
+
+    let num_imported_funcs = context.funcs.len() - module.funcs.len();
+    for (idx, func) in module.funcs.iter().enumerate() {
+        let idx = num_imported_funcs + idx;
+        let (params, results) = context.func_type(func.f_type).map_err(|e| ValidationError { func: Some(idx), ..e })?;
+        let locals = params.iter().chain(&func.locals).copied().collect();
+        let mut validator = FuncValidator::new(&context, locals, results.clone());
+        validator.func(&func.body).map_err(|e| ValidationError { func: Some(idx), ..e })?;
+    }
+    Ok(())
+}
+
+// C ::= {types, funcs, tables, mems, globals, elems, datas, refs}
+// The locals, labels and return type of a function live in `FuncValidator`.
+struct Context<'m> {
+    types: &'m [FuncType],
+    funcs: Vec<usize>,
+    tables: Vec<TableType>,
+    mems: Vec<MemType>,
+    globals: Vec<GlobalType>,
+    elems: Vec<ReferenceType>,
+    num_datas: usize,
+    // Functions that may be referenced by `ref.func` in function bodies
+    refs: HashSet<usize>,
+}
+
+impl<'m> Context<'m> {
+    fn new(module: &'m Module) -> Self {
+        let mut context = Self {
+            types: &module.types,
+            funcs: vec![],
+            tables: vec![],
+            mems: vec![],
+            globals: vec![],
+            elems: module.elem.iter().map(|elem| elem.elem_type).collect(),
+            num_datas: module.data.len(),
+            refs: HashSet::new(),
+        };
+
+        // Imports come first in every index space
+        for import in &module.imports {
+            match &import.desc {
+                ImportDesc::Func(type_idx) => context.funcs.push(*type_idx),
+                ImportDesc::Table(table_type) => context.tables.push(*table_type),
+                ImportDesc::Mem(mem_type) => context.mems.push(*mem_type),
+                ImportDesc::Global(global_type) => context.globals.push(*global_type),
+            }
+        }
+        context.funcs.extend(module.funcs.iter().map(|func| func.f_type));
+        context.tables.extend(module.tables.iter().map(|table| table.table_type));
+        context.mems.extend(module.mems.iter().map(|mem| mem.mem_type));
+        context.globals.extend(module.globals.iter().map(|global| global.global_type));
+
+        // Every function index occurring outside of function bodies is declared
+        let exprs = module.globals.iter().map(|global| &global.init)
+            .chain(module.elem.iter().flat_map(|elem| &elem.init));
+        for expr in exprs {
+            for instr in expr {
+                if let Instr::RefFunc(idx) = instr {
+                    context.refs.insert(*idx);
+                }
+            }
+        }
+        for export in &module.exports {
+            if let ExportDesc::Func(idx) = export.desc {
+                context.refs.insert(idx);
+            }
+        }
+        context
+    }
+
+    fn func_type(&self, idx: usize) -> Result<&'m FuncType, ValidationError> {
+        self.types.get(idx).ok_or_else(|| ValidationError::new(format!("unknown type {}", idx)))
+    }
+
+    // Returns the type index of function `idx`.
+    fn func(&self, idx: usize) -> Result<usize, ValidationError> {
+        self.funcs.get(idx).copied().ok_or_else(|| ValidationError::new(format!("unknown function {}", idx)))
+    }
+
+    fn table(&self, idx: usize) -> Result<TableType, ValidationError> {
+        self.tables.get(idx).copied().ok_or_else(|| ValidationError::new(format!("unknown table {}", idx)))
+    }
+
+    fn mem(&self, idx: usize) -> Result<MemType, ValidationError> {
+        self.mems.get(idx).copied().ok_or_else(|| ValidationError::new(format!("unknown memory {}", idx)))
+    }
+
+    fn global(&self, idx: usize) -> Result<GlobalType, ValidationError> {
+        self.globals.get(idx).copied().ok_or_else(|| ValidationError::new(format!("unknown global {}", idx)))
+    }
+
+    fn elem(&self, idx: usize) -> Result<ReferenceType, ValidationError> {
+        self.elems.get(idx).copied().ok_or_else(|| ValidationError::new(format!("unknown elem segment {}", idx)))
+    }
+
+    fn data(&self, idx: usize) -> Result<(), ValidationError> {
+        if idx < self.num_datas {
+            Ok(())
+        } else {
+            Err(ValidationError::new(format!("unknown data segment {}", idx)))
+        }
+    }
+
+    // Constant expressions consist of constants, references, reads of the first `num_globals`
+    // globals, which must be immutable, and integer additions, subtractions and
+    // multiplications (the extended constant expressions proposal).
+    fn const_expr(&self, expr: &[Instr], expected: ValueType, num_globals: usize) -> Result<(), ValidationError> {
+        for instr in expr {
+            match instr {
+                Instr::I32Const(_) | Instr::I64Const(_) | Instr::F32Const(_) | Instr::F64Const(_)
+                | Instr::V128Const(_) | Instr::RefNull(_) | Instr::RefFunc(_) => {},
+                Instr::I32Add | Instr::I32Sub | Instr::I32Mul | Instr::I64Add | Instr::I64Sub | Instr::I64Mul => {},
+                Instr::GlobalGet(idx) if *idx < num_globals => {
+                    if self.global(*idx)?.mutable {
+                        return Err(ValidationError::new("constant expression required"));
+                    }
+                },
+                Instr::GlobalGet(idx) => return Err(ValidationError::new(format!("unknown global {}", idx))),
+                _ => return Err(ValidationError::new("constant expression required")),
+            }
+        }
+        let mut validator = FuncValidator::new(self, vec![], vec![expected]);
+        validator.func(expr)
+    }
+}
+
+// Limits must be ordered and lie within `max`.
+fn check_limits(limits: &Limits, max: u32, message: &str) -> Result<(), ValidationError> {
+    if limits.min > max || limits.max.is_some_and(|m| m > max) {
+        return Err(ValidationError::new(message));
+    }
+    if limits.max.is_some_and(|m| m < limits.min) {
+        return Err(ValidationError::new("size minimum must not be greater than maximum"));
+    }
+    Ok(())
+}
+
+fn check_table_type(table_type: &TableType) -> Result<(), ValidationError> {
+    check_limits(&table_type.limits, u32::MAX, "table size must be at most 2^32-1")
+}
+
+fn check_mem_type(mem_type: &MemType) -> Result<(), ValidationError> {
+    check_limits(mem_type, MAX_PAGES, "memory size must be at most 65536 pages (4GiB)")
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CtrlKind {
+    Block,
+    Loop,
+    If,
+}
+
+// ctrl_frame ::= {opcode, start_types, end_types, height, unreachable}
+struct CtrlFrame {
+    kind: CtrlKind,
+    start_types: Vec<ValueType>,
+    end_types: Vec<ValueType>,
+    height: usize,
+    unreachable: bool,
+}
+
+impl CtrlFrame {
+    // Branches to a loop jump back to its start, all other branches to the end.
+    fn label_types(&self) -> &[ValueType] {
+        if self.kind == CtrlKind::Loop {
+            &self.start_types
+        } else {
+            &self.end_types
+        }
+    }
+}
+
+// Validates a function body with the operand and control stacks of the validation algorithm
+// in the appendix of the specification. `None` on the operand stack is an unknown type, which
+// appears in unreachable code.
+struct FuncValidator<'c, 'm> {
+    context: &'c Context<'m>,
+    locals: Vec<ValueType>,
+    results: Vec<ValueType>,
+    vals: Vec<Option<ValueType>>,
+    ctrls: Vec<CtrlFrame>,
+    offset: usize,
+}
+
+impl<'c, 'm> FuncValidator<'c, 'm> {
+    fn new(context: &'c Context<'m>, locals: Vec<ValueType>, results: Vec<ValueType>) -> Self {
+        Self {
+            context,
+            locals,
+            results,
+            vals: vec![],
+            ctrls: vec![],
+            offset: 0,
+        }
+    }
+
+    fn func(&mut self, body: &[Instr]) -> Result<(), ValidationError> {
+        let results = self.results.clone();
+        self.push_ctrl(CtrlKind::Block, vec![], results);
+        self.instrs(body)?;
+        self.pop_ctrl()?;
+        Ok(())
+    }
+
+    fn error(&self, message: impl Into<String>) -> ValidationError {
+        ValidationError {
+            message: message.into(),
+            func: None,
+            offset: Some(self.offset),
+        }
+    }
+
+    // Attaches the current instruction offset to errors of the context lookups.
+    fn at<T>(&self, result: Result<T, ValidationError>) -> Result<T, ValidationError> {
+        result.map_err(|e| self.error(e.message))
+    }
+
+    fn push_val(&mut self, value_type: Option<ValueType>) {
+        self.vals.push(value_type);
+    }
+
+    fn pop_val(&mut self) -> Result<Option<ValueType>, ValidationError> {
+        let frame = self.ctrls.last().unwrap();
+        if self.vals.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return Err(self.error("type mismatch"));
+        }
+        Ok(self.vals.pop().unwrap())
+    }
+
+    fn pop_expect(&mut self, expected: Option<ValueType>) -> Result<Option<ValueType>, ValidationError> {
+        let actual = self.pop_val()?;
+        match (actual, expected) {
+            (Some(actual), Some(expected)) if actual != expected => Err(self.error("type mismatch")),
+            (None, _) => Ok(expected),
+            _ => Ok(actual),
+        }
+    }
+
+    fn push_vals(&mut self, types: &[ValueType]) {
+        self.vals.extend(types.iter().map(|t| Some(*t)));
+    }
+
+    fn pop_vals(&mut self, types: &[ValueType]) -> Result<(), ValidationError> {
+        for value_type in types.iter().rev() {
+            self.pop_expect(Some(*value_type))?;
+        }
+        Ok(())
+    }
+
+    fn push_ctrl(&mut self, kind: CtrlKind, start_types: Vec<ValueType>, end_types: Vec<ValueType>) {
+        self.ctrls.push(CtrlFrame {
+            kind,
+            start_types: start_types.clone(),
+            end_types,
+            height: self.vals.len(),
+            unreachable: false,
+        });
+        self.push_vals(&start_types);
+    }
+
+    fn pop_ctrl(&mut self) -> Result<CtrlFrame, ValidationError> {
+        let end_types = self.ctrls.last().unwrap().end_types.clone();
+        self.pop_vals(&end_types)?;
+        if self.vals.len() != self.ctrls.last().unwrap().height {
+            return Err(self.error("type mismatch"));
+        }
+        Ok(self.ctrls.pop().unwrap())
+    }
+
+    fn unreachable(&mut self) {
+        let frame = self.ctrls.last_mut().unwrap();
+        self.vals.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn label_types(&self, depth: usize) -> Result<Vec<ValueType>, ValidationError> {
+        match self.ctrls.len().checked_sub(depth + 1) {
+            Some(idx) => Ok(self.ctrls[idx].label_types().to_vec()),
+            None => Err(self.error(format!("unknown label {}", depth))),
+        }
+    }
+
+    fn block_type(&self, block_type: &BlockType) -> Result<FuncType, ValidationError> {
+        match block_type {
+            BlockType::Empty => Ok((vec![], vec![])),
+            BlockType::Value(value_type) => Ok((vec![], vec![*value_type])),
+            BlockType::Type(idx) => self.at(self.context.func_type(*idx)).cloned(),
+        }
+    }
+
+    fn local(&self, idx: usize) -> Result<ValueType, ValidationError> {
+        self.locals.get(idx).copied().ok_or_else(|| self.error(format!("unknown local {}", idx)))
+    }
+
+    // The alignment of a memory access must not exceed the natural alignment of its type.
+    fn memarg(&self, memarg: &MemArg, natural_align: u32) -> Result<(), ValidationError> {
+        self.at(self.context.mem(0))?;
+        if memarg.align > natural_align {
+            return Err(self.error("alignment must not be larger than natural"));
+        }
+        Ok(())
+    }
+
+    fn lane(&self, lane: u8, lanes: u8) -> Result<(), ValidationError> {
+        if lane >= lanes {
+            return Err(self.error("invalid lane index"));
+        }
+        Ok(())
+    }
+
+    // Validates `instrs` and the bodies of the blocks in them. Nested bodies are kept on a
+    // stack together with the else branch of an if that is still to come, rather than
+    // validated recursively.
+    fn instrs(&mut self, instrs: &[Instr]) -> Result<(), ValidationError> {
+        let mut bodies: Vec<(_, Option<&[Instr]>)> = vec![(instrs.iter(), None)];
+        while let Some((body, otherwise)) = bodies.last_mut() {
+            let Some(instr) = body.next() else {
+                // A missing else branch is validated as an empty one, so it must pass the
+                // parameters of the if through as its results
+                if let Some(instrs) = otherwise.take() {
+                    let frame = self.pop_ctrl()?;
+                    self.push_ctrl(CtrlKind::If, frame.start_types, frame.end_types);
+                    *body = instrs.iter();
+                    continue;
+                }
+                bodies.pop();
+                // The frame of the outermost body belongs to the caller
+                if !bodies.is_empty() {
+                    let frame = self.pop_ctrl()?;
+                    self.push_vals(&frame.end_types);
+                }
+                continue;
+            };
+            self.offset += 1;
+            match instr {
+                Instr::Block(block_type, body) | Instr::Loop(block_type, body) => {
+                    let (params, results) = self.block_type(block_type)?;
+                    let kind = if matches!(instr, Instr::Loop(..)) { CtrlKind::Loop } else { CtrlKind::Block };
+                    self.pop_vals(&params)?;
+                    self.push_ctrl(kind, params, results);
+                    bodies.push((body.iter(), None));
+                },
+                Instr::If(block_type, then, otherwise) => {
+                    let (params, results) = self.block_type(block_type)?;
+                    self.pop_expect(Some(I32))?;
+                    self.pop_vals(&params)?;
+                    self.push_ctrl(CtrlKind::If, params, results);
+                    bodies.push((then.iter(), Some(otherwise.as_slice())));
+                },
+                _ => self.instr(instr)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: &Instr) -> Result<(), ValidationError> {
+        let (params, results): (&[ValueType], &[ValueType]) = match instr {
+            // Control instructions
+            Instr::Unreachable => {
+                self.unreachable();
+                return Ok(());
+            },
+            Instr::Nop => (&[], &[]),
+            // Validated by `instrs`
+            Instr::Block(..) | Instr::Loop(..) | Instr::If(..) => unreachable!("blocks are validated by `instrs`"),
+            Instr::Br(depth) => {
+                let types = self.label_types(*depth)?;
+                self.pop_vals(&types)?;
+                self.unreachable();
+                return Ok(());
+            },
+            Instr::BrIf(depth) => {
+                let types = self.label_types(*depth)?;
+                self.pop_expect(Some(I32))?;
+                self.pop_vals(&types)?;
+                self.push_vals(&types);
+                return Ok(());
+            },
+            Instr::BrTable(depths, default) => {
+                self.pop_expect(Some(I32))?;
+                let default_types = self.label_types(*default)?;
+                for depth in depths {
+                    let types = self.label_types(*depth)?;
+                    if types.len() != default_types.len() {
+                        return Err(self.error("type mismatch"));
+                    }
+                    // Each target is checked against the operands, which stay in place
+                    let saved = self.vals.clone();
+                    self.pop_vals(&types)?;
+                    self.vals = saved;
+                }
+                self.pop_vals(&default_types)?;
+                self.unreachable();
+                return Ok(());
+            },
+            Instr::Return => {
+                let results = self.results.clone();
+                self.pop_vals(&results)?;
+                self.unreachable();
+                return Ok(());
+            },
+            Instr::Call(idx) => {
+                let type_idx = self.at(self.context.func(*idx))?;
+                let (params, results) = self.at(self.context.func_type(type_idx))?;
+                (params, results)
+            },
+            Instr::CallIndirect(table, type_idx) => {
+                let table_type = self.at(self.context.table(*table))?;
+                if table_type.elem_type != ReferenceType::FuncRef {
+                    return Err(self.error("type mismatch"));
+                }
+                let (params, results) = self.at(self.context.func_type(*type_idx))?;
+                self.pop_expect(Some(I32))?;
+                (params, results)
+            },
+
+            // Reference instructions
+            Instr::RefNull(ref_type) => {
+                self.push_val(Some(ValueType::ReferenceType(*ref_type)));
+                return Ok(());
+            },
+            Instr::RefIsNull => {
+                if let Some(value_type) = self.pop_val()? {
+                    if !matches!(value_type, ValueType::ReferenceType(_)) {
+                        return Err(self.error("type mismatch"));
+                    }
+                }
+                (&[], &[I32])
+            },
+            Instr::RefFunc(idx) => {
+                self.at(self.context.func(*idx))?;
+                if !self.context.refs.contains(idx) {
+                    return Err(self.error("undeclared function reference"));
+                }
+                (&[], &[ValueType::ReferenceType(ReferenceType::FuncRef)])
+            },
+
+            // Parametric instructions
+            Instr::Drop => {
+                self.pop_val()?;
+                return Ok(());
+            },
+            // Without a type annotation, select only applies to numeric and vector operands
+            Instr::Select(None) => {
+                self.pop_expect(Some(I32))?;
+                let t1 = self.pop_val()?;
+                let t2 = self.pop_val()?;
+                let is_num = |t: Option<ValueType>| !matches!(t, Some(ValueType::ReferenceType(_)));
+                if !is_num(t1) || !is_num(t2) {
+                    return Err(self.error("type mismatch"));
+                }
+                if t1.is_some() && t2.is_some() && t1 != t2 {
+                    return Err(self.error("type mismatch"));
+                }
+                self.push_val(t1.or(t2));
+                return Ok(());
+            },
+            Instr::Select(Some(types)) => {
+                if types.len() != 1 {
+                    return Err(self.error("invalid result arity"));
+                }
+                self.pop_expect(Some(I32))?;
+                self.pop_vals(types)?;
+                self.pop_vals(types)?;
+                self.push_vals(types);
+                return Ok(());
+            },
+
+            // Variable instructions
+            Instr::LocalGet(idx) => {
+                let value_type = self.local(*idx)?;
+                self.push_val(Some(value_type));
+                return Ok(());
+            },
+            Instr::LocalSet(idx) => {
+                let value_type = self.local(*idx)?;
+                self.pop_expect(Some(value_type))?;
+                return Ok(());
+            },
+            Instr::LocalTee(idx) => {
+                let value_type = self.local(*idx)?;
+                self.pop_expect(Some(value_type))?;
+                self.push_val(Some(value_type));
+                return Ok(());
+            },
+            Instr::GlobalGet(idx) => {
+                let global_type = self.at(self.context.global(*idx))?;
+                self.push_val(Some(global_type.value_type));
+                return Ok(());
+            },
+            Instr::GlobalSet(idx) => {
+                let global_type = self.at(self.context.global(*idx))?;
+                if !global_type.mutable {
+                    return Err(self.error("global is immutable"));
+                }
+                self.pop_expect(Some(global_type.value_type))?;
+                return Ok(());
+            },
+
+            // Table instructions
+            Instr::TableGet(idx) => {
+                let elem_type = ValueType::ReferenceType(self.at(self.context.table(*idx))?.elem_type);
+                self.pop_expect(Some(I32))?;
+                self.push_val(Some(elem_type));
+                return Ok(());
+            },
+            Instr::TableSet(idx) => {
+                let elem_type = ValueType::ReferenceType(self.at(self.context.table(*idx))?.elem_type);
+                self.pop_vals(&[I32, elem_type])?;
+                return Ok(());
+            },
+            Instr::TableSize(idx) => {
+                self.at(self.context.table(*idx))?;
+                (&[], &[I32])
+            },
+            Instr::TableGrow(idx) => {
+                let elem_type = ValueType::ReferenceType(self.at(self.context.table(*idx))?.elem_type);
+                self.pop_vals(&[elem_type, I32])?;
+                self.push_val(Some(I32));
+                return Ok(());
+            },
+            Instr::TableFill(idx) => {
+                let elem_type = ValueType::ReferenceType(self.at(self.context.table(*idx))?.elem_type);
+                self.pop_vals(&[I32, elem_type, I32])?;
+                return Ok(());
+            },
+            Instr::TableCopy(dst, src) => {
+                let dst = self.at(self.context.table(*dst))?;
+                let src = self.at(self.context.table(*src))?;
+                if dst.elem_type != src.elem_type {
+                    return Err(self.error("type mismatch"));
+                }
+                (&[I32, I32, I32], &[])
+            },
+            Instr::TableInit(table, elem) => {
+                let table = self.at(self.context.table(*table))?;
+                let elem_type = self.at(self.context.elem(*elem))?;
+                if table.elem_type != elem_type {
+                    return Err(self.error("type mismatch"));
+                }
+                (&[I32, I32, I32], &[])
+            },
+            Instr::ElemDrop(idx) => {
+                self.at(self.context.elem(*idx))?;
+                (&[], &[])
+            },
+
+            // Memory instructions
+            Instr::MemorySize => {
+                self.at(self.context.mem(0))?;
+                (&[], &[I32])
+            },
+            Instr::MemoryGrow => {
+                self.at(self.context.mem(0))?;
+                (&[I32], &[I32])
+            },
+            Instr::MemoryFill | Instr::MemoryCopy => {
+                self.at(self.context.mem(0))?;
+                (&[I32, I32, I32], &[])
+            },
+            Instr::MemoryInit(idx) => {
+                self.at(self.context.mem(0))?;
+                self.at(self.context.data(*idx))?;
+                (&[I32, I32, I32], &[])
+            },
+            Instr::DataDrop(idx) => {
+                self.at(self.context.data(*idx))?;
+                (&[], &[])
+            },
+
+            // Numeric instructions
+            Instr::I32Const(_) => (&[], &[I32]),
+            Instr::I64Const(_) => (&[], &[I64]),
+            Instr::F32Const(_) => (&[], &[F32]),
+            Instr::F64Const(_) => (&[], &[F64]),
+
+            // Vector instructions
+            Instr::V128Const(_) => (&[], &[V128]),
+            Instr::I8x16Shuffle(lanes) => {
+                if lanes.iter().any(|lane| *lane >= 32) {
+                    return Err(self.error("invalid lane index"));
+                }
+                (&[V128, V128], &[V128])
+            },
+
+            _ => self.table_instr(instr)?,
+        };
+        self.pop_vals(params)?;
+        self.push_vals(results);
+        Ok(())
+    }
+
+    // Checks the memory arguments and lane indices of the instructions described by the
+    // tables of `ast::Instr` and returns their signature.
+    fn table_instr(&self, instr: &Instr) -> Result<(&'static [ValueType], &'static [ValueType]), ValidationError> {
+        if let (Some(memarg), Some((_, _, natural_align))) = (instr.memarg(), instr.memory_info()) {
+            self.memarg(memarg, natural_align)?;
+        }
+        if let (Some(memarg), Some((_, _, (natural_align, lanes)))) = (instr.memarg(), instr.memory_lane_info()) {
+            self.memarg(memarg, natural_align)?;
+            self.lane(instr.lane().unwrap(), lanes)?;
+        }
+        if let (Some(lane), Some((_, _, lanes))) = (instr.lane(), instr.lane_info()) {
+            self.lane(lane, lanes)?;
+        }
+
+        table_signature(instr).ok_or_else(|| self.error("unknown instruction"))
+    }
+}
+
+// Operand and result types of the instructions described by the tables of `ast::Instr`.
+pub(crate) fn table_signature(instr: &Instr) -> Option<(&'static [ValueType], &'static [ValueType])> {
+    let signature: (&'static [ValueType], &'static [ValueType]) = match instr {
+        // Memory instructions
+        Instr::I32Load(_) | Instr::I32Load8S(_) | Instr::I32Load8U(_) | Instr::I32Load16S(_)
+        | Instr::I32Load16U(_) => (&[I32], &[I32]),
+        Instr::I64Load(_) | Instr::I64Load8S(_) | Instr::I64Load8U(_) | Instr::I64Load16S(_)
+        | Instr::I64Load16U(_) | Instr::I64Load32S(_) | Instr::I64Load32U(_) => (&[I32], &[I64]),
+        Instr::F32Load(_) => (&[I32], &[F32]),
+        Instr::F64Load(_) => (&[I32], &[F64]),
+        Instr::I32Store(_) | Instr::I32Store8(_) | Instr::I32Store16(_) => (&[I32, I32], &[]),
+        Instr::I64Store(_) | Instr::I64Store8(_) | Instr::I64Store16(_) | Instr::I64Store32(_) => (&[I32, I64], &[]),
+        Instr::F32Store(_) => (&[I32, F32], &[]),
+        Instr::F64Store(_) => (&[I32, F64], &[]),
+        Instr::V128Store(_) => (&[I32, V128], &[]),
+        Instr::V128Load(_) | Instr::V128Load8x8S(_) | Instr::V128Load8x8U(_) | Instr::V128Load16x4S(_)
+        | Instr::V128Load16x4U(_) | Instr::V128Load32x2S(_) | Instr::V128Load32x2U(_)
+        | Instr::V128Load8Splat(_) | Instr::V128Load16Splat(_) | Instr::V128Load32Splat(_)
+        | Instr::V128Load64Splat(_) | Instr::V128Load32Zero(_) | Instr::V128Load64Zero(_) => (&[I32], &[V128]),
+        Instr::V128Load8Lane(..) | Instr::V128Load16Lane(..) | Instr::V128Load32Lane(..)
+        | Instr::V128Load64Lane(..) => (&[I32, V128], &[V128]),
+        Instr::V128Store8Lane(..) | Instr::V128Store16Lane(..) | Instr::V128Store32Lane(..)
+        | Instr::V128Store64Lane(..) => (&[I32, V128], &[]),
+
+        // Lane instructions
+        Instr::I8x16ExtractLaneS(_) | Instr::I8x16ExtractLaneU(_) | Instr::I16x8ExtractLaneS(_)
+        | Instr::I16x8ExtractLaneU(_) | Instr::I32x4ExtractLane(_) => (&[V128], &[I32]),
+        Instr::I64x2ExtractLane(_) => (&[V128], &[I64]),
+        Instr::F32x4ExtractLane(_) => (&[V128], &[F32]),
+        Instr::F64x2ExtractLane(_) => (&[V128], &[F64]),
+        Instr::I8x16ReplaceLane(_) | Instr::I16x8ReplaceLane(_) | Instr::I32x4ReplaceLane(_) => (&[V128, I32], &[V128]),
+        Instr::I64x2ReplaceLane(_) => (&[V128, I64], &[V128]),
+        Instr::F32x4ReplaceLane(_) => (&[V128, F32], &[V128]),
+        Instr::F64x2ReplaceLane(_) => (&[V128, F64], &[V128]),
+
+        // Numeric and vector instructions without immediates
+        Instr::I32Eqz | Instr::I32Clz | Instr::I32Ctz | Instr::I32Popcnt | Instr::I32Extend8S |
+        Instr::I32Extend16S => (&[I32], &[I32]),
+        Instr::I32Eq | Instr::I32Ne | Instr::I32LtS | Instr::I32LtU | Instr::I32GtS | Instr::I32GtU |
+        Instr::I32LeS | Instr::I32LeU | Instr::I32GeS | Instr::I32GeU | Instr::I32Add | Instr::I32Sub |
+        Instr::I32Mul | Instr::I32DivS | Instr::I32DivU | Instr::I32RemS | Instr::I32RemU | Instr::I32And |
+        Instr::I32Or | Instr::I32Xor | Instr::I32Shl | Instr::I32ShrS | Instr::I32ShrU | Instr::I32Rotl |
+        Instr::I32Rotr => (&[I32, I32], &[I32]),
+        Instr::I64Eqz | Instr::I32WrapI64 => (&[I64], &[I32]),
+        Instr::I64Eq | Instr::I64Ne | Instr::I64LtS | Instr::I64LtU | Instr::I64GtS | Instr::I64GtU |
+        Instr::I64LeS | Instr::I64LeU | Instr::I64GeS | Instr::I64GeU => (&[I64, I64], &[I32]),
+        Instr::F32Eq | Instr::F32Ne | Instr::F32Lt | Instr::F32Gt | Instr::F32Le | Instr::F32Ge => (&[F32, F32], &[I32]),
+        Instr::F64Eq | Instr::F64Ne | Instr::F64Lt | Instr::F64Gt | Instr::F64Le | Instr::F64Ge => (&[F64, F64], &[I32]),
+        Instr::I64Clz | Instr::I64Ctz | Instr::I64Popcnt | Instr::I64Extend8S | Instr::I64Extend16S |
+        Instr::I64Extend32S => (&[I64], &[I64]),
+        Instr::I64Add | Instr::I64Sub | Instr::I64Mul | Instr::I64DivS | Instr::I64DivU | Instr::I64RemS |
+        Instr::I64RemU | Instr::I64And | Instr::I64Or | Instr::I64Xor | Instr::I64Shl | Instr::I64ShrS |
+        Instr::I64ShrU | Instr::I64Rotl | Instr::I64Rotr => (&[I64, I64], &[I64]),
+        Instr::F32Abs | Instr::F32Neg | Instr::F32Ceil | Instr::F32Floor | Instr::F32Trunc |
+        Instr::F32Nearest | Instr::F32Sqrt => (&[F32], &[F32]),
+        Instr::F32Add | Instr::F32Sub | Instr::F32Mul | Instr::F32Div | Instr::F32Min | Instr::F32Max |
+        Instr::F32Copysign => (&[F32, F32], &[F32]),
+        Instr::F64Abs | Instr::F64Neg | Instr::F64Ceil | Instr::F64Floor | Instr::F64Trunc |
+        Instr::F64Nearest | Instr::F64Sqrt => (&[F64], &[F64]),
+        Instr::F64Add | Instr::F64Sub | Instr::F64Mul | Instr::F64Div | Instr::F64Min | Instr::F64Max |
+        Instr::F64Copysign => (&[F64, F64], &[F64]),
+        Instr::I32TruncF32S | Instr::I32TruncF32U | Instr::I32ReinterpretF32 | Instr::I32TruncSatF32S |
+        Instr::I32TruncSatF32U => (&[F32], &[I32]),
+        Instr::I32TruncF64S | Instr::I32TruncF64U | Instr::I32TruncSatF64S | Instr::I32TruncSatF64U => (&[F64], &[I32]),
+        Instr::I64ExtendI32S | Instr::I64ExtendI32U => (&[I32], &[I64]),
+        Instr::I64TruncF32S | Instr::I64TruncF32U | Instr::I64TruncSatF32S | Instr::I64TruncSatF32U => (&[F32], &[I64]),
+        Instr::I64TruncF64S | Instr::I64TruncF64U | Instr::I64ReinterpretF64 | Instr::I64TruncSatF64S |
+        Instr::I64TruncSatF64U => (&[F64], &[I64]),
+        Instr::F32ConvertI32S | Instr::F32ConvertI32U | Instr::F32ReinterpretI32 => (&[I32], &[F32]),
+        Instr::F32ConvertI64S | Instr::F32ConvertI64U => (&[I64], &[F32]),
+        Instr::F32DemoteF64 => (&[F64], &[F32]),
+        Instr::F64ConvertI32S | Instr::F64ConvertI32U => (&[I32], &[F64]),
+        Instr::F64ConvertI64S | Instr::F64ConvertI64U | Instr::F64ReinterpretI64 => (&[I64], &[F64]),
+        Instr::F64PromoteF32 => (&[F32], &[F64]),
+        Instr::I8x16Swizzle | Instr::I8x16Eq | Instr::I8x16Ne | Instr::I8x16LtS | Instr::I8x16LtU |
+        Instr::I8x16GtS | Instr::I8x16GtU | Instr::I8x16LeS | Instr::I8x16LeU | Instr::I8x16GeS |
+        Instr::I8x16GeU | Instr::I16x8Eq | Instr::I16x8Ne | Instr::I16x8LtS | Instr::I16x8LtU |
+        Instr::I16x8GtS | Instr::I16x8GtU | Instr::I16x8LeS | Instr::I16x8LeU | Instr::I16x8GeS |
+        Instr::I16x8GeU | Instr::I32x4Eq | Instr::I32x4Ne | Instr::I32x4LtS | Instr::I32x4LtU |
+        Instr::I32x4GtS | Instr::I32x4GtU | Instr::I32x4LeS | Instr::I32x4LeU | Instr::I32x4GeS |
+        Instr::I32x4GeU | Instr::F32x4Eq | Instr::F32x4Ne | Instr::F32x4Lt | Instr::F32x4Gt |
+        Instr::F32x4Le | Instr::F32x4Ge | Instr::F64x2Eq | Instr::F64x2Ne | Instr::F64x2Lt |
+        Instr::F64x2Gt | Instr::F64x2Le | Instr::F64x2Ge | Instr::V128And | Instr::V128Andnot |
+        Instr::V128Or | Instr::V128Xor | Instr::I8x16NarrowI16x8S | Instr::I8x16NarrowI16x8U |
+        Instr::I8x16Add | Instr::I8x16AddSatS | Instr::I8x16AddSatU | Instr::I8x16Sub |
+        Instr::I8x16SubSatS | Instr::I8x16SubSatU | Instr::I8x16MinS | Instr::I8x16MinU |
+        Instr::I8x16MaxS | Instr::I8x16MaxU | Instr::I8x16AvgrU | Instr::I16x8Q15mulrSatS |
+        Instr::I16x8NarrowI32x4S | Instr::I16x8NarrowI32x4U | Instr::I16x8Add | Instr::I16x8AddSatS |
+        Instr::I16x8AddSatU | Instr::I16x8Sub | Instr::I16x8SubSatS | Instr::I16x8SubSatU |
+        Instr::I16x8Mul | Instr::I16x8MinS | Instr::I16x8MinU | Instr::I16x8MaxS | Instr::I16x8MaxU |
+        Instr::I16x8AvgrU | Instr::I16x8ExtmulLowI8x16S | Instr::I16x8ExtmulHighI8x16S |
+        Instr::I16x8ExtmulLowI8x16U | Instr::I16x8ExtmulHighI8x16U | Instr::I32x4Add | Instr::I32x4Sub |
+        Instr::I32x4Mul | Instr::I32x4MinS | Instr::I32x4MinU | Instr::I32x4MaxS | Instr::I32x4MaxU |
+        Instr::I32x4DotI16x8S | Instr::I32x4ExtmulLowI16x8S | Instr::I32x4ExtmulHighI16x8S |
+        Instr::I32x4ExtmulLowI16x8U | Instr::I32x4ExtmulHighI16x8U | Instr::I64x2Add | Instr::I64x2Sub |
+        Instr::I64x2Mul | Instr::I64x2Eq | Instr::I64x2Ne | Instr::I64x2LtS | Instr::I64x2GtS |
+        Instr::I64x2LeS | Instr::I64x2GeS | Instr::I64x2ExtmulLowI32x4S | Instr::I64x2ExtmulHighI32x4S |
+        Instr::I64x2ExtmulLowI32x4U | Instr::I64x2ExtmulHighI32x4U | Instr::F32x4Add | Instr::F32x4Sub |
+        Instr::F32x4Mul | Instr::F32x4Div | Instr::F32x4Min | Instr::F32x4Max | Instr::F32x4Pmin |
+        Instr::F32x4Pmax | Instr::F64x2Add | Instr::F64x2Sub | Instr::F64x2Mul | Instr::F64x2Div |
+        Instr::F64x2Min | Instr::F64x2Max | Instr::F64x2Pmin | Instr::F64x2Pmax => (&[V128, V128], &[V128]),
+        Instr::I8x16Splat | Instr::I16x8Splat | Instr::I32x4Splat => (&[I32], &[V128]),
+        Instr::I64x2Splat => (&[I64], &[V128]),
+        Instr::F32x4Splat => (&[F32], &[V128]),
+        Instr::F64x2Splat => (&[F64], &[V128]),
+        Instr::V128Not | Instr::F32x4DemoteF64x2Zero | Instr::F64x2PromoteLowF32x4 | Instr::I8x16Abs |
+        Instr::I8x16Neg | Instr::I8x16Popcnt | Instr::F32x4Ceil | Instr::F32x4Floor | Instr::F32x4Trunc |
+        Instr::F32x4Nearest | Instr::F64x2Ceil | Instr::F64x2Floor | Instr::F64x2Trunc |
+        Instr::I16x8ExtaddPairwiseI8x16S | Instr::I16x8ExtaddPairwiseI8x16U |
+        Instr::I32x4ExtaddPairwiseI16x8S | Instr::I32x4ExtaddPairwiseI16x8U | Instr::I16x8Abs |
+        Instr::I16x8Neg | Instr::I16x8ExtendLowI8x16S | Instr::I16x8ExtendHighI8x16S |
+        Instr::I16x8ExtendLowI8x16U | Instr::I16x8ExtendHighI8x16U | Instr::F64x2Nearest |
+        Instr::I32x4Abs | Instr::I32x4Neg | Instr::I32x4ExtendLowI16x8S | Instr::I32x4ExtendHighI16x8S |
+        Instr::I32x4ExtendLowI16x8U | Instr::I32x4ExtendHighI16x8U | Instr::I64x2Abs | Instr::I64x2Neg |
+        Instr::I64x2ExtendLowI32x4S | Instr::I64x2ExtendHighI32x4S | Instr::I64x2ExtendLowI32x4U |
+        Instr::I64x2ExtendHighI32x4U | Instr::F32x4Abs | Instr::F32x4Neg | Instr::F32x4Sqrt |
+        Instr::F64x2Abs | Instr::F64x2Neg | Instr::F64x2Sqrt | Instr::I32x4TruncSatF32x4S |
+        Instr::I32x4TruncSatF32x4U | Instr::F32x4ConvertI32x4S | Instr::F32x4ConvertI32x4U |
+        Instr::I32x4TruncSatF64x2SZero | Instr::I32x4TruncSatF64x2UZero | Instr::F64x2ConvertLowI32x4S |
+        Instr::F64x2ConvertLowI32x4U => (&[V128], &[V128]),
+        Instr::V128Bitselect => (&[V128, V128, V128], &[V128]),
+        Instr::V128AnyTrue | Instr::I8x16AllTrue | Instr::I8x16Bitmask | Instr::I16x8AllTrue |
+        Instr::I16x8Bitmask | Instr::I32x4AllTrue | Instr::I32x4Bitmask | Instr::I64x2AllTrue |
+        Instr::I64x2Bitmask => (&[V128], &[I32]),
+        Instr::I8x16Shl | Instr::I8x16ShrS | Instr::I8x16ShrU | Instr::I16x8Shl | Instr::I16x8ShrS |
+        Instr::I16x8ShrU | Instr::I32x4Shl | Instr::I32x4ShrS | Instr::I32x4ShrU | Instr::I64x2Shl |
+        Instr::I64x2ShrS | Instr::I64x2ShrU => (&[V128, I32], &[V128]),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::MAX_NESTING_DEPTH;
+    use crate::parser;
+
+    fn is_valid(source: &str) -> bool {
+        validate(&parser::parse(source).unwrap()).is_ok()
+    }
+
+    fn error(source: &str) -> (String, Option<usize>, Option<usize>) {
+        let error = validate(&parser::parse(source).unwrap()).unwrap_err();
+        (error.message, error.func, error.offset)
+    }
+
+    fn at(message: &str, func: usize, offset: usize) -> (String, Option<usize>, Option<usize>) {
+        (message.to_string(), Some(func), Some(offset))
+    }
+
+    fn module_level(message: &str) -> (String, Option<usize>, Option<usize>) {
+        (message.to_string(), None, None)
+    }
+
+    #[test]
+    fn rejects_invalid_instructions() {
+        // Offsets count the instructions of the body from 1, including those of nested blocks,
+        // and function indices include the imported functions
+        assert_eq!(error("(module (func i32.const 1 i32.add drop))"), at("type mismatch", 0, 2));
+        assert_eq!(error("(module (func (param f32) local.get 0 i32.eqz drop))"), at("type mismatch", 0, 2));
+        assert_eq!(
+            error(r#"(module (import "env" "f" (func)) (func nop block i64.const 0 i32.eqz drop end))"#),
+            at("type mismatch", 1, 4),
+        );
+        assert_eq!(error("(module (func (result i32) i64.const 0))"), at("type mismatch", 0, 1));
+        assert_eq!(error("(module (func block br 2 end))"), at("unknown label 2", 0, 2));
+        assert_eq!(error("(module (func i32.const 1 if nop else br 3 end))"), at("unknown label 3", 0, 4));
+    }
+
+    #[test]
+    fn rejects_operand_stack_underflow() {
+        assert_eq!(error("(module (func i32.add drop))"), at("type mismatch", 0, 1));
+        assert_eq!(error("(module (func (result i32)))"), at("type mismatch", 0, 0));
+        // A block cannot pop the operands of the enclosing one.
+        assert_eq!(error("(module (func i32.const 0 block i32.eqz drop end drop))"), at("type mismatch", 0, 3));
+        assert_eq!(error("(module (func i32.const 0 block end))"), at("type mismatch", 0, 2));
+    }
+
+    #[test]
+    fn checks_branch_label_arity() {
+        assert!(is_valid("(module (func (result i32) block (result i32) i32.const 1 br 0 end))"));
+        assert_eq!(error("(module (func (result i32) block (result i32) br 0 end))"), at("type mismatch", 0, 2));
+        assert_eq!(
+            error("(module (func block (result i32) i64.const 1 br 0 end drop))"),
+            at("type mismatch", 0, 3),
+        );
+        // Branches to a loop take the loop parameters, not its results.
+        assert!(is_valid("(module (func loop (result i32) br 0 end drop))"));
+        assert_eq!(
+            error("(module (func i32.const 0 loop (param i32) drop br 0 end))"),
+            at("type mismatch", 0, 4),
+        );
+        assert_eq!(
+            error("(module (func block (result i32) i32.const 0 br_if 0 end drop))"),
+            at("type mismatch", 0, 3),
+        );
+        assert!(is_valid(
+            "(module (func (result i32) block (result i32) i32.const 1 i32.const 0 br_table 0 0 end))"
+        ));
+        assert_eq!(
+            error("(module (func block (result i64) block (result i32) i32.const 1 i32.const 0 br_table 0 1 end drop end drop))"),
+            at("type mismatch", 0, 5),
+        );
+    }
+
+    #[test]
+    fn unreachable_code_is_stack_polymorphic() {
+        assert!(is_valid("(module (func (result i32) unreachable))"));
+        assert!(is_valid("(module (func (result i32) unreachable i32.add))"));
+        assert!(is_valid("(module (func unreachable i64.const 0 i64.add drop))"));
+        assert!(is_valid("(module (func (result f32) f32.const 0 return drop drop))"));
+        assert_eq!(error("(module (func (result i64) unreachable i32.const 0 i32.add))"), at("type mismatch", 0, 3));
+        assert_eq!(error("(module (func br 0 i64.const 0 i32.add drop))"), at("type mismatch", 0, 3));
+        // Only the current block is unreachable.
+        assert_eq!(error("(module (func block unreachable end i32.add drop))"), at("type mismatch", 0, 3));
+    }
+
+    #[test]
+    fn checks_if_without_else() {
+        assert!(is_valid("(module (func i32.const 1 if nop end))"));
+        assert!(is_valid(
+            "(module (func (result i32) i32.const 2 i32.const 1 if (param i32) (result i32) i32.const 1 i32.add end))"
+        ));
+        // Without an else branch, the missing branch passes its parameters through as results.
+        assert_eq!(
+            error("(module (func (result i32) i32.const 1 if (result i32) i32.const 1 end))"),
+            at("type mismatch", 0, 3),
+        );
+        assert_eq!(error("(module (func if nop end))"), at("type mismatch", 0, 1));
+    }
+
+    #[test]
+    fn rejects_unknown_indices() {
+        assert_eq!(error("(module (func call 3))"), at("unknown function 3", 0, 1));
+        assert_eq!(error("(module (type (func)) (func i32.const 0 call_indirect (type 0)))"), at("unknown table 0", 0, 2));
+        assert_eq!(error("(module (func i32.const 0 i32.load drop))"), at("unknown memory 0", 0, 2));
+        assert_eq!(error("(module (func global.get 1 drop))"), at("unknown global 1", 0, 1));
+        assert_eq!(
+            error("(module (table 0 funcref) (func (call_indirect (type 1) (i32.const 0))))"),
+            at("unknown type 1", 0, 2),
+        );
+        assert_eq!(error("(module (func block (type 1) end))"), at("unknown type 1", 0, 1));
+        assert_eq!(error(r#"(module (export "f" (func 1)))"#), module_level("unknown function 1"));
+        assert_eq!(error(r#"(module (export "t" (table 0)))"#), module_level("unknown table 0"));
+    }
+
+    #[test]
+    fn rejects_invalid_module_fields() {
+        let min_above_max = module_level("size minimum must not be greater than maximum");
+        assert_eq!(error("(module (memory 2 1))"), min_above_max);
+        assert_eq!(error("(module (table 2 1 funcref))"), min_above_max);
+        assert_eq!(
+            error(r#"(module (func) (export "a" (func 0)) (export "a" (func 0)))"#),
+            module_level("duplicate export name"),
+        );
+        assert_eq!(error("(module (global i32 i32.const 1 i32.clz))"), module_level("constant expression required"));
+        assert_eq!(
+            error(r#"(module (import "env" "g" (global (mut i32))) (global i32 global.get 0))"#),
+            module_level("constant expression required"),
+        );
+    }
+
+    // Runs on the stack of the test thread.
+    #[test]
+    fn validates_deeply_nested_blocks() {
+        let source = format!("(module (func {} {}))", "block ".repeat(MAX_NESTING_DEPTH), "end ".repeat(MAX_NESTING_DEPTH));
+        assert!(is_valid(&source));
+    }
+}
@@ -0,0 +1,956 @@
+use std::collections::HashMap;
+use std::str;
+
+use crate::ast::{Limits, Module, NumberType, ReferenceType, TableType, ValueType};
+use crate::lexer::Lexer;
+use crate::parser::{self, ParseError};
+use crate::runtime::{loader, Instance, Linker, RuntimeError, Store, Trap, Value};
+use crate::token::{Span, Token, TokenKind};
+use crate::validator;
+
+// A command of a spec test script together with the span of its s-expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub kind: CommandKind,
+    pub span: Span,
+}
+
+// cmd ::= module | '(' 'register' name id? ')' | action | assertion
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandKind {
+    Module(ScriptModule),
+    Register { name: String, module: Option<String> },
+    Action(Action),
+    AssertReturn { action: Action, results: Vec<Expected> },
+    AssertTrap { action: Action, message: String },
+    AssertModuleTrap { module: ScriptModule, message: String },
+    AssertExhaustion { action: Action, message: String },
+    AssertMalformed { module: ScriptModule, message: String },
+    AssertInvalid { module: ScriptModule, message: String },
+    AssertUnlinkable { module: ScriptModule, message: String },
+}
+
+impl CommandKind {
+    pub fn directive(&self) -> &'static str {
+        match self {
+            Self::Module(_) => "module",
+            Self::Register { .. } => "register",
+            Self::Action(Action::Invoke { .. }) => "invoke",
+            Self::Action(Action::Get { .. }) => "get",
+            Self::AssertReturn { .. } => "assert_return",
+            Self::AssertTrap { .. } | Self::AssertModuleTrap { .. } => "assert_trap",
+            Self::AssertExhaustion { .. } => "assert_exhaustion",
+            Self::AssertMalformed { .. } => "assert_malformed",
+            Self::AssertInvalid { .. } => "assert_invalid",
+            Self::AssertUnlinkable { .. } => "assert_unlinkable",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptModule {
+    pub id: Option<String>,
+    pub source: ModuleSource,
+}
+
+// Text modules are only parsed when their command runs, so that `assert_malformed` can
+// observe the failure. `Text` is the span of the whole `(module ...)` in the script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleSource {
+    Text(Span),
+    Binary(Vec<u8>),
+    Quote(Vec<u8>),
+}
+
+// action ::= '(' 'invoke' id? name const* ')' | '(' 'get' id? name ')'
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Invoke { module: Option<String>, name: String, args: Vec<Value> },
+    Get { module: Option<String>, name: String },
+}
+
+// The expected result of an `assert_return`. Floats are compared by their bits.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    Value(Value),
+    CanonicalNan(NumberType),
+    ArithmeticNan(NumberType),
+    // A vector of four f32 or two f64 lanes, some of which are NaN patterns
+    Lanes(Vec<Expected>),
+    // Any non-null function reference
+    RefFunc,
+}
+
+impl Expected {
+    fn matches(&self, actual: &Value) -> bool {
+        match (self, actual) {
+            (Self::Value(Value::F32(expected)), Value::F32(actual)) => expected.to_bits() == actual.to_bits(),
+            (Self::Value(Value::F64(expected)), Value::F64(actual)) => expected.to_bits() == actual.to_bits(),
+            (Self::Value(expected), actual) => expected == actual,
+            (Self::CanonicalNan(NumberType::F32), Value::F32(actual)) => actual.to_bits() & 0x7FFF_FFFF == 0x7FC0_0000,
+            (Self::CanonicalNan(NumberType::F64), Value::F64(actual)) => {
+                actual.to_bits() & 0x7FFF_FFFF_FFFF_FFFF == 0x7FF8_0000_0000_0000
+            },
+            (Self::ArithmeticNan(NumberType::F32), Value::F32(actual)) => actual.to_bits() & 0x7FC0_0000 == 0x7FC0_0000,
+            (Self::ArithmeticNan(NumberType::F64), Value::F64(actual)) => {
+                actual.to_bits() & 0x7FF8_0000_0000_0000 == 0x7FF8_0000_0000_0000
+            },
+            (Self::Lanes(lanes), Value::V128(bits)) => {
+                let width = 128 / lanes.len();
+                lanes.iter().enumerate().all(|(lane, expected)| {
+                    let lane_bits = (bits >> (lane * width)) as u64;
+                    let actual = match width {
+                        32 => Value::F32(f32::from_bits(lane_bits as u32)),
+                        _ => Value::F64(f64::from_bits(lane_bits)),
+                    };
+                    expected.matches(&actual)
+                })
+            },
+            (Self::RefFunc, Value::FuncRef(Some(_))) => true,
+            _ => false,
+        }
+    }
+}
+
+// The result of running one command.
+#[derive(Debug)]
+pub struct Outcome {
+    pub directive: &'static str,
+    pub span: Span,
+    pub result: Result<(), String>,
+}
+
+pub fn parse(source: &str) -> Result<Vec<Command>, ParseError> {
+    ScriptParser::new(source)?.script()
+}
+
+// Runs every command of the script in `source`. Only a script that cannot be parsed is an
+// error, failing commands are reported in their outcome.
+pub fn run(source: &str) -> Result<Vec<Outcome>, ParseError> {
+    let commands = parse(source)?;
+    let mut runner = Runner::new(source);
+    Ok(commands.iter().map(|command| runner.command(command)).collect())
+}
+
+struct ScriptParser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    eof: Span,
+}
+
+impl<'a> ScriptParser<'a> {
+    fn new(source: &'a str) -> Result<Self, ParseError> {
+        let mut tokens = vec![];
+        for token in Lexer::new(source) {
+            let token = token.map_err(|e| error(e.to_string(), e.span()))?;
+            if !token.kind.is_trivia() {
+                tokens.push(token);
+            }
+        }
+        Ok(Self {
+            tokens,
+            pos: 0,
+            eof: Span { start: source.len() as u32, end: source.len() as u32 },
+        })
+    }
+
+    // script ::= cmd*
+    fn script(&mut self) -> Result<Vec<Command>, ParseError> {
+        let mut commands = vec![];
+        while self.pos < self.tokens.len() {
+            commands.push(self.command()?);
+        }
+        Ok(commands)
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        let start = self.peek_span().start;
+        let (keyword, span) = self.peek_field()?;
+        let kind = match keyword {
+            "module" => CommandKind::Module(self.module()?),
+            "register" => {
+                self.pos += 2;
+                let name = self.name()?;
+                let module = self.id();
+                self.expect_right_paren()?;
+                CommandKind::Register { name, module }
+            },
+            "invoke" | "get" => CommandKind::Action(self.action()?),
+            // assertion ::= '(' 'assert_return' action result* ')'
+            "assert_return" => {
+                self.pos += 2;
+                let action = self.action()?;
+                let mut results = vec![];
+                while !self.peek_right_paren() {
+                    results.push(self.expected()?);
+                }
+                self.expect_right_paren()?;
+                CommandKind::AssertReturn { action, results }
+            },
+            //             | '(' 'assert_trap' (action | module) failure ')'
+            //             | '(' 'assert_exhaustion' action failure ')'
+            "assert_trap" | "assert_exhaustion" => {
+                self.pos += 2;
+                let kind = if keyword == "assert_trap" && self.peek_keyword_field("module") {
+                    let module = self.module()?;
+                    CommandKind::AssertModuleTrap { module, message: self.name()? }
+                } else {
+                    let action = self.action()?;
+                    let message = self.name()?;
+                    if keyword == "assert_trap" {
+                        CommandKind::AssertTrap { action, message }
+                    } else {
+                        CommandKind::AssertExhaustion { action, message }
+                    }
+                };
+                self.expect_right_paren()?;
+                kind
+            },
+            //             | '(' ('assert_malformed' | 'assert_invalid' | 'assert_unlinkable') module failure ')'
+            "assert_malformed" | "assert_invalid" | "assert_unlinkable" => {
+                self.pos += 2;
+                let module = self.module()?;
+                let message = self.name()?;
+                self.expect_right_paren()?;
+                match keyword {
+                    "assert_malformed" => CommandKind::AssertMalformed { module, message },
+                    "assert_invalid" => CommandKind::AssertInvalid { module, message },
+                    _ => CommandKind::AssertUnlinkable { module, message },
+                }
+            },
+            _ => return Err(error(format!("unknown command `{}`", keyword), span)),
+        };
+        let end = self.tokens[self.pos - 1].span.end;
+        Ok(Command { kind, span: Span { start, end } })
+    }
+
+    // module ::= '(' 'module' id? ('binary' | 'quote') string* ')' | '(' 'module' id? modulefield* ')'
+    fn module(&mut self) -> Result<ScriptModule, ParseError> {
+        let start = self.pos;
+        self.expect_left_paren()?;
+        self.expect_keyword("module")?;
+        let id = self.id();
+        let source = match self.tokens.get(self.pos).map(|t| t.kind) {
+            Some(TokenKind::Keyword(kind @ ("binary" | "quote"))) => {
+                self.pos += 1;
+                let mut bytes = vec![];
+                while !self.peek_right_paren() {
+                    bytes.extend(self.string()?);
+                }
+                self.expect_right_paren()?;
+                if kind == "binary" { ModuleSource::Binary(bytes) } else { ModuleSource::Quote(bytes) }
+            },
+            _ => {
+                self.pos = start;
+                ModuleSource::Text(self.skip_form()?)
+            },
+        };
+        Ok(ScriptModule { id, source })
+    }
+
+    fn action(&mut self) -> Result<Action, ParseError> {
+        let (keyword, span) = self.peek_field()?;
+        if keyword != "invoke" && keyword != "get" {
+            return Err(error("expected an action", span));
+        }
+        self.pos += 2;
+        let module = self.id();
+        let name = self.name()?;
+        let action = if keyword == "invoke" {
+            let mut args = vec![];
+            while !self.peek_right_paren() {
+                let span = self.peek_span();
+                match self.expected()? {
+                    Expected::Value(value) => args.push(value),
+                    _ => return Err(error("expected a constant", span)),
+                }
+            }
+            Action::Invoke { module, name, args }
+        } else {
+            Action::Get { module, name }
+        };
+        self.expect_right_paren()?;
+        Ok(action)
+    }
+
+    // const ::= '(' 'i32.const' i32 ')' | '(' 'i64.const' i64 ')' | '(' 'f32.const' f32 ')'
+    //         | '(' 'f64.const' f64 ')' | '(' 'v128.const' shape lane* ')'
+    //         | '(' 'ref.null' heaptype ')' | '(' 'ref.extern' u32 ')'
+    // result ::= const | '(' 'ref.func' ')' | float constants and lanes with 'nan:canonical'
+    //          or 'nan:arithmetic'
+    fn expected(&mut self) -> Result<Expected, ParseError> {
+        self.expect_left_paren()?;
+        let (keyword, span) = self.keyword()?;
+        let expected = match keyword {
+            "i32.const" => Expected::Value(Value::I32(self.int_bits(32)? as u32 as i32)),
+            "i64.const" => Expected::Value(Value::I64(self.int_bits(64)? as i64)),
+            "f32.const" => self.float(NumberType::F32)?,
+            "f64.const" => self.float(NumberType::F64)?,
+            "v128.const" => self.v128()?,
+            "ref.null" => match self.keyword()? {
+                ("func", _) => Expected::Value(Value::FuncRef(None)),
+                ("extern", _) => Expected::Value(Value::ExternRef(None)),
+                (_, span) => return Err(error("expected a heap type", span)),
+            },
+            "ref.extern" => Expected::Value(Value::ExternRef(Some(self.int_bits(32)? as usize))),
+            "ref.func" => Expected::RefFunc,
+            _ => return Err(error(format!("unexpected constant `{}`", keyword), span)),
+        };
+        self.expect_right_paren()?;
+        Ok(expected)
+    }
+
+    fn float(&mut self, number_type: NumberType) -> Result<Expected, ParseError> {
+        let token = self.next()?;
+        let bits = match token.kind {
+            TokenKind::Keyword("nan:canonical") => return Ok(Expected::CanonicalNan(number_type)),
+            TokenKind::Keyword("nan:arithmetic") => return Ok(Expected::ArithmeticNan(number_type)),
+            TokenKind::Float(float) if number_type == NumberType::F32 => float.to_f32_bits().map(u64::from),
+            TokenKind::Integer(integer) if number_type == NumberType::F32 => integer.to_f32_bits().map(u64::from),
+            TokenKind::Float(float) => float.to_f64_bits(),
+            TokenKind::Integer(integer) => integer.to_f64_bits(),
+            _ => return Err(error("expected a float", token.span)),
+        };
+        let bits = bits.ok_or_else(|| error("float constant out of range", token.span))?;
+        Ok(Expected::Value(match number_type {
+            NumberType::F32 => Value::F32(f32::from_bits(bits as u32)),
+            _ => Value::F64(f64::from_bits(bits)),
+        }))
+    }
+
+    fn v128(&mut self) -> Result<Expected, ParseError> {
+        let (shape, span) = self.keyword()?;
+        let (lanes, bits, float) = match shape {
+            "i8x16" => (16, 8, None),
+            "i16x8" => (8, 16, None),
+            "i32x4" => (4, 32, None),
+            "i64x2" => (2, 64, None),
+            "f32x4" => (4, 32, Some(NumberType::F32)),
+            "f64x2" => (2, 64, Some(NumberType::F64)),
+            _ => return Err(error(format!("unknown vector shape `{}`", shape), span)),
+        };
+        let mut value = 0u128;
+        let mut patterns = vec![];
+        for lane in 0..lanes {
+            let lane_bits = match float {
+                Some(number_type) => {
+                    let expected = self.float(number_type)?;
+                    let lane_bits = match expected {
+                        Expected::Value(Value::F32(value)) => value.to_bits() as u64,
+                        Expected::Value(Value::F64(value)) => value.to_bits(),
+                        _ => 0,
+                    };
+                    patterns.push(expected);
+                    lane_bits
+                },
+                None => self.int_bits(bits)?,
+            };
+            value |= (lane_bits as u128) << (lane * bits);
+        }
+        if patterns.iter().any(|pattern| !matches!(pattern, Expected::Value(_))) {
+            return Ok(Expected::Lanes(patterns));
+        }
+        Ok(Expected::Value(Value::V128(value)))
+    }
+
+    // Skips a balanced s-expression and returns its span.
+    fn skip_form(&mut self) -> Result<Span, ParseError> {
+        let start = self.peek_span().start;
+        self.expect_left_paren()?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()?.kind {
+                TokenKind::LeftParen => depth += 1,
+                TokenKind::RightParen => depth -= 1,
+                _ => {},
+            }
+        }
+        Ok(Span { start, end: self.tokens[self.pos - 1].span.end })
+    }
+
+    fn int_bits(&mut self, bits: u32) -> Result<u64, ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Integer(integer) => integer.to_bits(bits)
+                .ok_or_else(|| error(format!("i{} constant out of range", bits), token.span)),
+            _ => Err(error("expected an integer", token.span)),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        let span = self.peek_span();
+        String::from_utf8(self.string()?).map_err(|_| error("malformed UTF-8 encoding", span))
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::String(string) => Ok(string.bytes()),
+            _ => Err(error("expected a string", token.span)),
+        }
+    }
+
+    fn id(&mut self) -> Option<String> {
+        match self.tokens.get(self.pos)?.kind {
+            TokenKind::Identifier(id) => {
+                self.pos += 1;
+                Some(id.to_string())
+            },
+            _ => None,
+        }
+    }
+
+    fn keyword(&mut self) -> Result<(&'a str, Span), ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::Keyword(keyword) => Ok((keyword, token.span)),
+            _ => Err(error("expected a keyword", token.span)),
+        }
+    }
+
+    fn expect_keyword(&mut self, expected: &str) -> Result<(), ParseError> {
+        let (keyword, span) = self.keyword()?;
+        if keyword != expected {
+            return Err(error(format!("expected `{}`", expected), span));
+        }
+        Ok(())
+    }
+
+    fn expect_left_paren(&mut self) -> Result<(), ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::LeftParen => Ok(()),
+            _ => Err(error("expected `(`", token.span)),
+        }
+    }
+
+    fn expect_right_paren(&mut self) -> Result<(), ParseError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::RightParen => Ok(()),
+            _ => Err(error("expected `)`", token.span)),
+        }
+    }
+
+    fn peek_right_paren(&self) -> bool {
+        matches!(self.tokens.get(self.pos).map(|t| t.kind), Some(TokenKind::RightParen))
+    }
+
+    // Returns the keyword after the `(` at the current position.
+    fn peek_field(&self) -> Result<(&'a str, Span), ParseError> {
+        match (self.tokens.get(self.pos).map(|t| t.kind), self.tokens.get(self.pos + 1)) {
+            (Some(TokenKind::LeftParen), Some(Token { kind: TokenKind::Keyword(keyword), span })) => Ok((keyword, *span)),
+            _ => Err(error("expected a command", self.peek_span())),
+        }
+    }
+
+    fn peek_keyword_field(&self, expected: &str) -> bool {
+        matches!(self.peek_field(), Ok((keyword, _)) if keyword == expected)
+    }
+
+    fn peek_span(&self) -> Span {
+        self.tokens.get(self.pos).map_or(self.eof, |token| token.span)
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, ParseError> {
+        let token = *self.tokens.get(self.pos).ok_or_else(|| error("unexpected end of script", self.eof))?;
+        self.pos += 1;
+        Ok(token)
+    }
+}
+
+fn error(message: impl Into<String>, span: Span) -> ParseError {
+    ParseError { message: message.into(), span }
+}
+
+// Why a script module could not be turned into an `ast::Module`. Spans are only known for
+// text modules written out in the script.
+enum Rejection {
+    Malformed { message: String, span: Option<Span> },
+    Invalid { message: String, span: Option<Span> },
+}
+
+struct Runner<'a> {
+    source: &'a str,
+    instances: Vec<Instance>,
+    // The most recently instantiated module, which actions without a module id refer to
+    current: Option<usize>,
+    ids: HashMap<String, usize>,
+    // Resolves imports from `spectest` and from the instances registered by name
+    linker: Linker,
+}
+
+impl<'a> Runner<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            instances: vec![],
+            current: None,
+            ids: HashMap::new(),
+            linker: spectest(&Store::new()),
+        }
+    }
+
+    fn command(&mut self, command: &Command) -> Outcome {
+        let mut span = command.span;
+        let result = match &command.kind {
+            CommandKind::Module(module) => match self.load(module) {
+                Ok(loaded) => self.instantiate(module.id.as_deref(), &loaded).map_err(|e| e.to_string()),
+                Err(Rejection::Malformed { message, span: at } | Rejection::Invalid { message, span: at }) => {
+                    span = at.unwrap_or(span);
+                    Err(message)
+                },
+            },
+            CommandKind::Register { name, module } => self.index(module.as_deref()).map(|idx| {
+                self.linker.instance(name, &self.instances[idx]);
+            }),
+            CommandKind::Action(action) => self.action(action)
+                .and_then(|result| result.map(|_| ()).map_err(|trap| format!("unexpected trap: {}", trap))),
+            CommandKind::AssertReturn { action, results } => self.assert_return(action, results),
+            CommandKind::AssertTrap { action, message } => match self.action(action) {
+                Ok(Err(trap)) if !matches!(trap, Trap::Runtime(_)) => expect_message(&trap.to_string(), message),
+                Ok(Err(e)) => Err(format!("expected trap `{}`, got error: {}", message, e)),
+                Ok(Ok(values)) => Err(format!("expected trap `{}`, got {:?}", message, values)),
+                Err(e) => Err(e),
+            },
+            CommandKind::AssertModuleTrap { module, message } => match self.instantiate_valid(module) {
+                Ok(Err(trap)) if !matches!(trap, Trap::Runtime(_)) => expect_message(&trap.to_string(), message),
+                Ok(Err(e)) => Err(format!("expected trap `{}`, got error: {}", message, e)),
+                Ok(Ok(())) => Err(format!("expected trap `{}`, but the module was instantiated", message)),
+                Err(e) => Err(e),
+            },
+            CommandKind::AssertExhaustion { action, message } => match self.action(action) {
+                Ok(Err(trap @ Trap::CallStackExhausted)) => expect_message(&trap.to_string(), message),
+                Ok(Err(e)) => Err(format!("expected `{}`, got: {}", message, e)),
+                Ok(Ok(values)) => Err(format!("expected `{}`, got {:?}", message, values)),
+                Err(e) => Err(e),
+            },
+            CommandKind::AssertMalformed { module, message: expected } => match self.load(module) {
+                Err(Rejection::Malformed { message, .. }) => expect_message(&message, expected),
+                Err(Rejection::Invalid { message, .. }) => Err(format!("module is invalid, not malformed: {}", message)),
+                Ok(_) => Err("module is not malformed".to_string()),
+            },
+            CommandKind::AssertInvalid { module, message: expected } => match self.load(module) {
+                Err(Rejection::Invalid { message, .. }) => expect_message(&message, expected),
+                Err(Rejection::Malformed { message, .. }) => Err(format!("module is malformed: {}", message)),
+                Ok(_) => Err("module is valid".to_string()),
+            },
+            CommandKind::AssertUnlinkable { module, message } => self.assert_unlinkable(module, message),
+        };
+        Outcome { directive: command.kind.directive(), span, result }
+    }
+
+    fn assert_return(&mut self, action: &Action, results: &[Expected]) -> Result<(), String> {
+        let values = self.action(action)?.map_err(|trap| format!("unexpected trap: {}", trap))?;
+        let matches = values.len() == results.len()
+            && results.iter().zip(&values).all(|(expected, actual)| expected.matches(actual));
+        if !matches {
+            return Err(format!("expected {:?}, got {:?}", results, values));
+        }
+        Ok(())
+    }
+
+    // The module has to be valid but fail to link.
+    fn assert_unlinkable(&mut self, module: &ScriptModule, message: &str) -> Result<(), String> {
+        match self.instantiate_valid(module)? {
+            Err(Trap::Runtime(e @ (RuntimeError::UnknownImport { .. } | RuntimeError::IncompatibleImportType { .. }))) => {
+                expect_message(&e.to_string(), message)
+            },
+            Err(e) => Err(format!("expected `{}`, got: {}", message, e)),
+            Ok(()) => Err(format!("expected `{}`, but the module was instantiated", message)),
+        }
+    }
+
+    // Instantiates a module that has to be valid. The outer error means it is not.
+    fn instantiate_valid(&mut self, module: &ScriptModule) -> Result<Result<(), Trap>, String> {
+        let loaded = self.load(module).map_err(|rejection| match rejection {
+            Rejection::Malformed { message, .. } | Rejection::Invalid { message, .. } => {
+                format!("module is rejected before instantiation: {}", message)
+            },
+        })?;
+        Ok(self.instantiate(module.id.as_deref(), &loaded))
+    }
+
+    fn load(&self, module: &ScriptModule) -> Result<Module, Rejection> {
+        match &module.source {
+            ModuleSource::Text(span) => load_text(&self.source[*span], Some(span.start)),
+            ModuleSource::Quote(bytes) => {
+                let text = str::from_utf8(bytes).map_err(|_| Rejection::Malformed {
+                    message: "malformed UTF-8 encoding".to_string(),
+                    span: None,
+                })?;
+                // Quoted modules may leave out the surrounding `(module ...)`
+                let is_module = {
+                    let mut tokens = Lexer::new(text).filter_map(Result::ok).filter(|t| !t.kind.is_trivia());
+                    matches!(
+                        (tokens.next().map(|t| t.kind), tokens.next().map(|t| t.kind)),
+                        (Some(TokenKind::LeftParen), Some(TokenKind::Keyword("module")))
+                    )
+                };
+                if is_module {
+                    load_text(text, None)
+                } else {
+                    load_text(&format!("(module {})", text), None)
+                }
+            },
+            ModuleSource::Binary(bytes) => {
+                let module = loader::decode(bytes)
+                    .map_err(|e| Rejection::Malformed { message: e.to_string(), span: None })?;
+                validator::validate(&module)
+                    .map_err(|e| Rejection::Invalid { message: e.to_string(), span: None })?;
+                Ok(module)
+            },
+        }
+    }
+
+    fn instantiate(&mut self, id: Option<&str>, module: &Module) -> Result<(), Trap> {
+        let instance = self.linker.instantiate(module)?;
+        self.instances.push(instance);
+        let idx = self.instances.len() - 1;
+        self.current = Some(idx);
+        if let Some(id) = id {
+            self.ids.insert(id.to_string(), idx);
+        }
+        Ok(())
+    }
+
+    fn index(&self, id: Option<&str>) -> Result<usize, String> {
+        match id {
+            Some(id) => self.ids.get(id).copied().ok_or_else(|| format!("unknown module `{}`", id)),
+            None => self.current.ok_or_else(|| "no module to act on".to_string()),
+        }
+    }
+
+    // Runs an action. The outer error means the action could not be performed at all.
+    fn action(&self, action: &Action) -> Result<Result<Vec<Value>, Trap>, String> {
+        match action {
+            Action::Invoke { module, name, args } => {
+                let instance = &self.instances[self.index(module.as_deref())?];
+                Ok(instance.invoke(name, args))
+            },
+            Action::Get { module, name } => {
+                let instance = &self.instances[self.index(module.as_deref())?];
+                let value = instance.global(name).map_err(|e| format!("cannot get `{}`: {}", name, e))?;
+                Ok(Ok(vec![value]))
+            },
+        }
+    }
+}
+
+// The `spectest` module the spec tests import from. Its print functions do nothing, their
+// output is not checked.
+fn spectest(store: &Store) -> Linker {
+    const I32: ValueType = ValueType::NumberType(NumberType::I32);
+    const I64: ValueType = ValueType::NumberType(NumberType::I64);
+    const F32: ValueType = ValueType::NumberType(NumberType::F32);
+    const F64: ValueType = ValueType::NumberType(NumberType::F64);
+
+    let mut linker = Linker::new(store);
+    for (name, params) in [
+        ("print", vec![]),
+        ("print_i32", vec![I32]),
+        ("print_i64", vec![I64]),
+        ("print_f32", vec![F32]),
+        ("print_f64", vec![F64]),
+        ("print_i32_f32", vec![I32, F32]),
+        ("print_f64_f64", vec![F64, F64]),
+    ] {
+        linker.func("spectest", name, (params, vec![]), |_, _| Ok(vec![]));
+    }
+    let table_type = TableType { limits: Limits { min: 10, max: Some(20) }, elem_type: ReferenceType::FuncRef };
+    linker.table("spectest", "table", &table_type);
+    linker.memory("spectest", "memory", &Limits { min: 1, max: Some(2) });
+    linker.global("spectest", "global_i32", false, Value::I32(666));
+    linker.global("spectest", "global_i64", false, Value::I64(666));
+    linker.global("spectest", "global_f32", false, Value::F32(666.6));
+    linker.global("spectest", "global_f64", false, Value::F64(666.6));
+    linker
+}
+
+// Parses and validates a text module. `offset` is where the text starts in the script, if
+// it is written out there.
+fn load_text(text: &str, offset: Option<u32>) -> Result<Module, Rejection> {
+    let shift = |span: Span| offset.map(|offset| Span { start: span.start + offset, end: span.end + offset });
+    let (module, spans) = parser::parse_with_spans(text)
+        .map_err(|e| Rejection::Malformed { message: e.message, span: shift(e.span) })?;
+    validator::validate(&module).map_err(|e| {
+        let span = e.func.and_then(|func| spans.func(func, e.offset)).and_then(shift);
+        Rejection::Invalid { message: e.message, span }
+    })?;
+    Ok(module)
+}
+
+// Trap messages of the spec tests are prefixes of the messages of the runtime, ignoring case.
+fn expect_message(actual: &str, expected: &str) -> Result<(), String> {
+    if !actual.to_lowercase().starts_with(&expected.to_lowercase()) {
+        return Err(format!("expected `{}`, got `{}`", expected, actual));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcomes(source: &str) -> Vec<(&'static str, Result<(), String>)> {
+        run(source).unwrap().into_iter().map(|outcome| (outcome.directive, outcome.result)).collect()
+    }
+
+    #[test]
+    fn runs_scripts() {
+        let script = r#"
+            (module $m
+              (func $add (param i32 i32) (result i32)
+                local.get 0
+                local.get 1
+                i32.add)
+              (func $div (param i32 i32) (result i32)
+                local.get 0
+                local.get 1
+                i32.div_s)
+              (func $nan (result f32 f64)
+                f32.const nan
+                f64.const -nan:0x8_0000_0000_0001)
+              (func $loop
+                call $loop)
+              (export "add" (func $add))
+              (export "div" (func $div))
+              (export "nan" (func $nan))
+              (export "loop" (func $loop)))
+            (register "m" $m)
+            (invoke "add" (i32.const 1) (i32.const 2))
+            (assert_return (invoke $m "add" (i32.const 0xFFFF_FFFF) (i32.const 2)) (i32.const 1))
+            (assert_return (invoke "nan") (f32.const nan:canonical) (f64.const nan:arithmetic))
+            (assert_trap (invoke "div" (i32.const 1) (i32.const 0)) "integer divide by zero")
+            (assert_exhaustion (invoke "loop") "call stack exhausted")
+            (assert_invalid (module (func (result i32) i64.const 0)) "type mismatch")
+            (assert_malformed (module quote "(func i32.const 12x)") "malformed number")
+            (assert_malformed (module binary "\00asm\02\00\00\00") "unknown binary version")
+            (assert_unlinkable (module (import "missing" "f" (func))) "unknown import")
+            (module binary "\00asm" "\01\00\00\00")
+        "#;
+        let outcomes = outcomes(script);
+        assert!(outcomes.iter().all(|(_, result)| result.is_ok()), "{:?}", outcomes);
+        assert_eq!(outcomes.len(), 12);
+        assert_eq!(outcomes[2].0, "invoke");
+    }
+
+    #[test]
+    fn accesses_memory() {
+        let script = r#"
+            (module
+              (memory (export "memory") 1 2)
+              (data (i32.const 8) "\01\02\03\04\ff")
+              (func (export "load") (param i32) (result i32)
+                (i32.load offset=8 (local.get 0)))
+              (func (export "load8") (param i32) (result i64)
+                (i64.load8_s (local.get 0)))
+              (func (export "store16") (param i32 i32)
+                (i32.store16 (local.get 0) (local.get 1)))
+              (func (export "grow") (param i32) (result i32)
+                (memory.grow (local.get 0)))
+              (func (export "size") (result i32)
+                memory.size))
+            (assert_return (invoke "load" (i32.const 0)) (i32.const 0x04030201))
+            (assert_return (invoke "load8" (i32.const 12)) (i64.const -1))
+            (invoke "store16" (i32.const 9) (i32.const 0x1_ffff))
+            (assert_return (invoke "load" (i32.const 0)) (i32.const 0x04ffff01))
+            (assert_trap (invoke "load" (i32.const 65525)) "out of bounds memory access")
+            (assert_trap (invoke "load" (i32.const -1)) "out of bounds memory access")
+            (assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+            (assert_return (invoke "load" (i32.const 65525)) (i32.const 0))
+            (assert_return (invoke "grow" (i32.const 1)) (i32.const -1))
+            (assert_return (invoke "size") (i32.const 2))
+            (assert_trap (module (memory 1) (data (i32.const 65535) "ab")) "out of bounds memory access")
+        "#;
+        let outcomes = outcomes(script);
+        assert!(outcomes.iter().all(|(_, result)| result.is_ok()), "{:?}", outcomes);
+    }
+
+    #[test]
+    fn copies_memory_in_bulk() {
+        let script = r#"
+            (module
+              (memory 1 1)
+              (data (i32.const 0) "abcdef")
+              (data $passive "xyz")
+              (func (export "load") (param i32) (result i32)
+                (i32.load8_u (local.get 0)))
+              (func (export "fill") (param i32 i32 i32)
+                (memory.fill (local.get 0) (local.get 1) (local.get 2)))
+              (func (export "copy") (param i32 i32 i32)
+                (memory.copy (local.get 0) (local.get 1) (local.get 2)))
+              (func (export "init") (param i32 i32 i32)
+                (memory.init $passive (local.get 0) (local.get 1) (local.get 2)))
+              (func (export "drop")
+                (data.drop $passive))
+              (func (export "reinit")
+                (memory.init 0 (i32.const 0) (i32.const 0) (i32.const 1))))
+            (invoke "copy" (i32.const 1) (i32.const 0) (i32.const 4))
+            (assert_return (invoke "load" (i32.const 1)) (i32.const 0x61))
+            (assert_return (invoke "load" (i32.const 4)) (i32.const 0x64))
+            (assert_return (invoke "load" (i32.const 5)) (i32.const 0x66))
+            (invoke "fill" (i32.const 65534) (i32.const 0x1ff) (i32.const 2))
+            (assert_return (invoke "load" (i32.const 65535)) (i32.const 0xff))
+            (assert_trap (invoke "fill" (i32.const 65535) (i32.const 0) (i32.const 2)) "out of bounds memory access")
+            (assert_trap (invoke "copy" (i32.const 0) (i32.const 65535) (i32.const 2)) "out of bounds memory access")
+            (invoke "init" (i32.const 8) (i32.const 1) (i32.const 2))
+            (assert_return (invoke "load" (i32.const 9)) (i32.const 0x7a))
+            (assert_trap (invoke "init" (i32.const 0) (i32.const 2) (i32.const 2)) "out of bounds memory access")
+            (invoke "init" (i32.const 0) (i32.const 3) (i32.const 0))
+            (invoke "drop")
+            (invoke "drop")
+            (assert_trap (invoke "init" (i32.const 0) (i32.const 0) (i32.const 1)) "out of bounds memory access")
+            (assert_trap (invoke "reinit") "out of bounds memory access")
+            (module (func (export "drop") (data.drop 0)) (data "passive"))
+            (invoke "drop")
+        "#;
+        let outcomes = outcomes(script);
+        assert!(outcomes.iter().all(|(_, result)| result.is_ok()), "{:?}", outcomes);
+    }
+
+    #[test]
+    fn calls_indirectly() {
+        let script = r#"
+            (module $lib
+              (type $unary (func (param i32) (result i32)))
+              (table $t (export "table") 4 funcref)
+              (func $double (type $unary) (i32.mul (local.get 0) (i32.const 2)))
+              (func $square (type $unary) (i32.mul (local.get 0) (local.get 0)))
+              (func $nullary (result i32) (i32.const 0))
+              (elem (table $t) (i32.const 1) func $double $square)
+              (elem $passive func $nullary $square)
+              (elem declare func $double)
+              (func (export "call") (param i32 i32) (result i32)
+                (call_indirect (type $unary) (local.get 1) (local.get 0)))
+              (func (export "init") (param i32 i32 i32)
+                (table.init $t $passive (local.get 0) (local.get 1) (local.get 2)))
+              (func (export "drop")
+                (elem.drop $passive))
+              (func (export "is_null") (param i32) (result i32)
+                (ref.is_null (table.get $t (local.get 0))))
+              (func (export "grow") (param i32) (result i32)
+                (table.grow $t (ref.func $double) (local.get 0)))
+              (func (export "fill") (param i32 i32)
+                (table.fill $t (local.get 0) (ref.null func) (local.get 1)))
+              (func (export "copy") (param i32 i32 i32)
+                (table.copy (local.get 0) (local.get 1) (local.get 2)))
+              (func (export "size") (result i32)
+                table.size))
+            (register "lib" $lib)
+            (module
+              (import "lib" "table" (table 4 funcref))
+              (import "spectest" "table" (table $spec 10 20 funcref))
+              (func $negate (param i32) (result i32) (i32.sub (i32.const 0) (local.get 0)))
+              (elem (i32.const 3) $negate)
+              (func (export "spec") (result i32)
+                (i32.add (table.size $spec) (call_indirect (param i32) (result i32) (i32.const 9) (i32.const 0)))))
+            (assert_return (invoke $lib "call" (i32.const 1) (i32.const 21)) (i32.const 42))
+            (assert_return (invoke $lib "call" (i32.const 2) (i32.const 5)) (i32.const 25))
+            (assert_return (invoke $lib "call" (i32.const 3) (i32.const 7)) (i32.const -7))
+            (assert_trap (invoke $lib "call" (i32.const 0) (i32.const 0)) "uninitialized element")
+            (assert_trap (invoke $lib "call" (i32.const 4) (i32.const 0)) "undefined element")
+            (invoke $lib "init" (i32.const 0) (i32.const 0) (i32.const 1))
+            (assert_trap (invoke $lib "call" (i32.const 0) (i32.const 0)) "indirect call type mismatch")
+            (assert_trap (invoke $lib "init" (i32.const 3) (i32.const 0) (i32.const 2)) "out of bounds table access")
+            (invoke $lib "drop")
+            (assert_trap (invoke $lib "init" (i32.const 0) (i32.const 0) (i32.const 1)) "out of bounds table access")
+            (assert_return (invoke $lib "grow" (i32.const 2)) (i32.const 4))
+            (assert_return (invoke $lib "call" (i32.const 5) (i32.const 4)) (i32.const 8))
+            (invoke $lib "copy" (i32.const 0) (i32.const 1) (i32.const 3))
+            (assert_return (invoke $lib "call" (i32.const 1) (i32.const 4)) (i32.const 16))
+            (invoke $lib "fill" (i32.const 1) (i32.const 5))
+            (assert_return (invoke $lib "is_null" (i32.const 5)) (i32.const 1))
+            (assert_return (invoke $lib "is_null" (i32.const 0)) (i32.const 0))
+            (assert_trap (invoke $lib "is_null" (i32.const 6)) "out of bounds table access")
+            (assert_trap (invoke $lib "fill" (i32.const 1) (i32.const 6)) "out of bounds table access")
+            (assert_return (invoke $lib "size") (i32.const 6))
+            (assert_return (invoke "spec") (i32.const 28))
+            (assert_unlinkable (module (import "lib" "table" (table 4 externref))) "incompatible import type")
+            (assert_trap (module (table 1 funcref) (func) (elem (i32.const 1) 0)) "out of bounds table access")
+        "#;
+        let outcomes = outcomes(script);
+        assert!(outcomes.iter().all(|(_, result)| result.is_ok()), "{:?}", outcomes);
+    }
+
+    #[test]
+    fn shares_globals() {
+        let script = r#"
+            (module $lib
+              (global (export "counter") (mut i32) (i32.const 0))
+              (global (export "base") i64 (i64.const 40))
+              (func (export "bump") (result i32)
+                (global.set 0 (i32.add (global.get 0) (i32.const 1)))
+                (global.get 0)))
+            (register "lib")
+            (module
+              (import "lib" "counter" (global $counter (mut i32)))
+              (import "lib" "base" (global $base i64))
+              (import "spectest" "global_i32" (global i32))
+              (global (export "answer") i64 (i64.add (global.get $base) (i64.const 2)))
+              (global (export "spec") i32 (global.get 2))
+              (func (export "bump") (result i32)
+                (global.set $counter (i32.mul (global.get $counter) (i32.const 10)))
+                (global.get $counter)))
+            (assert_return (get "answer") (i64.const 42))
+            (assert_return (get "spec") (i32.const 666))
+            (assert_return (invoke $lib "bump") (i32.const 1))
+            (assert_return (invoke "bump") (i32.const 10))
+            (assert_return (get $lib "counter") (i32.const 10))
+        "#;
+        let outcomes = outcomes(script);
+        assert!(outcomes.iter().all(|(_, result)| result.is_ok()), "{:?}", outcomes);
+    }
+
+    #[test]
+    fn runs_start_functions() {
+        let script = r#"
+            (module
+              (memory 1)
+              (data (i32.const 0) "*")
+              (global $answer (export "answer") (mut i32) (i32.const 0))
+              (func $s
+                (global.set $answer (i32.load8_u (i32.const 0))))
+              (start $s))
+            (assert_return (get "answer") (i32.const 42))
+            (assert_trap (module (func $t unreachable) (start $t)) "unreachable")
+        "#;
+        let outcomes = outcomes(script);
+        assert!(outcomes.iter().all(|(_, result)| result.is_ok()), "{:?}", outcomes);
+    }
+
+    #[test]
+    fn reports_failures() {
+        let script = "(module (func (result i32) i32.const 1) (export \"one\" (func 0)))\n\
+                      (assert_return (invoke \"one\") (i32.const 2))\n\
+                      (assert_trap (invoke \"one\") \"unreachable\")\n\
+                      (module (func (result i32) i64.const 0))\n\
+                      (assert_trap (module (import \"env\" \"f\" (func))) \"unknown import\")\n\
+                      (assert_unlinkable (module (func unreachable) (start 0)) \"unreachable\")\n\
+                      (assert_unlinkable (module (import \"env\" \"f\" (func))) \"incompatible import type\")\n\
+                      (assert_trap (module (func unreachable) (start 0)) \"out of bounds memory access\")\n\
+                      (assert_invalid (module (func (result i32) i64.const 0)) \"unknown type\")\n\
+                      (assert_malformed (module binary \"\\00asm\\02\\00\\00\\00\") \"magic header not detected\")\n\
+                      (module (func $f call $f) (export \"f\" (func $f)))\n\
+                      (assert_exhaustion (invoke \"f\") \"integer overflow\")";
+        let outcomes = run(script).unwrap();
+        assert_eq!(outcomes[1].result, Err("expected [Value(I32(2))], got [I32(1)]".to_string()));
+        assert_eq!(outcomes[1].span.start, script.find("(assert_return").unwrap() as u32);
+        assert!(outcomes[2].result.is_err());
+        // Validation errors of text modules point at the offending instruction
+        assert_eq!(outcomes[3].result, Err("type mismatch".to_string()));
+        assert_eq!(outcomes[3].span.start, script.find("i64.const 0").unwrap() as u32);
+        // Module assertions tell traps from link errors and check their messages
+        assert_eq!(outcomes[4].result, Err("expected trap `unknown import`, got error: Unknown import `env` `f`".to_string()));
+        assert_eq!(outcomes[5].result, Err("expected `unreachable`, got: Unreachable executed".to_string()));
+        assert!(outcomes[6].result.is_err());
+        assert!(outcomes[7].result.is_err());
+        // As do the assertions on malformed and invalid modules and on exhaustion
+        assert_eq!(outcomes[8].result, Err("expected `unknown type`, got `type mismatch`".to_string()));
+        assert_eq!(
+            outcomes[9].result,
+            Err("expected `magic header not detected`, got `Unknown binary version`".to_string()),
+        );
+        assert_eq!(outcomes[11].result, Err("expected `integer overflow`, got `Call stack exhausted`".to_string()));
+    }
+}